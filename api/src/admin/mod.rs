@@ -1,6 +1,7 @@
 use axum::extract::Path;
 use axum::response::IntoResponse;
 use axum::{body, extract::Extension, routing::get, Json, Router};
+use entity::kv_binding;
 use entity::namespace;
 use entity::user;
 use migration::sea_orm::ActiveValue::Set;
@@ -75,7 +76,20 @@ async fn create_user(
         created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
         ..entity::namespace::ActiveModel::default()
     };
-    namespace::Entity::insert(to_be_inserted)
+    let namespace_res = namespace::Entity::insert(to_be_inserted)
+        .exec(conn)
+        .await
+        .map_err(ApiError::db)?;
+
+    // Bind the namespace as `kvStorage` in the user's scripts
+    let to_be_inserted = kv_binding::ActiveModel {
+        name: Set("kvStorage".into()),
+        user_id: Set(insert_res.last_insert_id),
+        namespace_id: Set(namespace_res.last_insert_id),
+        created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+        ..entity::kv_binding::ActiveModel::default()
+    };
+    kv_binding::Entity::insert(to_be_inserted)
         .exec(conn)
         .await
         .map_err(ApiError::db)?;
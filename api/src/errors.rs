@@ -7,15 +7,15 @@ use migration::DbErr;
 
 pub struct ApiError {
     status_code: StatusCode,
-    message: Option<&'static str>,
+    message: Option<String>,
 }
 
 impl ApiError {
-    pub fn new(status_code: u16, message: &'static str) -> Self {
+    pub fn new(status_code: u16, message: &str) -> Self {
         Self {
             status_code: StatusCode::from_u16(status_code)
                 .expect("Status Code used that doesn't exist"),
-            message: Some(message),
+            message: Some(message.into()),
         }
     }
 
@@ -32,7 +32,7 @@ impl ApiError {
 
         Self {
             status_code: StatusCode::INTERNAL_SERVER_ERROR,
-            message: "Database error".into(),
+            message: Some("Database error".into()),
         }
     }
 }
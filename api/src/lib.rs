@@ -8,9 +8,8 @@ use axum::extract::Extension;
 use axum::http::header;
 use axum::routing::post;
 use axum::Router;
+use config::Config;
 use migration::sea_orm::Database;
-use std::net::SocketAddr;
-use std::str::FromStr;
 use std::sync::Arc;
 use tower::ServiceBuilder;
 use tower_http::ServiceBuilderExt;
@@ -24,17 +23,23 @@ mod user;
 
 /// # Errors
 ///
-/// Will return `Err` if webserver panics
-pub async fn run() -> Result<()> {
-    let conn = Database::connect(
-        std::env::var("DATABASE_URL")
-            .expect("No DATABASE_URL environment variable found.")
-            .as_str(),
-    )
-    .await
-    .context("Database connection failed")?;
-
-    let bucket = init_bucket();
+/// Will return `Err` if webserver panics or S3 or the auth settings aren't configured
+pub async fn run(config: Config) -> Result<()> {
+    let s3 = config
+        .s3
+        .clone()
+        .context("S3 has to be configured for the API")?;
+    let auth = config
+        .auth
+        .clone()
+        .context("Auth has to be configured for the API")?;
+    middleware::auth::init_keys(auth.jwt_secret.as_bytes());
+
+    let conn = Database::connect(config.database_url()?)
+        .await
+        .context("Database connection failed")?;
+
+    let bucket = s3.bucket()?;
 
     let sensitive_headers: Arc<[_]> = vec![header::AUTHORIZATION, header::COOKIE].into();
     let middleware = ServiceBuilder::new()
@@ -47,9 +52,11 @@ pub async fn run() -> Result<()> {
         .nest("/:user_id", user::router())
         .layer(Extension(conn))
         .layer(Extension(bucket))
+        .layer(Extension(auth))
+        .layer(Extension(config.clone()))
         .layer(middleware);
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
+    let addr = config.api_listen;
 
     println!("Api listening on {}", addr);
     axum::Server::bind(&addr)
@@ -58,58 +65,3 @@ pub async fn run() -> Result<()> {
 
     Ok(())
 }
-
-fn init_bucket() -> s3::Bucket {
-    let credentials = s3::creds::Credentials::new(
-        Some(
-            std::env::var("S3_ACCESS_KEY")
-                .expect("S3_ACCESS_KEY not found")
-                .as_str(),
-        ),
-        Some(
-            std::env::var("S3_SECRET_KEY")
-                .expect("S3_SECRET_KEY not found")
-                .as_str(),
-        ),
-        None,
-        None,
-        None,
-    )
-    .unwrap();
-
-    let maybe_endpoint = std::env::var("S3_ENDPOINT");
-
-    if maybe_endpoint.is_err() {
-        let bucket = s3::Bucket::new(
-            std::env::var("S3_BUCKET")
-                .expect("S3_BUCKET not found")
-                .as_str(),
-            s3::Region::from_str(
-                std::env::var("S3_REGION")
-                    .expect("S3_REGION not found")
-                    .as_str(),
-            )
-            .expect("Unknown region"),
-            credentials,
-        )
-        .unwrap();
-
-        return bucket;
-    }
-
-    let region = s3::Region::Custom {
-        region: std::env::var("S3_REGION").expect("S3_REGION not found"),
-        endpoint: maybe_endpoint.unwrap(),
-    };
-
-    let bucket = s3::Bucket::new_with_path_style(
-        std::env::var("S3_BUCKET")
-            .expect("S3_BUCKET not found")
-            .as_str(),
-        region,
-        credentials,
-    )
-    .unwrap();
-
-    bucket
-}
@@ -7,33 +7,42 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use config::AuthConfig;
 use entity::user;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use migration::sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
-use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 
 use crate::errors::ApiError;
 
-pub static KEYS: Lazy<Keys> = Lazy::new(|| {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    Keys::new(secret.as_bytes())
-});
+static KEYS: OnceCell<Keys> = OnceCell::new();
+
+/// Sets the secret the tokens are signed with, only the first call has an effect
+pub fn init_keys(secret: &[u8]) {
+    KEYS.get_or_init(|| Keys::new(secret));
+}
+
+/// # Panics
+///
+/// Will panic if `init_keys` wasn't called yet
+pub fn keys() -> &'static Keys {
+    KEYS.get().expect("The auth keys aren't initialized")
+}
 
 // should be post
 pub async fn authorize_route(
     Json(payload): Json<Payload>,
     Extension(ref conn): Extension<DatabaseConnection>,
+    Extension(ref auth): Extension<AuthConfig>,
 ) -> Result<Json<Body>, ApiError> {
     if payload.client_id.is_empty() || payload.client_secret.is_empty() {
         return Err(ApiError::new(400, "Missing credentials"));
     }
 
-    let admin_client_id = std::env::var("ADMIN_CLIENT_ID").expect("ADMIN_CLIENT_ID must be set");
-    let admin_client_secret =
-        std::env::var("ADMIN_CLIENT_SECRET").expect("ADMIN_CLIENT_SECRET must be set");
-
-    if payload.client_id == admin_client_id && payload.client_secret == admin_client_secret {
+    if payload.client_id == auth.admin_client_id
+        && payload.client_secret == auth.admin_client_secret
+    {
         let token = encode_token(&Claims {
             sub: 0,
             is_admin: true,
@@ -64,7 +73,7 @@ pub async fn authorize_route(
 }
 
 fn encode_token(claims: &Claims) -> Result<String, ApiError> {
-    let result = encode(&Header::default(), &claims, &KEYS.encoding)
+    let result = encode(&Header::default(), &claims, &keys().encoding)
         .map_err(|_| ApiError::new(500, "Failed to create token"))?;
 
     Ok(result)
@@ -83,7 +92,7 @@ pub async fn is_admin_middleware(
 
     let token_data = decode::<Claims>(
         bearer.unwrap().token(),
-        &KEYS.decoding,
+        &keys().decoding,
         &Validation::default(),
     );
     if token_data.is_err() {
@@ -9,6 +9,7 @@ use entity::user;
 use jsonwebtoken::{decode, Validation};
 use migration::sea_orm::{DatabaseConnection, EntityTrait};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use super::auth::Claims;
 
@@ -23,10 +24,16 @@ where
     type Rejection = Error;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        // extract the id from the path
+        // extract the id from the path, nested routes can have more params next to it
         let id = {
-            let path: Path<i32> = axum::extract::Path::from_request(req).await.unwrap();
+            let path: Path<HashMap<String, String>> = axum::extract::Path::from_request(req)
+                .await
+                .map_err(|_| Error::NotFound)?;
+
             path.0
+                .get("user_id")
+                .and_then(|id| id.parse::<i32>().ok())
+                .ok_or(Error::NotFound)?
         };
 
         // now we deserialize the token and check if the user has perms
@@ -36,7 +43,7 @@ where
         }
         let token_data = decode::<Claims>(
             bearer.unwrap().token(),
-            &crate::middleware::auth::KEYS.decoding,
+            &crate::middleware::auth::keys().decoding,
             &Validation::default(),
         );
         if token_data.is_err() {
@@ -0,0 +1,117 @@
+use axum::extract::Path;
+use axum::{extract::Extension, Json};
+use entity::actor_binding;
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+
+use super::bindings::is_valid_identifier;
+use crate::{errors::ApiError, middleware::user::User};
+
+#[derive(Debug, Serialize)]
+pub struct ActorBinding {
+    name: String,
+    class_name: String,
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_actor_bindings(
+    user: User,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<Vec<ActorBinding>>, ApiError> {
+    let items = actor_binding::Entity::find()
+        .filter(actor_binding::Column::UserId.eq(user.0.id))
+        .all(conn)
+        .await
+        .map_err(ApiError::db)?;
+
+    let bindings = items
+        .into_iter()
+        .map(|binding| ActorBinding {
+            name: binding.name,
+            class_name: binding.class_name,
+        })
+        .collect();
+
+    Ok(Json(bindings))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutActorBinding {
+    class_name: String,
+}
+
+/// Binds the actor class exported by the script to the app under `name`,
+/// replacing the class if the binding exists
+#[axum_macros::debug_handler]
+pub async fn put_actor_binding(
+    user: User,
+    Path((_, name)): Path<(i32, String)>,
+    Json(params): Json<PutActorBinding>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<ActorBinding>, ApiError> {
+    if !is_valid_identifier(&name) || !is_valid_identifier(&params.class_name) {
+        return Err(ApiError::new(
+            400,
+            "Binding and class names have to be valid JavaScript identifiers",
+        ));
+    }
+
+    let maybe_binding = find_actor_binding(conn, user.0.id, &name).await?;
+    if let Some(binding) = maybe_binding {
+        let mut to_be_updated: actor_binding::ActiveModel = binding.into();
+        to_be_updated.class_name = Set(params.class_name.clone());
+
+        actor_binding::Entity::update(to_be_updated)
+            .exec(conn)
+            .await
+            .map_err(ApiError::db)?;
+    } else {
+        let to_be_inserted = actor_binding::ActiveModel {
+            name: Set(name.clone()),
+            user_id: Set(user.0.id),
+            class_name: Set(params.class_name.clone()),
+            created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+            ..entity::actor_binding::ActiveModel::default()
+        };
+
+        actor_binding::Entity::insert(to_be_inserted)
+            .exec(conn)
+            .await
+            .map_err(ApiError::db)?;
+    }
+
+    Ok(Json(ActorBinding {
+        name,
+        class_name: params.class_name,
+    }))
+}
+
+/// The storage of the actors is kept, binding the class again makes it available again
+#[axum_macros::debug_handler]
+pub async fn delete_actor_binding(
+    user: User,
+    Path((_, name)): Path<(i32, String)>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    let binding = find_actor_binding(conn, user.0.id, &name)
+        .await?
+        .ok_or_else(|| ApiError::new(404, "No actor binding found with this name"))?;
+
+    binding.delete(conn).await.map_err(ApiError::db)?;
+
+    Ok(Json("Deleted actor binding succesfully"))
+}
+
+async fn find_actor_binding(
+    conn: &DatabaseConnection,
+    user_id: i32,
+    name: &str,
+) -> Result<Option<actor_binding::Model>, ApiError> {
+    actor_binding::Entity::find()
+        .filter(actor_binding::Column::UserId.eq(user_id))
+        .filter(actor_binding::Column::Name.eq(name))
+        .one(conn)
+        .await
+        .map_err(ApiError::db)
+}
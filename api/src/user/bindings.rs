@@ -0,0 +1,131 @@
+use axum::extract::Path;
+use axum::{extract::Extension, Json};
+use entity::{kv_binding, namespace};
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+
+use super::namespaces::find_namespace;
+use crate::{errors::ApiError, middleware::user::User};
+
+#[derive(Debug, Serialize)]
+pub struct Binding {
+    name: String,
+    namespace: String,
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_bindings(
+    user: User,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<Vec<Binding>>, ApiError> {
+    let items = kv_binding::Entity::find()
+        .filter(kv_binding::Column::UserId.eq(user.0.id))
+        .find_also_related(namespace::Entity)
+        .all(conn)
+        .await
+        .map_err(ApiError::db)?;
+
+    let bindings = items
+        .into_iter()
+        .filter_map(|(binding, namespace)| {
+            namespace.map(|namespace| Binding {
+                name: binding.name,
+                namespace: namespace.name,
+            })
+        })
+        .collect();
+
+    Ok(Json(bindings))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutBinding {
+    namespace: String,
+}
+
+/// Binds a namespace to the app under `name`, replacing the namespace if the binding exists
+#[axum_macros::debug_handler]
+pub async fn put_binding(
+    user: User,
+    Path((_, name)): Path<(i32, String)>,
+    Json(params): Json<PutBinding>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<Binding>, ApiError> {
+    if !is_valid_identifier(&name) {
+        return Err(ApiError::new(
+            400,
+            "Binding names have to be valid JavaScript identifiers",
+        ));
+    }
+
+    let namespace = find_namespace(conn, user.0.id, &params.namespace)
+        .await?
+        .ok_or_else(|| ApiError::new(404, "No namespace found with this name"))?;
+
+    let maybe_binding = find_binding(conn, user.0.id, &name).await?;
+    if let Some(binding) = maybe_binding {
+        let mut to_be_updated: kv_binding::ActiveModel = binding.into();
+        to_be_updated.namespace_id = Set(namespace.id);
+
+        kv_binding::Entity::update(to_be_updated)
+            .exec(conn)
+            .await
+            .map_err(ApiError::db)?;
+    } else {
+        let to_be_inserted = kv_binding::ActiveModel {
+            name: Set(name.clone()),
+            user_id: Set(user.0.id),
+            namespace_id: Set(namespace.id),
+            created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+            ..entity::kv_binding::ActiveModel::default()
+        };
+
+        kv_binding::Entity::insert(to_be_inserted)
+            .exec(conn)
+            .await
+            .map_err(ApiError::db)?;
+    }
+
+    Ok(Json(Binding {
+        name,
+        namespace: namespace.name,
+    }))
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_binding(
+    user: User,
+    Path((_, name)): Path<(i32, String)>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    let binding = find_binding(conn, user.0.id, &name)
+        .await?
+        .ok_or_else(|| ApiError::new(404, "No binding found with this name"))?;
+
+    binding.delete(conn).await.map_err(ApiError::db)?;
+
+    Ok(Json("Deleted binding succesfully"))
+}
+
+async fn find_binding(
+    conn: &DatabaseConnection,
+    user_id: i32,
+    name: &str,
+) -> Result<Option<kv_binding::Model>, ApiError> {
+    kv_binding::Entity::find()
+        .filter(kv_binding::Column::UserId.eq(user_id))
+        .filter(kv_binding::Column::Name.eq(name))
+        .one(conn)
+        .await
+        .map_err(ApiError::db)
+}
+
+pub(super) fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first_is_valid = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_' || c == '$');
+
+    first_is_valid && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
@@ -0,0 +1,64 @@
+use axum::http::Uri;
+use axum::{extract::Extension, Json};
+use entity::cache_purge;
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Deserialize;
+
+use crate::{errors::ApiError, middleware::user::User};
+
+/// Purges are only kept until every workers process had time to apply them
+const PURGE_RETENTION_HOURS: i64 = 24;
+
+#[derive(Debug, Deserialize)]
+pub struct Purge {
+    /// Absolute URLs, every cached response of the app is purged if not set
+    urls: Option<Vec<String>>,
+}
+
+/// Drops responses from the edge cache of the workers, they apply purges within a few seconds
+#[axum_macros::debug_handler]
+pub async fn purge_cache(
+    user: User,
+    Json(params): Json<Purge>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    let urls: Vec<Option<String>> = match params.urls {
+        Some(urls) => urls.into_iter().map(Some).collect(),
+        None => vec![None],
+    };
+
+    for url in urls.iter().flatten() {
+        let is_absolute = url
+            .parse::<Uri>()
+            .map_or(false, |uri| uri.authority().is_some());
+        if !is_absolute {
+            return Err(ApiError::new(400, "Purged URLs have to be absolute"));
+        }
+    }
+
+    let now = chrono::Utc::now();
+    cache_purge::Entity::delete_many()
+        .filter(
+            cache_purge::Column::CreatedAt.lt(now - chrono::Duration::hours(PURGE_RETENTION_HOURS)),
+        )
+        .exec(conn)
+        .await
+        .map_err(ApiError::db)?;
+
+    if urls.is_empty() {
+        return Ok(Json("Nothing to purge"));
+    }
+
+    cache_purge::Entity::insert_many(urls.into_iter().map(|url| cache_purge::ActiveModel {
+        user_id: Set(user.0.id),
+        url: Set(url),
+        created_at: Set(chrono::DateTime::into(now)),
+        ..cache_purge::ActiveModel::default()
+    }))
+    .exec(conn)
+    .await
+    .map_err(ApiError::db)?;
+
+    Ok(Json("Purged cache succesfully"))
+}
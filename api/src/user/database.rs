@@ -0,0 +1,134 @@
+use axum::{extract::Extension, Json};
+use config::Config;
+use migration::sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use storage::sql::{self, SqlError};
+
+use crate::{errors::ApiError, middleware::user::User};
+
+#[derive(Debug, Serialize)]
+pub struct Database {
+    schema: String,
+    migrations: Vec<Migration>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Migration {
+    name: String,
+    applied_at: String,
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_database(
+    user: User,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<Database>, ApiError> {
+    let exists = sql::exists(conn, user.0.id).await.map_err(sql_error)?;
+    if !exists {
+        return Err(ApiError::new(404, "No database exists for this user"));
+    }
+
+    Ok(Json(database(conn, user.0.id).await?))
+}
+
+/// Creates the schema of the app, nothing happens if it exists already. Schemas created before
+/// apps had their own login are given one
+#[axum_macros::debug_handler]
+pub async fn create_database(
+    user: User,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<Database>, ApiError> {
+    sql::create(conn, user.0.id).await.map_err(sql_error)?;
+
+    Ok(Json(database(conn, user.0.id).await?))
+}
+
+/// Drops the schema with all its tables and data
+#[axum_macros::debug_handler]
+pub async fn delete_database(
+    user: User,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    sql::delete(conn, user.0.id).await.map_err(sql_error)?;
+
+    Ok(Json("Deleted database succesfully"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyMigration {
+    name: String,
+    /// Sent to Postgres one at a time, in order
+    statements: Vec<String>,
+}
+
+/// Applies a migration in one transaction, each name is only applied once
+#[axum_macros::debug_handler]
+pub async fn apply_migration(
+    user: User,
+    Json(params): Json<ApplyMigration>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+    Extension(ref config): Extension<Config>,
+) -> Result<Json<Database>, ApiError> {
+    let exists = sql::exists(conn, user.0.id).await.map_err(sql_error)?;
+    if !exists {
+        return Err(ApiError::new(404, "No database exists for this user"));
+    }
+
+    let applied = sql::migrations(conn, user.0.id).await.map_err(sql_error)?;
+    if applied
+        .iter()
+        .any(|migration| migration.name == params.name)
+    {
+        return Err(ApiError::new(
+            409,
+            "A migration with this name was applied already",
+        ));
+    }
+
+    let database_url = config.database_url().map_err(sql_error)?;
+    let db = sql::connect(conn, database_url, user.0.id)
+        .await
+        .map_err(sql_error)?;
+    sql::migrate(
+        conn,
+        &db,
+        &params.name,
+        &params.statements,
+        config.db.into(),
+    )
+    .await
+    .map_err(sql_error)?;
+
+    Ok(Json(database(conn, user.0.id).await?))
+}
+
+async fn database(conn: &DatabaseConnection, user_id: i32) -> Result<Database, ApiError> {
+    let migrations = sql::migrations(conn, user_id)
+        .await
+        .map_err(sql_error)?
+        .into_iter()
+        .map(|migration| Migration {
+            name: migration.name,
+            applied_at: migration.created_at.to_rfc3339(),
+        })
+        .collect();
+
+    Ok(Database {
+        schema: sql::schema_name(user_id),
+        migrations,
+    })
+}
+
+/// Failed statements of a migration are the fault of the client, anything else is a database error
+fn sql_error(err: anyhow::Error) -> ApiError {
+    println!("{:?}", err);
+
+    if err.downcast_ref::<SqlError>().is_some() {
+        return ApiError::new(
+            400,
+            "A statement of the migration failed, nothing was applied",
+        );
+    }
+
+    ApiError::new(500, "Database error")
+}
@@ -0,0 +1,199 @@
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+use migration::sea_orm::prelude::DateTimeWithTimeZone;
+use migration::sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use storage::{Item, KvError, ListOptions, Value, Write};
+
+use super::namespaces::find_namespace;
+use crate::{errors::ApiError, middleware::user::User};
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    prefix: Option<String>,
+    limit: Option<u64>,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListKey {
+    name: String,
+    expires_at: Option<DateTimeWithTimeZone>,
+    metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListResponse {
+    keys: Vec<ListKey>,
+    list_complete: bool,
+    cursor: Option<String>,
+}
+
+#[axum_macros::debug_handler]
+pub async fn list_keys(
+    user: User,
+    Path((_, namespace)): Path<(i32, String)>,
+    Query(query): Query<ListQuery>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<ListResponse>, ApiError> {
+    let namespace_id = namespace_id(conn, &user, &namespace).await?;
+    let options = ListOptions {
+        prefix: query.prefix,
+        limit: query.limit,
+        cursor: query.cursor,
+    };
+    let page = storage::list(conn, namespace_id, &options)
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json(ListResponse {
+        keys: page
+            .items
+            .into_iter()
+            .map(|item| ListKey {
+                name: item.key,
+                expires_at: item.expires_at,
+                metadata: item.metadata,
+            })
+            .collect(),
+        list_complete: page.list_complete,
+        cursor: page.cursor,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutValue {
+    /// In the shape `{ "type": "text" | "json" | "bytes", "value": ... }`, bytes are base64 encoded
+    value: Value,
+    metadata: Option<serde_json::Value>,
+    /// Seconds from now after which the key expires
+    expiration_ttl: Option<i64>,
+    /// Seconds since the UNIX epoch at which the key expires
+    expiration: Option<i64>,
+}
+
+impl PutValue {
+    fn into_write(self, key: String) -> Result<Write, ApiError> {
+        let expires_at =
+            storage::expires_at(self.expiration_ttl, self.expiration).map_err(storage_error)?;
+
+        Write::new(key, self.value, self.metadata, expires_at).map_err(storage_error)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutKey {
+    key: String,
+    #[serde(flatten)]
+    value: PutValue,
+}
+
+/// Writes all keys in one transaction
+#[axum_macros::debug_handler]
+pub async fn put_keys(
+    user: User,
+    Path((_, namespace)): Path<(i32, String)>,
+    Json(keys): Json<Vec<PutKey>>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    let namespace_id = namespace_id(conn, &user, &namespace).await?;
+    let writes = keys
+        .into_iter()
+        .map(|key| key.value.into_write(key.key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    storage::put_many(conn, namespace_id, writes)
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json("Stored keys succesfully"))
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_keys(
+    user: User,
+    Path((_, namespace)): Path<(i32, String)>,
+    Json(keys): Json<Vec<String>>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    let namespace_id = namespace_id(conn, &user, &namespace).await?;
+    storage::delete_many(conn, namespace_id, keys)
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json("Deleted keys succesfully"))
+}
+
+#[axum_macros::debug_handler]
+pub async fn get_key(
+    user: User,
+    Path((_, namespace, key)): Path<(i32, String, String)>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<Item>, ApiError> {
+    let namespace_id = namespace_id(conn, &user, &namespace).await?;
+    let item = storage::get(conn, namespace_id, wildcard_key(&key))
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(|| ApiError::new(404, "No key found with this name"))?;
+
+    Ok(Json(item))
+}
+
+#[axum_macros::debug_handler]
+pub async fn put_key(
+    user: User,
+    Path((_, namespace, key)): Path<(i32, String, String)>,
+    Json(value): Json<PutValue>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    let namespace_id = namespace_id(conn, &user, &namespace).await?;
+    let write = value.into_write(wildcard_key(&key).to_string())?;
+
+    storage::put(conn, namespace_id, write)
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json("Stored key succesfully"))
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_key(
+    user: User,
+    Path((_, namespace, key)): Path<(i32, String, String)>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    let namespace_id = namespace_id(conn, &user, &namespace).await?;
+    storage::delete(conn, namespace_id, wildcard_key(&key))
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json("Deleted key succesfully"))
+}
+
+pub(super) async fn namespace_id(
+    conn: &DatabaseConnection,
+    user: &User,
+    name: &str,
+) -> Result<i32, ApiError> {
+    let namespace = find_namespace(conn, user.0.id, name)
+        .await?
+        .ok_or_else(|| ApiError::new(404, "No namespace found with this name"))?;
+
+    Ok(namespace.id)
+}
+
+/// Keys are matched with a wildcard so they can contain slashes, the match starts with one
+fn wildcard_key(key: &str) -> &str {
+    key.strip_prefix('/').unwrap_or(key)
+}
+
+/// Rejected operations are the fault of the client, anything else is a database error
+pub(super) fn storage_error(err: anyhow::Error) -> ApiError {
+    println!("{:?}", err);
+
+    if let Some(err) = err.downcast_ref::<KvError>() {
+        return ApiError::new(400, &err.to_string());
+    }
+
+    ApiError::new(500, "Database error")
+}
@@ -1,6 +1,6 @@
 use axum::{
     extract::{Extension, Multipart},
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use entity::user;
@@ -10,10 +10,55 @@ use sha256::digest_bytes;
 
 use crate::{errors::ApiError, middleware::user::User};
 
+mod actor_bindings;
+mod bindings;
+mod cache;
+mod database;
+mod keys;
+mod namespaces;
+mod transfer;
+
 pub fn router() -> Router {
     Router::new()
         .route("/", get(me))
         .route("/deploy", post(deploy))
+        .route(
+            "/namespaces",
+            get(namespaces::get_namespaces).post(namespaces::create_namespace),
+        )
+        .route("/namespaces/:name", delete(namespaces::delete_namespace))
+        .route(
+            "/namespaces/:name/keys",
+            get(keys::list_keys)
+                .put(keys::put_keys)
+                .delete(keys::delete_keys),
+        )
+        .route("/namespaces/:name/export", get(transfer::export_keys))
+        .route("/namespaces/:name/import", post(transfer::import_keys))
+        .route(
+            "/namespaces/:name/keys/*key",
+            get(keys::get_key)
+                .put(keys::put_key)
+                .delete(keys::delete_key),
+        )
+        .route("/bindings", get(bindings::get_bindings))
+        .route(
+            "/bindings/:name",
+            put(bindings::put_binding).delete(bindings::delete_binding),
+        )
+        .route("/actor-bindings", get(actor_bindings::get_actor_bindings))
+        .route(
+            "/actor-bindings/:name",
+            put(actor_bindings::put_actor_binding).delete(actor_bindings::delete_actor_binding),
+        )
+        .route(
+            "/database",
+            get(database::get_database)
+                .put(database::create_database)
+                .delete(database::delete_database),
+        )
+        .route("/database/migrations", post(database::apply_migration))
+        .route("/cache/purge", post(cache::purge_cache))
 }
 
 #[axum_macros::debug_handler]
@@ -0,0 +1,113 @@
+use axum::extract::Path;
+use axum::{extract::Extension, Json};
+use entity::namespace;
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter};
+use serde::Deserialize;
+
+use crate::{errors::ApiError, middleware::user::User};
+
+static DEFAULT_NAMESPACE: &str = "default";
+
+#[axum_macros::debug_handler]
+pub async fn get_namespaces(
+    user: User,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<Vec<namespace::Model>>, ApiError> {
+    let items = namespace::Entity::find()
+        .filter(namespace::Column::UserId.eq(user.0.id))
+        .all(conn)
+        .await
+        .map_err(ApiError::db)?;
+
+    Ok(Json(items))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNamespace {
+    name: String,
+}
+
+#[axum_macros::debug_handler]
+pub async fn create_namespace(
+    user: User,
+    Json(params): Json<CreateNamespace>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<namespace::Model>, ApiError> {
+    if !is_valid_name(&params.name) {
+        return Err(ApiError::new(
+            400,
+            "Namespace names can only contain letters, digits, '-' and '_' and be at most 64 characters",
+        ));
+    }
+
+    if find_namespace(conn, user.0.id, &params.name)
+        .await?
+        .is_some()
+    {
+        return Err(ApiError::new(
+            409,
+            "A namespace with this name already exists",
+        ));
+    }
+
+    let to_be_inserted = namespace::ActiveModel {
+        name: Set(params.name),
+        user_id: Set(user.0.id),
+        created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+        ..entity::namespace::ActiveModel::default()
+    };
+    let insert_res = namespace::Entity::insert(to_be_inserted)
+        .exec(conn)
+        .await
+        .map_err(ApiError::db)?;
+
+    let namespace = namespace::Entity::find_by_id(insert_res.last_insert_id)
+        .one(conn)
+        .await
+        .map_err(ApiError::db)?
+        .ok_or_else(|| ApiError::new(500, "Namespace disappeared after creating it"))?;
+
+    Ok(Json(namespace))
+}
+
+#[axum_macros::debug_handler]
+pub async fn delete_namespace(
+    user: User,
+    Path((_, name)): Path<(i32, String)>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+) -> Result<Json<&'static str>, ApiError> {
+    if name == DEFAULT_NAMESPACE {
+        return Err(ApiError::new(400, "The default namespace can't be deleted"));
+    }
+
+    let namespace = find_namespace(conn, user.0.id, &name)
+        .await?
+        .ok_or_else(|| ApiError::new(404, "No namespace found with this name"))?;
+
+    // store items and bindings of this namespace are removed by the cascade
+    namespace.delete(conn).await.map_err(ApiError::db)?;
+
+    Ok(Json("Deleted namespace succesfully"))
+}
+
+pub async fn find_namespace(
+    conn: &DatabaseConnection,
+    user_id: i32,
+    name: &str,
+) -> Result<Option<namespace::Model>, ApiError> {
+    namespace::Entity::find()
+        .filter(namespace::Column::UserId.eq(user_id))
+        .filter(namespace::Column::Name.eq(name))
+        .one(conn)
+        .await
+        .map_err(ApiError::db)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -0,0 +1,87 @@
+use axum::body::StreamBody;
+use axum::extract::{BodyStream, Extension, Path};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Json;
+use futures::StreamExt;
+use migration::sea_orm::DatabaseConnection;
+use storage::{ImportSummary, Importer, MAX_IMPORT_LINE_BYTES};
+
+use super::keys::{namespace_id, storage_error};
+use crate::{errors::ApiError, middleware::user::User};
+
+/// Streams every key of the namespace as JSON lines, one page at a time
+#[axum_macros::debug_handler]
+pub async fn export_keys(
+    user: User,
+    Path((_, namespace)): Path<(i32, String)>,
+    Extension(conn): Extension<DatabaseConnection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let namespace_id = namespace_id(&conn, &user, &namespace).await?;
+
+    // `None` once the last page was sent, otherwise the cursor of the next page
+    let lines = futures::stream::try_unfold(Some(None), move |cursor: Option<Option<String>>| {
+        let conn = conn.clone();
+        async move {
+            let cursor = match cursor {
+                Some(cursor) => cursor,
+                None => return Ok(None),
+            };
+            let (lines, next) = storage::export_page(&conn, namespace_id, cursor).await?;
+
+            Ok::<_, anyhow::Error>(Some((lines, next.map(Some))))
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(lines),
+    ))
+}
+
+/// Reads JSON lines from the request body and writes them in batches,
+/// existing keys are overwritten
+#[axum_macros::debug_handler]
+pub async fn import_keys(
+    user: User,
+    Path((_, namespace)): Path<(i32, String)>,
+    Extension(ref conn): Extension<DatabaseConnection>,
+    mut body: BodyStream,
+) -> Result<Json<ImportSummary>, ApiError> {
+    let namespace_id = namespace_id(conn, &user, &namespace).await?;
+    let mut importer = Importer::new(conn, namespace_id);
+
+    let mut buffer: Vec<u8> = vec![];
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|_| ApiError::new(400, "Failed to read request body"))?;
+        // the bytes already in the buffer hold no newline, only the new ones are searched
+        let mut scanned = buffer.len();
+        buffer.extend_from_slice(&chunk);
+
+        let mut start = 0;
+        while let Some(offset) = buffer[scanned..].iter().position(|byte| *byte == b'\n') {
+            let end = scanned + offset;
+            push_line(&mut importer, &buffer[start..end]).await?;
+            start = end + 1;
+            scanned = start;
+        }
+        buffer.drain(..start);
+
+        // what's left is the start of a line, it can't be longer than a whole line
+        if buffer.len() > MAX_IMPORT_LINE_BYTES {
+            return Err(ApiError::new(413, "A line of the import is too long"));
+        }
+    }
+    push_line(&mut importer, &buffer).await?;
+
+    let summary = importer.finish().await.map_err(storage_error)?;
+
+    Ok(Json(summary))
+}
+
+async fn push_line(importer: &mut Importer<'_>, line: &[u8]) -> Result<(), ApiError> {
+    let line =
+        std::str::from_utf8(line).map_err(|_| ApiError::new(400, "Import has to be UTF-8"))?;
+
+    importer.push(line).await.map_err(storage_error)
+}
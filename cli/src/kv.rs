@@ -0,0 +1,87 @@
+use reqwest::{Client, Method, RequestBuilder, Response, Url};
+use std::path::Path;
+use storage::ImportSummary;
+use tokio::fs::File;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// The API that holds the namespace and the token of a user that owns it
+pub struct Remote {
+    pub url: String,
+    pub token: String,
+    pub user_id: i32,
+}
+
+impl Remote {
+    /// Builds a request for `/:user_id/namespaces/:namespace/:action`, the namespace is escaped
+    fn request(&self, method: Method, namespace: &str, action: &str) -> RequestBuilder {
+        let user_id = self.user_id.to_string();
+        let mut url = Url::parse(&self.url)
+            .unwrap_or_else(|e| exit(&format!("{} isn't a valid API URL: {}", self.url, e)));
+        url.path_segments_mut()
+            .unwrap_or_else(|_| exit(&format!("{} isn't a valid API URL", self.url)))
+            .pop_if_empty()
+            .extend([user_id.as_str(), "namespaces", namespace, action]);
+
+        Client::new().request(method, url).bearer_auth(&self.token)
+    }
+}
+
+/// Sends the request and exits with the message of the API if it fails
+async fn send(request: RequestBuilder) -> Response {
+    let response = request
+        .send()
+        .await
+        .unwrap_or_else(|e| exit(&format!("Failed to reach the API: {}", e)));
+
+    let status = response.status();
+    if !status.is_success() {
+        let message = response.text().await.unwrap_or_default();
+        exit(&format!("The API answered with {}: {}", status, message));
+    }
+
+    response
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// Writes every key of the namespace as JSON lines to the file or stdout
+pub async fn export(remote: &Remote, namespace: &str, output: Option<&Path>) {
+    let mut response = send(remote.request(Method::GET, namespace, "export")).await;
+
+    let mut writer: Box<dyn AsyncWrite + Unpin> = match output {
+        Some(path) => Box::new(File::create(path).await.expect("Failed to create file")),
+        None => Box::new(tokio::io::stdout()),
+    };
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .unwrap_or_else(|e| exit(&format!("Export failed: {}", e)))
+    {
+        writer
+            .write_all(&chunk)
+            .await
+            .expect("Failed to write export");
+    }
+
+    writer.flush().await.expect("Failed to write export");
+}
+
+/// Streams the JSON lines of the file into the namespace, existing keys are overwritten
+pub async fn import(remote: &Remote, namespace: &str, input: &Path) {
+    let file = File::open(input).await.expect("Failed to open file");
+    let response = send(remote.request(Method::POST, namespace, "import").body(file)).await;
+
+    let summary: ImportSummary = response
+        .json()
+        .await
+        .unwrap_or_else(|e| exit(&format!("Import failed: {}", e)));
+
+    println!(
+        "Imported {} keys, skipped {} expired keys",
+        summary.imported, summary.skipped
+    );
+}
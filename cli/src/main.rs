@@ -4,12 +4,23 @@ use std::{
     time::{Duration, Instant},
 };
 
-use clap::{arg, Command};
+use clap::{arg, Arg, ArgMatches, Command};
+use config::KvBackendKind;
 use notify::{watcher, RecursiveMode, Watcher};
 use tokio::task::JoinHandle;
 
+mod kv;
 mod run;
 
+/// The API the KV commands talk to, they never touch a database themselves
+fn remote_args() -> [Arg<'static>; 3] {
+    [
+        arg!(--api <URL> "The URL of the API"),
+        arg!(--token <TOKEN> "A token of the user from /authorize"),
+        arg!(--user <ID> "The id of the user that owns the namespace"),
+    ]
+}
+
 fn cli() -> Command<'static> {
     Command::new("hbw")
         .about("Run homebrew-workers locally")
@@ -22,8 +33,46 @@ fn cli() -> Command<'static> {
                 .about("Runs script from this directory")
                 .arg(arg!(<PATH> "The path to the script"))
                 .arg(arg!(-w --watch "Watch the directory"))
+                .arg(
+                    arg!(--kv <BACKEND> "Where KV items are kept, memory needs no database")
+                        .required(false)
+                        .possible_values(["postgres", "memory"]),
+                )
                 .arg_required_else_help(true),
         )
+        .subcommand(
+            Command::new("kv")
+                .about("Moves KV namespaces in and out of a deployment through its API")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("export")
+                        .about("Exports a namespace as JSON lines")
+                        .args(remote_args())
+                        .arg(arg!(<NAMESPACE> "The name of the namespace"))
+                        .arg(
+                            arg!(-o --output <FILE> "Writes to a file instead of stdout")
+                                .required(false),
+                        )
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Imports JSON lines into a namespace")
+                        .args(remote_args())
+                        .arg(arg!(<NAMESPACE> "The name of the namespace"))
+                        .arg(arg!(<FILE> "The file to import"))
+                        .arg_required_else_help(true),
+                ),
+        )
+}
+
+fn remote(matches: &ArgMatches) -> kv::Remote {
+    kv::Remote {
+        url: matches.value_of("api").expect("required").into(),
+        token: matches.value_of("token").expect("required").into(),
+        user_id: matches.value_of_t("user").unwrap_or_else(|e| e.exit()),
+    }
 }
 
 #[tokio::main]
@@ -34,6 +83,9 @@ async fn main() {
         Some(("run", sub_matches)) => {
             let path_str = sub_matches.value_of("PATH").expect("required");
             let path = PathBuf::from_str(path_str).expect("Failed to build pathbuf");
+            let kv = sub_matches
+                .value_of("kv")
+                .map(|kv| KvBackendKind::from_str(kv).expect("Checked by clap"));
 
             let is_watch_mode = sub_matches.is_present("watch");
             if is_watch_mode {
@@ -58,7 +110,7 @@ async fn main() {
 
                         let path = path.clone();
                         let handle = tokio::spawn(async move {
-                            run::start(path).await;
+                            run::start(path, kv).await;
                         });
                         current_handle = Some(handle);
                     }
@@ -78,9 +130,24 @@ async fn main() {
                     last_restart = Instant::now();
                 }
             } else {
-                run::start(path).await;
+                run::start(path, kv).await;
             }
         }
+        Some(("kv", sub_matches)) => match sub_matches.subcommand() {
+            Some(("export", export_matches)) => {
+                let remote = remote(export_matches);
+                let namespace = export_matches.value_of("NAMESPACE").expect("required");
+                let output = export_matches.value_of("output").map(PathBuf::from);
+                kv::export(&remote, namespace, output.as_deref()).await;
+            }
+            Some(("import", import_matches)) => {
+                let remote = remote(import_matches);
+                let namespace = import_matches.value_of("NAMESPACE").expect("required");
+                let input = PathBuf::from(import_matches.value_of("FILE").expect("required"));
+                kv::import(&remote, namespace, &input).await;
+            }
+            _ => unreachable!(),
+        },
         _ => unreachable!(), // If all subcommands are defined above, anything else is unreachabe!()
     }
 }
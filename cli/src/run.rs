@@ -1,3 +1,5 @@
+use config::{Config, KvBackendKind};
+use entity::kv_binding;
 use entity::namespace;
 use entity::user;
 use migration::sea_orm::ActiveValue::Set;
@@ -9,8 +11,11 @@ use rand::{distributions::Alphanumeric, Rng};
 use session::Session;
 use std::path::PathBuf;
 use workers::app::App;
+use workers::manifest::Manifest;
 
 static USER_NAME: &str = "cli-user";
+/// The user scripts run as without a database
+const OFFLINE_USER_ID: i32 = 0;
 
 async fn query_default_user(conn: &DatabaseConnection) -> Option<user::Model> {
     user::Entity::find()
@@ -56,7 +61,20 @@ async fn get_or_create_default_user(conn: &DatabaseConnection) -> user::Model {
         created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
         ..Default::default()
     };
-    namespace::Entity::insert(to_be_inserted)
+    let namespace_res = namespace::Entity::insert(to_be_inserted)
+        .exec(conn)
+        .await
+        .unwrap();
+
+    // Bind the namespace as `kvStorage` in the script
+    let to_be_inserted = kv_binding::ActiveModel {
+        name: Set("kvStorage".into()),
+        user_id: Set(insert_res.last_insert_id),
+        namespace_id: Set(namespace_res.last_insert_id),
+        created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+        ..Default::default()
+    };
+    kv_binding::Entity::insert(to_be_inserted)
         .exec(conn)
         .await
         .unwrap();
@@ -64,30 +82,70 @@ async fn get_or_create_default_user(conn: &DatabaseConnection) -> user::Model {
     return query_default_user(conn).await.unwrap();
 }
 
-pub async fn start(path_buf: PathBuf) {
-    let conn = Database::connect(
-        std::env::var("DATABASE_URL")
-            .expect("No DATABASE_URL environment variable found.")
-            .as_str(),
-    )
-    .await
-    .expect("Database connection failed");
+/// The CLI needs nothing but a database, S3 is used for blobs if it's configured
+fn load_config() -> Config {
+    match Config::load(&[]) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Connects to the database and brings the schema up to date
+async fn connect(config: &Config) -> DatabaseConnection {
+    let database_url = config.database_url().unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(1);
+    });
+    let conn = Database::connect(database_url)
+        .await
+        .expect("Database connection failed");
 
     Migrator::up(&conn, None).await.unwrap();
 
-    let user = get_or_create_default_user(&conn).await;
-    let session = Session {
-        user_id: user.id,
-        conn,
+    conn
+}
+
+/// Runs the script as the CLI user. With KV in memory and no `DATABASE_URL` it runs without
+/// a database, everything but KV and the cache is unavailable then
+pub async fn start(path_buf: PathBuf, kv: Option<KvBackendKind>) {
+    let manifest = Manifest::load(&path_buf).expect("Invalid deployment");
+    let mut config = load_config();
+    if let Some(kv) = kv {
+        config.kv.backend = kv;
+    }
+    let blobs_bucket = config
+        .s3
+        .as_ref()
+        .map(|s3| s3.blobs_bucket().expect("Invalid S3 configuration"));
+
+    let session = match config.database_url() {
+        Ok(_) => {
+            let conn = connect(&config).await;
+            let user = get_or_create_default_user(&conn).await;
+            Session::new(user.id, conn, &config)
+        }
+        Err(_) if config.kv.backend == KvBackendKind::Memory => {
+            println!("Running without a database, only KV and the cache are available");
+            Session::new(OFFLINE_USER_ID, DatabaseConnection::Disconnected, &config)
+        }
+        Err(e) => {
+            println!("{}, use --kv memory to run without a database", e);
+            std::process::exit(1);
+        }
     };
 
     let app = App::new(
         session,
         "default".into(),
         path_buf,
-        "main.js".into(),
+        manifest.main,
+        manifest.assets,
         "cli-deployment".into(),
+        blobs_bucket,
     );
 
-    workers::run(Some(app)).await.unwrap();
+    workers::run(config, Some(app)).await.unwrap();
 }
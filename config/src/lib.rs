@@ -0,0 +1,764 @@
+#![deny(clippy::all)]
+#![warn(clippy::pedantic)]
+#![warn(clippy::nursery)]
+//! Settings of the servers, read once at startup from the environment and the optional TOML
+//! file `CONFIG_FILE` points to. Environment variables win over the file
+use anyhow::{Context, Result};
+use ipnet::IpNet;
+use s3::Bucket;
+use serde::Deserialize;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const DEFAULT_WORKERS_LISTEN: &str = "0.0.0.0:3000";
+const DEFAULT_API_LISTEN: &str = "0.0.0.0:3001";
+const DEFAULT_TLS_PORT: u16 = 3443;
+const DEFAULT_EDGE_CACHE_BYTES: usize = 256 * 1024 * 1024;
+const DEFAULT_STATEMENT_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_MAX_ROWS: usize = 1000;
+const DEFAULT_CACHE_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_BLOBS_QUOTA_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// The servers a binary runs, settings only they use are required for them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Server {
+    Api,
+    Workers,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Required by the servers, `hbw run` works without a database when KV is kept in memory
+    database_url: Option<String>,
+    /// Holds the deployments and blobs, required by both servers
+    pub s3: Option<S3Config>,
+    /// Required by the API
+    pub auth: Option<AuthConfig>,
+    pub api_listen: SocketAddr,
+    pub workers_listen: SocketAddr,
+    /// Proxies that are allowed to set the `X-Forwarded-*` and `X-Request-Id` headers
+    pub trusted_proxies: Vec<IpNet>,
+    /// HTTPS is only served if certificates are configured
+    pub tls: Option<TlsConfig>,
+    /// The shared cache in front of the runtimes is off by default
+    pub edge_cache: Option<EdgeCacheConfig>,
+    pub kv: KvConfig,
+    pub db: DbConfig,
+    pub cache: CacheConfig,
+    pub blobs: BlobsConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    pub access_key: String,
+    pub secret_key: String,
+    pub bucket: String,
+    pub region: String,
+    /// Only set for S3 compatible storage like minio, buckets are addressed path style then
+    pub endpoint: Option<String>,
+    /// Blobs of the scripts are stored next to the deployments if not set
+    pub blobs_bucket: Option<String>,
+}
+
+impl S3Config {
+    /// The bucket of the deployments
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the region or the credentials are invalid
+    pub fn bucket(&self) -> Result<Bucket> {
+        self.open(&self.bucket)
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the region or the credentials are invalid
+    pub fn blobs_bucket(&self) -> Result<Bucket> {
+        self.open(self.blobs_bucket.as_ref().unwrap_or(&self.bucket))
+    }
+
+    fn open(&self, name: &str) -> Result<Bucket> {
+        let credentials = s3::creds::Credentials::new(
+            Some(&self.access_key),
+            Some(&self.secret_key),
+            None,
+            None,
+            None,
+        )
+        .context("Invalid S3 credentials")?;
+
+        let bucket = match &self.endpoint {
+            Some(endpoint) => Bucket::new_with_path_style(
+                name,
+                s3::Region::Custom {
+                    region: self.region.clone(),
+                    endpoint: endpoint.clone(),
+                },
+                credentials,
+            )?,
+            None => Bucket::new(
+                name,
+                s3::Region::from_str(&self.region).context("Unknown S3 region")?,
+                credentials,
+            )?,
+        };
+
+        Ok(bucket)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Signs the tokens handed out by `/authorize`
+    pub jwt_secret: String,
+    /// Credentials that get an admin token, used to create users
+    pub admin_client_id: String,
+    pub admin_client_secret: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// Certificate chain and key for clients without a matching domain
+    pub default: Option<(PathBuf, PathBuf)>,
+    /// A directory per domain with a `fullchain.pem` and a `privkey.pem`, laid out like the
+    /// `live` directory of certbot
+    pub certs_dir: Option<PathBuf>,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EdgeCacheConfig {
+    /// Responses of all apps together never take up more
+    pub budget_bytes: usize,
+}
+
+/// Where the items of KV namespaces are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvBackendKind {
+    Postgres,
+    /// Shared by the isolates of the process, every item is lost when it exits
+    Memory,
+}
+
+impl FromStr for KvBackendKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "postgres" => Ok(Self::Postgres),
+            "memory" => Ok(Self::Memory),
+            _ => Err(format!(
+                "{} is no KV backend, use postgres or memory",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KvConfig {
+    /// Postgres unless configured otherwise
+    pub backend: KvBackendKind,
+}
+
+/// Bounds every statement an app runs on its database
+#[derive(Debug, Clone, Copy)]
+pub struct DbConfig {
+    pub statement_timeout_ms: u64,
+    pub max_rows: usize,
+}
+
+/// Where the responses scripts put into `caches` are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheBackendKind {
+    /// Shared by the isolates of an app, lost when the process exits
+    Memory,
+    Postgres,
+}
+
+impl FromStr for CacheBackendKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(format!(
+                "{} is no cache backend, use memory or postgres",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheConfig {
+    /// Memory unless configured otherwise
+    pub backend: CacheBackendKind,
+    /// Bytes of responses the memory backend keeps per app
+    pub memory_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlobsConfig {
+    /// Total size of all objects of a user
+    pub quota_bytes: u64,
+}
+
+/// Every problem of the configuration, so all of them can be fixed at once
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    database_url: Option<String>,
+    s3: S3File,
+    auth: AuthFile,
+    api: ServerFile,
+    workers: WorkersFile,
+    tls: TlsFile,
+    edge_cache: EdgeCacheFile,
+    kv: KvFile,
+    db: DbFile,
+    cache: CacheFile,
+    blobs: BlobsFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct S3File {
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    bucket: Option<String>,
+    region: Option<String>,
+    endpoint: Option<String>,
+    blobs_bucket: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AuthFile {
+    jwt_secret: Option<String>,
+    admin_client_id: Option<String>,
+    admin_client_secret: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerFile {
+    listen: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WorkersFile {
+    listen: Option<String>,
+    trusted_proxies: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TlsFile {
+    cert: Option<String>,
+    key: Option<String>,
+    certs_dir: Option<String>,
+    port: Option<u16>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct EdgeCacheFile {
+    enabled: Option<bool>,
+    budget_bytes: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct KvFile {
+    backend: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DbFile {
+    statement_timeout_ms: Option<u64>,
+    max_rows: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CacheFile {
+    backend: Option<String>,
+    memory_bytes: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BlobsFile {
+    quota_bytes: Option<u64>,
+}
+
+fn read_file(path: &Path) -> Result<ConfigFile, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    toml::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+/// Collects the errors while the settings are read
+struct Loader<'a> {
+    /// Looks up environment variables, replaced in tests
+    env: &'a dyn Fn(&str) -> Option<String>,
+    errors: Vec<String>,
+}
+
+impl Loader<'_> {
+    /// The environment variable if it's set, the value from the file otherwise.
+    /// Empty values count as not set, like the ones of `.env.example`
+    fn optional(&self, env: &str, file: Option<String>) -> Option<String> {
+        let is_set = |value: &String| !value.is_empty();
+        (self.env)(env)
+            .filter(is_set)
+            .or_else(|| file.filter(is_set))
+    }
+
+    fn required(&mut self, env: &str, key: &str, file: Option<String>) -> String {
+        self.optional(env, file).unwrap_or_else(|| {
+            self.errors.push(format!(
+                "{} is not set (or {} in the config file)",
+                env, key
+            ));
+            String::new()
+        })
+    }
+
+    /// Parses the setting if it's set, falls back to the default otherwise
+    fn parsed<T>(&mut self, env: &str, file: Option<String>, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.optional(env, file) {
+            Some(value) => value.parse().unwrap_or_else(|e| {
+                self.errors.push(format!("{} is invalid: {}", env, e));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    fn address(&mut self, env: &str, file: Option<String>, default: &str) -> SocketAddr {
+        let value = self.optional(env, file).unwrap_or_else(|| default.into());
+        value.parse().unwrap_or_else(|_| {
+            self.errors
+                .push(format!("{} isn't a valid address: {}", env, value));
+            default.parse().unwrap()
+        })
+    }
+
+    /// Without a server S3 is optional, it's only used if it is configured completely
+    fn optional_s3(&self, file: S3File) -> Option<S3Config> {
+        let mut loader = Loader {
+            env: self.env,
+            errors: vec![],
+        };
+        let s3 = loader.s3(file);
+
+        if loader.errors.is_empty() {
+            Some(s3)
+        } else {
+            None
+        }
+    }
+
+    fn s3(&mut self, file: S3File) -> S3Config {
+        S3Config {
+            access_key: self.required("S3_ACCESS_KEY", "s3.access_key", file.access_key),
+            secret_key: self.required("S3_SECRET_KEY", "s3.secret_key", file.secret_key),
+            bucket: self.required("S3_BUCKET", "s3.bucket", file.bucket),
+            region: self.required("S3_REGION", "s3.region", file.region),
+            endpoint: self.optional("S3_ENDPOINT", file.endpoint),
+            blobs_bucket: self.optional("BLOBS_BUCKET", file.blobs_bucket),
+        }
+    }
+
+    fn auth(&mut self, file: AuthFile) -> AuthConfig {
+        AuthConfig {
+            jwt_secret: self.required("JWT_SECRET", "auth.jwt_secret", file.jwt_secret),
+            admin_client_id: self.required(
+                "ADMIN_CLIENT_ID",
+                "auth.admin_client_id",
+                file.admin_client_id,
+            ),
+            admin_client_secret: self.required(
+                "ADMIN_CLIENT_SECRET",
+                "auth.admin_client_secret",
+                file.admin_client_secret,
+            ),
+        }
+    }
+
+    /// A comma separated list of addresses or CIDR ranges, a list in the file
+    fn trusted_proxies(&mut self, file: Option<Vec<String>>) -> Vec<IpNet> {
+        let value = self
+            .optional("TRUSTED_PROXIES", file.map(|proxies| proxies.join(",")))
+            .unwrap_or_default();
+
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|it| !it.is_empty())
+            .filter_map(|it| {
+                let net = IpNet::from_str(it).or_else(|_| IpAddr::from_str(it).map(IpNet::from));
+                if net.is_err() {
+                    self.errors
+                        .push(format!("TRUSTED_PROXIES has an invalid address: {}", it));
+                }
+                net.ok()
+            })
+            .collect()
+    }
+
+    /// Only enabled if a default certificate or a directory of certificates is set
+    fn tls(&mut self, file: TlsFile) -> Option<TlsConfig> {
+        let cert = self.optional("TLS_CERT", file.cert);
+        let key = self.optional("TLS_KEY", file.key);
+        let default = match (cert, key) {
+            (Some(cert), Some(key)) => Some((cert.into(), key.into())),
+            (None, None) => None,
+            _ => {
+                self.errors
+                    .push("TLS_CERT and TLS_KEY have to be set together".into());
+                None
+            }
+        };
+        let certs_dir = self
+            .optional("TLS_CERTS_DIR", file.certs_dir)
+            .map(PathBuf::from);
+        let port = self.parsed(
+            "TLS_PORT",
+            file.port.map(|port| port.to_string()),
+            DEFAULT_TLS_PORT,
+        );
+
+        if default.is_none() && certs_dir.is_none() {
+            return None;
+        }
+
+        Some(TlsConfig {
+            default,
+            certs_dir,
+            port,
+        })
+    }
+
+    fn edge_cache(&mut self, file: EdgeCacheFile) -> Option<EdgeCacheConfig> {
+        let enabled = self.parsed(
+            "EDGE_CACHE",
+            file.enabled.map(|enabled| enabled.to_string()),
+            false,
+        );
+        let budget_bytes = self.parsed(
+            "EDGE_CACHE_BYTES",
+            file.budget_bytes.map(|bytes| bytes.to_string()),
+            DEFAULT_EDGE_CACHE_BYTES,
+        );
+
+        if enabled {
+            Some(EdgeCacheConfig { budget_bytes })
+        } else {
+            None
+        }
+    }
+
+    fn kv(&mut self, file: KvFile) -> KvConfig {
+        KvConfig {
+            backend: self.parsed("KV_BACKEND", file.backend, KvBackendKind::Postgres),
+        }
+    }
+
+    fn db(&mut self, file: DbFile) -> DbConfig {
+        DbConfig {
+            statement_timeout_ms: self.parsed(
+                "DB_STATEMENT_TIMEOUT_MS",
+                file.statement_timeout_ms.map(|timeout| timeout.to_string()),
+                DEFAULT_STATEMENT_TIMEOUT_MS,
+            ),
+            max_rows: self.parsed(
+                "DB_MAX_ROWS",
+                file.max_rows.map(|rows| rows.to_string()),
+                DEFAULT_MAX_ROWS,
+            ),
+        }
+    }
+
+    fn cache(&mut self, file: CacheFile) -> CacheConfig {
+        CacheConfig {
+            backend: self.parsed("CACHE_BACKEND", file.backend, CacheBackendKind::Memory),
+            memory_bytes: self.parsed(
+                "CACHE_MEMORY_BYTES",
+                file.memory_bytes.map(|bytes| bytes.to_string()),
+                DEFAULT_CACHE_MEMORY_BYTES,
+            ),
+        }
+    }
+
+    fn blobs(&mut self, file: BlobsFile) -> BlobsConfig {
+        BlobsConfig {
+            quota_bytes: self.parsed(
+                "BLOBS_QUOTA_BYTES",
+                file.quota_bytes.map(|bytes| bytes.to_string()),
+                DEFAULT_BLOBS_QUOTA_BYTES,
+            ),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the settings the given servers need, a binary without servers like the CLI
+    /// doesn't even need the database
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` listing every setting that is missing or invalid
+    pub fn load(servers: &[Server]) -> Result<Self, ConfigError> {
+        Self::load_from(servers, &|name| std::env::var(name).ok())
+    }
+
+    fn load_from(
+        servers: &[Server],
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut loader = Loader {
+            env,
+            errors: vec![],
+        };
+
+        let file = env("CONFIG_FILE")
+            .filter(|path| !path.is_empty())
+            .map_or_else(ConfigFile::default, |path| {
+                read_file(Path::new(&path)).unwrap_or_else(|e| {
+                    loader.errors.push(e);
+                    ConfigFile::default()
+                })
+            });
+
+        let database_url = if servers.is_empty() {
+            loader.optional("DATABASE_URL", file.database_url)
+        } else {
+            Some(loader.required("DATABASE_URL", "database_url", file.database_url))
+        };
+        let s3 = if servers.is_empty() {
+            loader.optional_s3(file.s3)
+        } else {
+            Some(loader.s3(file.s3))
+        };
+        let auth = if servers.contains(&Server::Api) {
+            Some(loader.auth(file.auth))
+        } else {
+            None
+        };
+        let api_listen = loader.address("API_LISTEN", file.api.listen, DEFAULT_API_LISTEN);
+        let workers_listen = loader.address(
+            "WORKERS_LISTEN",
+            file.workers.listen,
+            DEFAULT_WORKERS_LISTEN,
+        );
+        let trusted_proxies = loader.trusted_proxies(file.workers.trusted_proxies);
+        let tls = loader.tls(file.tls);
+        let edge_cache = loader.edge_cache(file.edge_cache);
+        let kv = loader.kv(file.kv);
+        let db = loader.db(file.db);
+        let cache = loader.cache(file.cache);
+        let blobs = loader.blobs(file.blobs);
+
+        if !loader.errors.is_empty() {
+            return Err(ConfigError(loader.errors));
+        }
+
+        Ok(Self {
+            database_url,
+            s3,
+            auth,
+            api_listen,
+            workers_listen,
+            trusted_proxies,
+            tls,
+            edge_cache,
+            kv,
+            db,
+            cache,
+            blobs,
+        })
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if no database is configured, only the CLI loads the settings
+    /// without one
+    pub fn database_url(&self) -> Result<&str> {
+        self.database_url
+            .as_deref()
+            .context("DATABASE_URL is not set (or database_url in the config file)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Loads the settings with only the given environment variables set
+    fn load(servers: &[Server], vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+            .collect();
+        Config::load_from(servers, &|name| vars.get(name).cloned())
+    }
+
+    /// Writes a config file that is removed again when it's dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "config-test-{}-{}.toml",
+                std::process::id(),
+                name
+            ));
+            std::fs::write(&path, content).unwrap();
+            Self(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).unwrap_or(());
+        }
+    }
+
+    #[test]
+    fn environment_wins_over_the_file() {
+        let file = TempFile::new(
+            "precedence",
+            r#"
+            database_url = "postgres://file"
+
+            [db]
+            statement_timeout_ms = 100
+            max_rows = 10
+
+            [kv]
+            backend = "memory"
+            "#,
+        );
+
+        let config = load(
+            &[],
+            &[
+                ("CONFIG_FILE", file.path()),
+                ("DATABASE_URL", "postgres://env"),
+                ("DB_MAX_ROWS", "20"),
+                // empty variables count as not set
+                ("KV_BACKEND", ""),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.database_url().unwrap(), "postgres://env");
+        assert_eq!(config.db.statement_timeout_ms, 100);
+        assert_eq!(config.db.max_rows, 20);
+        assert_eq!(config.kv.backend, KvBackendKind::Memory);
+    }
+
+    #[test]
+    fn defaults_apply_without_settings() {
+        let config = load(&[], &[]).unwrap();
+
+        assert!(config.database_url().is_err());
+        assert!(config.s3.is_none());
+        assert!(config.tls.is_none());
+        assert!(config.edge_cache.is_none());
+        assert_eq!(config.kv.backend, KvBackendKind::Postgres);
+        assert_eq!(config.cache.backend, CacheBackendKind::Memory);
+        assert_eq!(config.db.max_rows, DEFAULT_MAX_ROWS);
+        assert_eq!(config.blobs.quota_bytes, DEFAULT_BLOBS_QUOTA_BYTES);
+    }
+
+    #[test]
+    fn collects_every_error() {
+        let file = TempFile::new(
+            "errors",
+            r#"
+            [workers]
+            trusted_proxies = ["10.0.0.0/8", "proxy"]
+            "#,
+        );
+
+        let errors = load(
+            &[Server::Api, Server::Workers],
+            &[
+                ("CONFIG_FILE", file.path()),
+                ("S3_ACCESS_KEY", "key"),
+                ("KV_BACKEND", "redis"),
+                ("DB_MAX_ROWS", "many"),
+                ("TLS_CERT", "cert.pem"),
+                ("API_LISTEN", "nowhere"),
+            ],
+        )
+        .unwrap_err()
+        .0;
+
+        for expected in [
+            "DATABASE_URL is not set",
+            "S3_SECRET_KEY is not set",
+            "S3_BUCKET is not set",
+            "S3_REGION is not set",
+            "JWT_SECRET is not set",
+            "ADMIN_CLIENT_ID is not set",
+            "ADMIN_CLIENT_SECRET is not set",
+            "API_LISTEN isn't a valid address",
+            "TRUSTED_PROXIES has an invalid address: proxy",
+            "TLS_CERT and TLS_KEY have to be set together",
+            "KV_BACKEND is invalid",
+            "DB_MAX_ROWS is invalid",
+        ] {
+            assert!(
+                errors.iter().any(|error| error.starts_with(expected)),
+                "{} missing in {:?}",
+                expected,
+                errors
+            );
+        }
+        assert_eq!(errors.len(), 12);
+    }
+
+    #[test]
+    fn reports_unknown_file_keys() {
+        let file = TempFile::new("unknown", "[kv]\nbackends = \"memory\"\n");
+
+        let errors = load(&[], &[("CONFIG_FILE", file.path())]).unwrap_err().0;
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Invalid "), "{:?}", errors);
+    }
+}
@@ -1,3 +1,13 @@
+pub mod actor_binding;
+pub mod actor_storage;
+pub mod app_database;
+pub mod blob;
+pub mod cache_entry;
+pub mod cache_purge;
+pub mod database_migration;
+pub mod dead_letter;
+pub mod kv_binding;
 pub mod namespace;
+pub mod queue_message;
 pub mod store;
 pub mod user;
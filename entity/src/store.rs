@@ -7,10 +7,20 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub key: String,
-    #[sea_orm(column_type = "Custom(\"jsonb\".to_owned())")]
-    pub value: String,
+    /// One of `text`, `json` or `bytes`, tells which value column is set
+    pub value_type: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub value: Option<String>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub value_json: Option<Json>,
+    #[sea_orm(nullable)]
+    pub value_bytes: Option<Vec<u8>>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub metadata: Option<Json>,
     pub namespace_id: i32,
     pub created_at: DateTimeWithTimeZone,
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    pub version: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
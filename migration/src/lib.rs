@@ -3,6 +3,21 @@ pub use sea_schema::migration::*;
 mod m20220321_122000_create_users_table;
 mod m20220321_202100_create_namespaces_table;
 mod m20220321_204700_create_store_table;
+mod m20220402_153000_create_queue_tables;
+mod m20220404_101500_create_kv_bindings_table;
+mod m20220406_091000_add_expires_at_to_store;
+mod m20220407_140000_add_store_key_index;
+mod m20220408_113000_add_unique_store_key_index;
+mod m20220409_160000_add_version_to_store;
+mod m20220411_094500_add_typed_values_to_store;
+mod m20220412_170000_add_metadata_to_store;
+mod m20220413_120000_create_actor_tables;
+mod m20220414_100000_create_blobs_table;
+mod m20220415_090000_create_database_migrations_table;
+mod m20220416_110000_create_cache_entries_table;
+mod m20220417_150000_create_cache_purges_table;
+mod m20220418_100000_create_app_databases_table;
+mod m20220419_090000_use_c_collation_for_store_keys;
 
 pub struct Migrator;
 
@@ -13,6 +28,21 @@ impl MigratorTrait for Migrator {
             Box::new(m20220321_122000_create_users_table::Migration),
             Box::new(m20220321_202100_create_namespaces_table::Migration),
             Box::new(m20220321_204700_create_store_table::Migration),
+            Box::new(m20220402_153000_create_queue_tables::Migration),
+            Box::new(m20220404_101500_create_kv_bindings_table::Migration),
+            Box::new(m20220406_091000_add_expires_at_to_store::Migration),
+            Box::new(m20220407_140000_add_store_key_index::Migration),
+            Box::new(m20220408_113000_add_unique_store_key_index::Migration),
+            Box::new(m20220409_160000_add_version_to_store::Migration),
+            Box::new(m20220411_094500_add_typed_values_to_store::Migration),
+            Box::new(m20220412_170000_add_metadata_to_store::Migration),
+            Box::new(m20220413_120000_create_actor_tables::Migration),
+            Box::new(m20220414_100000_create_blobs_table::Migration),
+            Box::new(m20220415_090000_create_database_migrations_table::Migration),
+            Box::new(m20220416_110000_create_cache_entries_table::Migration),
+            Box::new(m20220417_150000_create_cache_purges_table::Migration),
+            Box::new(m20220418_100000_create_app_databases_table::Migration),
+            Box::new(m20220419_090000_use_c_collation_for_store_keys::Migration),
         ]
     }
 }
@@ -0,0 +1,147 @@
+use entity::{dead_letter, queue_message, user};
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220402_153000_create_queue_tables.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(queue_message::Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(queue_message::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(queue_message::Column::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(queue_message::Column::Queue)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(queue_message::Column::Body)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(queue_message::Column::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(queue_message::Column::VisibleAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(queue_message::Column::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(queue_message::Entity, queue_message::Column::UserId)
+                    .to(user::Entity, user::Column::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        // the consumer claims batches per user ordered by visibility
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_queue_messages_user_id_visible_at")
+                    .table(queue_message::Entity)
+                    .col(queue_message::Column::UserId)
+                    .col(queue_message::Column::VisibleAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(dead_letter::Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(dead_letter::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(dead_letter::Column::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(dead_letter::Column::Queue)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(dead_letter::Column::Body).text().not_null())
+                    .col(
+                        ColumnDef::new(dead_letter::Column::Attempts)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(dead_letter::Column::EnqueuedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(dead_letter::Column::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(dead_letter::Entity, dead_letter::Column::UserId)
+                    .to(user::Entity, user::Column::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(dead_letter::Entity).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(queue_message::Entity).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,113 @@
+use entity::{kv_binding::*, namespace, user};
+use sea_schema::migration::prelude::*;
+use sea_schema::migration::sea_orm::{ConnectionTrait, Statement};
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220404_101500_create_kv_bindings_table.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Column::Name).string().not_null())
+                    .col(ColumnDef::new(Column::UserId).integer().not_null())
+                    .col(ColumnDef::new(Column::NamespaceId).integer().not_null())
+                    .col(
+                        ColumnDef::new(Column::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(Entity, Column::UserId)
+                    .to(user::Entity, user::Column::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(Entity, Column::NamespaceId)
+                    .to(namespace::Entity, namespace::Column::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_kv_bindings_user_id_name")
+                    .table(Entity)
+                    .col(Column::UserId)
+                    .col(Column::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_namespaces_user_id_name")
+                    .table(namespace::Entity)
+                    .col(namespace::Column::UserId)
+                    .col(namespace::Column::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // scripts used the default namespace through `kvStorage`, keep that working
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"
+                INSERT INTO kv_bindings (name, user_id, namespace_id, created_at)
+                SELECT 'kvStorage', user_id, id, now() FROM namespaces WHERE name = 'default'
+                "#
+                .to_owned(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_namespaces_user_id_name")
+                    .table(namespace::Entity)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Entity).to_owned())
+            .await
+    }
+}
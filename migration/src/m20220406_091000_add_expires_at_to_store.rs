@@ -0,0 +1,56 @@
+use entity::store::*;
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220406_091000_add_expires_at_to_store.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(ColumnDef::new(Column::ExpiresAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+
+        // used by the sweeper to find expired items
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_store_expires_at")
+                    .table(Entity)
+                    .col(Column::ExpiresAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_store_expires_at")
+                    .table(Entity)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .drop_column(Column::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,37 @@
+use entity::store::*;
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220407_140000_add_store_key_index.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // keeps paginated listing an ordered index scan
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_store_namespace_id_key")
+                    .table(Entity)
+                    .col(Column::NamespaceId)
+                    .col(Column::Key)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_store_namespace_id_key")
+                    .table(Entity)
+                    .to_owned(),
+            )
+            .await
+    }
+}
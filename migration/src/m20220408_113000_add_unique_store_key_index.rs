@@ -0,0 +1,71 @@
+use entity::store::*;
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220408_113000_add_unique_store_key_index.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // concurrent writers could insert the same key twice, the most recent row wins
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"
+                DELETE FROM store a
+                USING store b
+                WHERE a.namespace_id = b.namespace_id AND a.key = b.key AND a.id < b.id
+                "#
+                .to_owned(),
+            ))
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_store_namespace_id_key")
+                    .table(Entity)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_store_namespace_id_key")
+                    .table(Entity)
+                    .col(Column::NamespaceId)
+                    .col(Column::Key)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_store_namespace_id_key")
+                    .table(Entity)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_store_namespace_id_key")
+                    .table(Entity)
+                    .col(Column::NamespaceId)
+                    .col(Column::Key)
+                    .to_owned(),
+            )
+            .await
+    }
+}
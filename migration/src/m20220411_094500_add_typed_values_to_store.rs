@@ -0,0 +1,92 @@
+use entity::store::*;
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220411_094500_add_typed_values_to_store.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // the entity declared `value` as jsonb while the table was created with a varchar,
+        // strings keep living in `value` and the other types get their own columns
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                "ALTER TABLE store ALTER COLUMN value TYPE text, ALTER COLUMN value DROP NOT NULL"
+                    .to_owned(),
+            ))
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(
+                        ColumnDef::new(Column::ValueType)
+                            .string()
+                            .not_null()
+                            .default("text"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(ColumnDef::new(Column::ValueJson).json_binary())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Entity)
+                    .add_column(ColumnDef::new(Column::ValueBytes).binary())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // JSON keeps its serialized form, binary values are base64 encoded
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"
+                UPDATE store SET value = CASE value_type
+                    WHEN 'json' THEN value_json::text
+                    WHEN 'bytes' THEN encode(value_bytes, 'base64')
+                    ELSE value
+                END
+                "#
+                .to_owned(),
+            ))
+            .await?;
+
+        for column in [Column::ValueBytes, Column::ValueJson, Column::ValueType] {
+            manager
+                .alter_table(Table::alter().table(Entity).drop_column(column).to_owned())
+                .await?;
+        }
+
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                "ALTER TABLE store ALTER COLUMN value TYPE varchar, ALTER COLUMN value SET NOT NULL"
+                    .to_owned(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+}
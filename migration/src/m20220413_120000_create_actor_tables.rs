@@ -0,0 +1,155 @@
+use entity::{actor_binding, actor_storage, user};
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220413_120000_create_actor_tables.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(actor_binding::Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(actor_binding::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_binding::Column::Name)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_binding::Column::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_binding::Column::ClassName)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_binding::Column::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(actor_binding::Entity, actor_binding::Column::UserId)
+                    .to(user::Entity, user::Column::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_actor_bindings_user_id_name")
+                    .table(actor_binding::Entity)
+                    .col(actor_binding::Column::UserId)
+                    .col(actor_binding::Column::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(actor_storage::Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(actor_storage::Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_storage::Column::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_storage::Column::ClassName)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_storage::Column::ActorId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_storage::Column::Key)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_storage::Column::Value)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(actor_storage::Column::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(actor_storage::Entity, actor_storage::Column::UserId)
+                    .to(user::Entity, user::Column::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        // writes upsert on this index, lists walk it in key order
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_actor_storage_actor_key")
+                    .table(actor_storage::Entity)
+                    .col(actor_storage::Column::UserId)
+                    .col(actor_storage::Column::ClassName)
+                    .col(actor_storage::Column::ActorId)
+                    .col(actor_storage::Column::Key)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(actor_storage::Entity).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(actor_binding::Entity).to_owned())
+            .await
+    }
+}
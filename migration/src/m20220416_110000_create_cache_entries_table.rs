@@ -0,0 +1,78 @@
+use entity::{cache_entry::*, user};
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220416_110000_create_cache_entries_table.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entity)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Column::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Column::UserId).integer().not_null())
+                    .col(ColumnDef::new(Column::CacheName).string().not_null())
+                    .col(ColumnDef::new(Column::Url).text().not_null())
+                    .col(ColumnDef::new(Column::Vary).json_binary().not_null())
+                    .col(ColumnDef::new(Column::Status).integer().not_null())
+                    .col(ColumnDef::new(Column::Headers).json_binary().not_null())
+                    .col(ColumnDef::new(Column::Body).binary().not_null())
+                    .col(
+                        ColumnDef::new(Column::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Column::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(Entity, Column::UserId)
+                    .to(user::Entity, user::Column::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        // every lookup is for all variants of a URL
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cache_entries_user_id_cache_name_url")
+                    .table(Entity)
+                    .col(Column::UserId)
+                    .col(Column::CacheName)
+                    .col(Column::Url)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Entity).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,38 @@
+use sea_schema::migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20220419_090000_use_c_collation_for_store_keys.rs"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // prefix `LIKE` can only use `idx_store_namespace_id_key` under the "C" collation,
+        // changing the column rebuilds the index. Keys are ordered by their bytes then, like
+        // in the memory backend
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"ALTER TABLE store ALTER COLUMN key TYPE varchar COLLATE "C""#.to_owned(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute(Statement::from_string(
+                manager.get_database_backend(),
+                r#"ALTER TABLE store ALTER COLUMN key TYPE varchar COLLATE "default""#.to_owned(),
+            ))
+            .await?;
+
+        Ok(())
+    }
+}
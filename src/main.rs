@@ -3,6 +3,7 @@
 #![warn(clippy::nursery)]
 #![allow(clippy::future_not_send)]
 
+use config::{Config, Server};
 use dotenv::dotenv;
 use migration::{sea_orm::Database, Migrator, MigratorTrait};
 
@@ -10,20 +11,25 @@ use migration::{sea_orm::Database, Migrator, MigratorTrait};
 async fn main() {
     dotenv().ok();
 
+    let config = match Config::load(&[Server::Api, Server::Workers]) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     {
-        let conn = Database::connect(
-            std::env::var("DATABASE_URL")
-                .expect("No DATABASE_URL environment variable found.")
-                .as_str(),
-        )
-        .await
-        .expect("Database connection failed");
+        let conn = Database::connect(config.database_url().unwrap())
+            .await
+            .expect("Database connection failed");
 
         Migrator::up(&conn, None).await.unwrap();
     }
 
-    let worker_server = tokio::spawn(async move { workers::run(None).await });
-    let api_server = tokio::spawn(async move { api::run().await });
+    let workers_config = config.clone();
+    let worker_server = tokio::spawn(async move { workers::run(workers_config, None).await });
+    let api_server = tokio::spawn(async move { api::run(config).await });
 
     let (_, _) = tokio::join!(worker_server, api_server);
 }
@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use migration::sea_orm::{
+    ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbBackend, Statement,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::{check_key, upsert_statement, KvError, Write};
+
+pub struct Check {
+    pub key: String,
+    /// `None` means the key must not exist
+    pub version: Option<i64>,
+}
+
+pub enum Mutation {
+    Set(Write),
+    Delete(String),
+    /// Adds to the integer stored at the key
+    Sum(String, i64),
+}
+
+impl Mutation {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Set(write) => write.key(),
+            Self::Delete(key) | Self::Sum(key, _) => key,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AtomicResult {
+    pub ok: bool,
+    /// Keys whose check failed, nothing was written if this isn't empty
+    pub conflicts: Vec<String>,
+}
+
+/// Runs every check and mutation in one transaction, either all mutations are applied or none
+///
+/// Checked rows are locked until the commit, keys that didn't exist while checking are only
+/// inserted if no concurrent writer created them in the meantime
+///
+/// # Errors
+///
+/// Will return `Err` if a database query fails or a sum is applied to a non integer value
+pub async fn atomic(
+    conn: &DatabaseConnection,
+    namespace_id: i32,
+    checks: Vec<Check>,
+    mutations: Vec<Mutation>,
+) -> Result<AtomicResult> {
+    let txn = conn.begin().await?;
+
+    let mut missing: HashSet<String> = HashSet::new();
+    let mut conflicts: Vec<String> = vec![];
+    for check in checks {
+        let current = match lock_version(&txn, namespace_id, &check.key).await? {
+            Some(version) => version,
+            None => {
+                missing.insert(check.key.clone());
+                None
+            }
+        };
+
+        if current != check.version {
+            conflicts.push(check.key);
+        }
+    }
+
+    if !conflicts.is_empty() {
+        txn.rollback().await?;
+        return Ok(AtomicResult {
+            ok: false,
+            conflicts,
+        });
+    }
+
+    for mutation in mutations {
+        let key = mutation.key().to_string();
+        let insert_only = missing.remove(&key);
+        let applied = match mutation {
+            Mutation::Set(write) => {
+                let statement = upsert_statement(namespace_id, write, insert_only);
+                txn.execute(statement).await?.rows_affected() > 0
+            }
+            Mutation::Delete(key) => {
+                txn.execute(Statement::from_sql_and_values(
+                    DbBackend::Postgres,
+                    "DELETE FROM store WHERE namespace_id = $1 AND key = $2",
+                    vec![namespace_id.into(), key.into()],
+                ))
+                .await?;
+
+                true
+            }
+            Mutation::Sum(key, delta) => {
+                check_key(&key)?;
+                sum(&txn, namespace_id, &key, delta, insert_only).await?
+            }
+        };
+
+        if !applied {
+            conflicts.push(key);
+        }
+    }
+
+    if !conflicts.is_empty() {
+        txn.rollback().await?;
+        return Ok(AtomicResult {
+            ok: false,
+            conflicts,
+        });
+    }
+
+    txn.commit().await?;
+
+    Ok(AtomicResult {
+        ok: true,
+        conflicts,
+    })
+}
+
+/// Locks the row of a key and returns its version, the inner value is `None`
+/// if the row exists but already expired
+async fn lock_version(
+    txn: &DatabaseTransaction,
+    namespace_id: i32,
+    key: &str,
+) -> Result<Option<Option<i64>>> {
+    let row = txn
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            SELECT CASE WHEN expires_at <= now() THEN NULL ELSE version END AS version
+            FROM store
+            WHERE namespace_id = $1 AND key = $2
+            FOR UPDATE
+            "#,
+            vec![namespace_id.into(), key.into()],
+        ))
+        .await?;
+
+    match row {
+        Some(row) => Ok(Some(row.try_get::<Option<i64>>("", "version")?)),
+        None => Ok(None),
+    }
+}
+
+fn not_an_integer() -> KvError {
+    KvError::new("sum can only be applied to integer values".into())
+}
+
+/// Adds to the integer stored at a key, missing and expired keys count as 0. The sum is
+/// computed here like the memory backend does, so failed queries are never mistaken
+/// for values that aren't integers. Returns whether the sum was stored
+async fn sum(
+    txn: &DatabaseTransaction,
+    namespace_id: i32,
+    key: &str,
+    delta: i64,
+    insert_only: bool,
+) -> Result<bool> {
+    loop {
+        let total = match lock_integer(txn, namespace_id, key).await? {
+            // a concurrent writer created the key after it was checked as missing
+            Some(_) if insert_only => return Ok(false),
+            Some(current) => current.checked_add(delta).ok_or_else(not_an_integer)?,
+            None => {
+                let inserted = txn
+                    .execute(Statement::from_sql_and_values(
+                        DbBackend::Postgres,
+                        r#"
+                        INSERT INTO store (key, value_type, value_json, namespace_id, created_at)
+                        VALUES ($1, 'json', to_jsonb($2::bigint), $3, now())
+                        ON CONFLICT (namespace_id, key) DO NOTHING
+                        "#,
+                        vec![key.into(), delta.into(), namespace_id.into()],
+                    ))
+                    .await
+                    .context("Failed to insert the sum")?
+                    .rows_affected()
+                    > 0;
+
+                // otherwise the key was created concurrently, its row is locked on the next try
+                if inserted || insert_only {
+                    return Ok(inserted);
+                }
+                continue;
+            }
+        };
+
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            UPDATE store SET
+                value_type = 'json',
+                value = NULL,
+                value_json = to_jsonb($3::bigint),
+                value_bytes = NULL,
+                expires_at = CASE WHEN expires_at <= now() THEN NULL ELSE expires_at END,
+                version = nextval('store_version_seq')
+            WHERE namespace_id = $1 AND key = $2
+            "#,
+            vec![namespace_id.into(), key.into(), total.into()],
+        ))
+        .await
+        .context("Failed to store the sum")?;
+
+        return Ok(true);
+    }
+}
+
+/// Locks the row of a key and reads its integer, expired rows count as 0
+///
+/// Will return a [`KvError`] if the value isn't an integer
+async fn lock_integer(
+    txn: &DatabaseTransaction,
+    namespace_id: i32,
+    key: &str,
+) -> Result<Option<i64>> {
+    let row = txn
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            SELECT value_type, value, value_json, COALESCE(expires_at <= now(), false) AS expired
+            FROM store
+            WHERE namespace_id = $1 AND key = $2
+            FOR UPDATE
+            "#,
+            vec![namespace_id.into(), key.into()],
+        ))
+        .await
+        .context("Failed to read the value to sum")?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if row.try_get::<bool>("", "expired")? {
+        return Ok(Some(0));
+    }
+
+    let value = match row.try_get::<String>("", "value_type")?.as_str() {
+        "text" => row
+            .try_get::<Option<String>>("", "value")?
+            .and_then(|text| text.parse::<i64>().ok()),
+        "json" => row
+            .try_get::<Option<serde_json::Value>>("", "value_json")?
+            .and_then(|json| json.as_i64()),
+        _ => None,
+    };
+
+    Ok(Some(value.ok_or_else(not_an_integer)?))
+}
@@ -0,0 +1,248 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use migration::sea_orm::prelude::DateTimeWithTimeZone;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use super::KvBackend;
+use crate::{
+    check_key, decode_cursor, encode_cursor, list_limit, AtomicResult, Check, Item, KvError,
+    ListOptions, ListPage, Mutation, Value, Write,
+};
+
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Value,
+    metadata: Option<serde_json::Value>,
+    expires_at: Option<DateTimeWithTimeZone>,
+    version: i64,
+}
+
+impl Entry {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| expires_at <= chrono::Utc::now())
+    }
+
+    fn into_item(self, key: String) -> Item {
+        Item {
+            key,
+            value: self.value,
+            metadata: self.metadata,
+            expires_at: self.expires_at,
+            version: self.version,
+        }
+    }
+}
+
+type Namespace = BTreeMap<String, Entry>;
+
+#[derive(Debug, Default)]
+struct State {
+    namespaces: HashMap<i32, Namespace>,
+    /// Shared by all namespaces like `store_version_seq`
+    version: i64,
+}
+
+impl State {
+    fn next_version(&mut self) -> i64 {
+        self.version += 1;
+        self.version
+    }
+}
+
+/// Keeps items in the memory of the process, for local development and tests
+/// that shouldn't need a database. Expired items are dropped when they are read
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    state: Mutex<State>,
+}
+
+impl MemoryBackend {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // a panic while holding the lock can't leave a namespace half written,
+        // every write replaces whole entries
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+fn write_entry(namespace: &mut Namespace, write: Write, version: i64) {
+    namespace.insert(
+        write.key,
+        Entry {
+            value: write.value,
+            metadata: write.metadata,
+            expires_at: write.expires_at,
+            version,
+        },
+    );
+}
+
+/// Missing and expired keys count as 0, binary values can't be summed
+fn sum(namespace: &mut Namespace, key: String, delta: i64, version: i64) -> Result<()> {
+    check_key(&key)?;
+    let not_an_integer = || KvError::new("sum can only be applied to integer values".into());
+
+    let current = namespace.get(&key).filter(|entry| !entry.is_expired());
+    let value = match current.map(|entry| &entry.value) {
+        None => 0,
+        Some(Value::Text(text)) => text.parse::<i64>().map_err(|_| not_an_integer())?,
+        Some(Value::Json(json)) => json.as_i64().ok_or_else(not_an_integer)?,
+        Some(Value::Bytes(_)) => return Err(not_an_integer().into()),
+    };
+    let total = value.checked_add(delta).ok_or_else(not_an_integer)?;
+
+    let (metadata, expires_at) = current.map_or((None, None), |entry| {
+        (entry.metadata.clone(), entry.expires_at)
+    });
+    namespace.insert(
+        key,
+        Entry {
+            value: Value::Json(total.into()),
+            metadata,
+            expires_at,
+            version,
+        },
+    );
+
+    Ok(())
+}
+
+#[async_trait]
+impl KvBackend for MemoryBackend {
+    async fn get(&self, namespace_id: i32, key: &str) -> Result<Option<Item>> {
+        let mut state = self.lock();
+        let namespace = match state.namespaces.get_mut(&namespace_id) {
+            Some(namespace) => namespace,
+            None => return Ok(None),
+        };
+
+        if namespace.get(key).map_or(false, Entry::is_expired) {
+            namespace.remove(key);
+        }
+
+        Ok(namespace
+            .get(key)
+            .map(|entry| entry.clone().into_item(key.to_string())))
+    }
+
+    async fn put_many(&self, namespace_id: i32, writes: Vec<Write>) -> Result<()> {
+        let mut state = self.lock();
+        for write in writes {
+            let version = state.next_version();
+            let namespace = state.namespaces.entry(namespace_id).or_default();
+            write_entry(namespace, write, version);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_many(&self, namespace_id: i32, keys: Vec<String>) -> Result<()> {
+        if let Some(namespace) = self.lock().namespaces.get_mut(&namespace_id) {
+            for key in keys {
+                namespace.remove(&key);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn clear(&self, namespace_id: i32) -> Result<()> {
+        self.lock().namespaces.remove(&namespace_id);
+
+        Ok(())
+    }
+
+    async fn list(&self, namespace_id: i32, options: &ListOptions) -> Result<ListPage> {
+        let limit = usize::try_from(list_limit(options)?)?;
+        let after = options.cursor.as_deref().map(decode_cursor).transpose()?;
+
+        let mut state = self.lock();
+        let namespace = state.namespaces.entry(namespace_id).or_default();
+        namespace.retain(|_, entry| !entry.is_expired());
+
+        // one extra item tells us whether there is another page
+        let mut items: Vec<Item> = namespace
+            .iter()
+            .filter(|(key, _)| after.as_ref().map_or(true, |after| *key > after))
+            .filter(|(key, _)| {
+                options
+                    .prefix
+                    .as_ref()
+                    .map_or(true, |prefix| key.starts_with(prefix.as_str()))
+            })
+            .take(limit + 1)
+            .map(|(key, entry)| entry.clone().into_item(key.clone()))
+            .collect();
+
+        let list_complete = items.len() <= limit;
+        items.truncate(limit);
+
+        let cursor = if list_complete {
+            None
+        } else {
+            items.last().map(|item| encode_cursor(&item.key))
+        };
+
+        Ok(ListPage {
+            items,
+            list_complete,
+            cursor,
+        })
+    }
+
+    async fn atomic(
+        &self,
+        namespace_id: i32,
+        checks: Vec<Check>,
+        mutations: Vec<Mutation>,
+    ) -> Result<AtomicResult> {
+        let mut state = self.lock();
+        let current = state
+            .namespaces
+            .get(&namespace_id)
+            .cloned()
+            .unwrap_or_default();
+
+        let conflicts: Vec<String> = checks
+            .into_iter()
+            .filter(|check| {
+                let version = current
+                    .get(&check.key)
+                    .filter(|entry| !entry.is_expired())
+                    .map(|entry| entry.version);
+
+                version != check.version
+            })
+            .map(|check| check.key)
+            .collect();
+
+        if !conflicts.is_empty() {
+            return Ok(AtomicResult {
+                ok: false,
+                conflicts,
+            });
+        }
+
+        // mutations are applied to a copy, a failing sum leaves the namespace untouched
+        let mut namespace = current;
+        for mutation in mutations {
+            let version = state.next_version();
+            match mutation {
+                Mutation::Set(write) => write_entry(&mut namespace, write, version),
+                Mutation::Delete(key) => {
+                    namespace.remove(&key);
+                }
+                Mutation::Sum(key, delta) => sum(&mut namespace, key, delta, version)?,
+            }
+        }
+        state.namespaces.insert(namespace_id, namespace);
+
+        Ok(AtomicResult {
+            ok: true,
+            conflicts,
+        })
+    }
+}
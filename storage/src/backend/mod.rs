@@ -0,0 +1,60 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use config::KvBackendKind;
+use migration::sea_orm::DatabaseConnection;
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+
+use crate::{AtomicResult, Check, Item, ListOptions, ListPage, Mutation, Write};
+
+mod memory;
+mod postgres;
+
+pub use memory::MemoryBackend;
+pub use postgres::PostgresBackend;
+
+/// Where the items of KV namespaces are kept, every backend enforces the same
+/// limits, expiry and versioning rules
+#[async_trait]
+pub trait KvBackend: Send + Sync {
+    /// Expired items are never returned
+    async fn get(&self, namespace_id: i32, key: &str) -> Result<Option<Item>>;
+
+    async fn put(&self, namespace_id: i32, write: Write) -> Result<()> {
+        self.put_many(namespace_id, vec![write]).await
+    }
+
+    /// Either all writes are applied or none
+    async fn put_many(&self, namespace_id: i32, writes: Vec<Write>) -> Result<()>;
+
+    async fn delete(&self, namespace_id: i32, key: &str) -> Result<()> {
+        self.delete_many(namespace_id, vec![key.to_string()]).await
+    }
+
+    async fn delete_many(&self, namespace_id: i32, keys: Vec<String>) -> Result<()>;
+
+    async fn clear(&self, namespace_id: i32) -> Result<()>;
+
+    /// Lists keys ordered by name, one page at a time
+    async fn list(&self, namespace_id: i32, options: &ListOptions) -> Result<ListPage>;
+
+    /// Either all mutations are applied or none, see [`crate::atomic`]
+    async fn atomic(
+        &self,
+        namespace_id: i32,
+        checks: Vec<Check>,
+        mutations: Vec<Mutation>,
+    ) -> Result<AtomicResult>;
+}
+
+/// Shared by every isolate of the process, so all of them see the same items
+static MEMORY: Lazy<Arc<MemoryBackend>> = Lazy::new(Arc::default);
+
+/// The configured backend, the memory backend loses every item when the process exits
+#[must_use]
+pub fn backend(kind: KvBackendKind, conn: DatabaseConnection) -> Arc<dyn KvBackend> {
+    match kind {
+        KvBackendKind::Postgres => Arc::new(PostgresBackend::new(conn)),
+        KvBackendKind::Memory => MEMORY.clone(),
+    }
+}
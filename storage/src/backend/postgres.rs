@@ -0,0 +1,54 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use migration::sea_orm::DatabaseConnection;
+
+use super::KvBackend;
+use crate::{AtomicResult, Check, Item, ListOptions, ListPage, Mutation, Write};
+
+/// Keeps items in the `store` table, expired rows are removed by the sweeper of the workers
+#[derive(Debug, Clone)]
+pub struct PostgresBackend {
+    conn: DatabaseConnection,
+}
+
+impl PostgresBackend {
+    pub const fn new(conn: DatabaseConnection) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait]
+impl KvBackend for PostgresBackend {
+    async fn get(&self, namespace_id: i32, key: &str) -> Result<Option<Item>> {
+        crate::get(&self.conn, namespace_id, key).await
+    }
+
+    async fn put(&self, namespace_id: i32, write: Write) -> Result<()> {
+        crate::put(&self.conn, namespace_id, write).await
+    }
+
+    async fn put_many(&self, namespace_id: i32, writes: Vec<Write>) -> Result<()> {
+        crate::put_many(&self.conn, namespace_id, writes).await
+    }
+
+    async fn delete_many(&self, namespace_id: i32, keys: Vec<String>) -> Result<()> {
+        crate::delete_many(&self.conn, namespace_id, keys).await
+    }
+
+    async fn clear(&self, namespace_id: i32) -> Result<()> {
+        crate::clear(&self.conn, namespace_id).await
+    }
+
+    async fn list(&self, namespace_id: i32, options: &ListOptions) -> Result<ListPage> {
+        crate::list(&self.conn, namespace_id, options).await
+    }
+
+    async fn atomic(
+        &self,
+        namespace_id: i32,
+        checks: Vec<Check>,
+        mutations: Vec<Mutation>,
+    ) -> Result<AtomicResult> {
+        crate::atomic(&self.conn, namespace_id, checks, mutations).await
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt;
+
+/// A rejected KV operation, e.g. a write that is too big. Callers can downcast to it to
+/// tell these apart from database errors
+#[derive(Debug)]
+pub struct KvError(String);
+
+impl KvError {
+    pub const fn new(message: String) -> Self {
+        Self(message)
+    }
+
+    pub fn too_big(what: &str, size: usize, max: usize) -> Self {
+        Self(format!(
+            "The {} is {} bytes, the maximum is {} bytes",
+            what, size, max
+        ))
+    }
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for KvError {}
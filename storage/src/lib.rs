@@ -0,0 +1,424 @@
+#![deny(clippy::all)]
+#![warn(clippy::pedantic)]
+#![warn(clippy::nursery)]
+use anyhow::{Context, Result};
+use chrono::TimeZone;
+use entity::store;
+use migration::sea_orm::prelude::DateTimeWithTimeZone;
+use migration::sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Statement,
+};
+use serde::Serialize;
+
+mod atomic;
+mod backend;
+mod error;
+pub mod sql;
+mod transfer;
+mod value;
+
+pub use atomic::{atomic, AtomicResult, Check, Mutation};
+pub use backend::{backend, KvBackend, MemoryBackend, PostgresBackend};
+pub use error::KvError;
+pub use transfer::{export_page, ImportSummary, Importer, Record, MAX_IMPORT_LINE_BYTES};
+pub use value::Value;
+
+/// Keys are limited to 512 bytes, values to 25 MiB and metadata to 1 KiB
+pub const MAX_KEY_BYTES: usize = 512;
+pub const MAX_VALUE_BYTES: usize = 25 * 1024 * 1024;
+pub const MAX_METADATA_BYTES: usize = 1024;
+/// Maximum and default amount of keys returned by a single `list` call
+pub const MAX_LIST_LIMIT: u64 = 1000;
+/// Keys expire at most 10 years from now
+pub const MAX_TTL_SECONDS: i64 = 10 * 365 * 24 * 60 * 60;
+
+/// A stored key, expired keys are never returned
+#[derive(Debug, Clone, Serialize)]
+pub struct Item {
+    pub key: String,
+    pub value: Value,
+    pub metadata: Option<serde_json::Value>,
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    /// Changes on every write of the key
+    pub version: i64,
+}
+
+impl TryFrom<store::Model> for Item {
+    type Error = anyhow::Error;
+
+    fn try_from(item: store::Model) -> Result<Self> {
+        Ok(Self {
+            value: Value::from_columns(
+                &item.value_type,
+                item.value,
+                item.value_json,
+                item.value_bytes,
+            )?,
+            key: item.key,
+            metadata: item.metadata,
+            expires_at: item.expires_at,
+            version: item.version,
+        })
+    }
+}
+
+/// A write whose size limits have been checked
+pub struct Write {
+    key: String,
+    value: Value,
+    metadata: Option<serde_json::Value>,
+    expires_at: Option<DateTimeWithTimeZone>,
+}
+
+impl Write {
+    /// # Errors
+    ///
+    /// Will return a [`KvError`] if the key, value or metadata is too big
+    pub fn new(
+        key: String,
+        value: Value,
+        metadata: Option<serde_json::Value>,
+        expires_at: Option<DateTimeWithTimeZone>,
+    ) -> Result<Self> {
+        check_key(&key)?;
+
+        if value.size() > MAX_VALUE_BYTES {
+            return Err(KvError::too_big("value", value.size(), MAX_VALUE_BYTES).into());
+        }
+
+        let metadata_bytes = metadata
+            .as_ref()
+            .map_or(0, |metadata| metadata.to_string().len());
+        if metadata_bytes > MAX_METADATA_BYTES {
+            return Err(KvError::too_big("metadata", metadata_bytes, MAX_METADATA_BYTES).into());
+        }
+
+        Ok(Self {
+            key,
+            value,
+            metadata,
+            expires_at,
+        })
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+/// Turns a TTL in seconds or a UNIX timestamp into the expiry of a key
+///
+/// # Errors
+///
+/// Will return a [`KvError`] if both are set, the expiry isn't in the future or it's further
+/// away than [`MAX_TTL_SECONDS`]
+pub fn expires_at(
+    expiration_ttl: Option<i64>,
+    expiration: Option<i64>,
+) -> Result<Option<DateTimeWithTimeZone>> {
+    let now = chrono::Utc::now();
+    let expires_at = match (expiration_ttl, expiration) {
+        (Some(_), Some(_)) => {
+            return Err(
+                KvError::new("Only one of expirationTtl and expiration can be set".into()).into(),
+            )
+        }
+        // `Duration::seconds` panics far below `i64::MAX`, so the range is checked first
+        (Some(ttl), None) if ttl <= 0 => Some(now),
+        (Some(ttl), None) if ttl <= MAX_TTL_SECONDS => {
+            now.checked_add_signed(chrono::Duration::seconds(ttl))
+        }
+        (Some(_), None) => None,
+        (None, Some(expiration)) => chrono::Utc.timestamp_opt(expiration, 0).single(),
+        (None, None) => return Ok(None),
+    };
+
+    let expires_at = expires_at
+        .filter(|expires_at| (*expires_at - now).num_seconds() <= MAX_TTL_SECONDS)
+        .ok_or_else(|| {
+            KvError::new(format!(
+                "The expiration of a key can be at most {} seconds in the future",
+                MAX_TTL_SECONDS
+            ))
+        })?;
+    if expires_at <= now {
+        return Err(KvError::new("The expiration of a key has to be in the future".into()).into());
+    }
+
+    Ok(Some(expires_at.into()))
+}
+
+pub(crate) fn check_key(key: &str) -> Result<()> {
+    if key.is_empty() {
+        return Err(KvError::new("Keys can't be empty".into()).into());
+    }
+
+    if key.len() > MAX_KEY_BYTES {
+        return Err(KvError::too_big("key", key.len(), MAX_KEY_BYTES).into());
+    }
+
+    Ok(())
+}
+
+/// # Errors
+///
+/// Will return `Err` if the database query fails
+pub async fn get(conn: &DatabaseConnection, namespace_id: i32, key: &str) -> Result<Option<Item>> {
+    let item = store::Entity::find()
+        .filter(store::Column::Key.eq(key))
+        .filter(store::Column::NamespaceId.eq(namespace_id))
+        .filter(not_expired())
+        .one(conn)
+        .await
+        .context("Failed to get store item from database")?;
+
+    item.map(Item::try_from).transpose()
+}
+
+/// Inserts or overwrites a key in a single statement, the unique index on
+/// `(namespace_id, key)` makes concurrent writers for the same key safe
+///
+/// # Errors
+///
+/// Will return `Err` if the database query fails
+pub async fn put(conn: &DatabaseConnection, namespace_id: i32, write: Write) -> Result<()> {
+    conn.execute(upsert_statement(namespace_id, write, false))
+        .await
+        .context("Failed to write store item to database")?;
+
+    Ok(())
+}
+
+/// Writes all keys in one transaction
+///
+/// # Errors
+///
+/// Will return `Err` if the database query fails, nothing is written in that case
+pub async fn put_many(
+    conn: &DatabaseConnection,
+    namespace_id: i32,
+    writes: Vec<Write>,
+) -> Result<()> {
+    let txn = conn.begin().await?;
+    for write in writes {
+        txn.execute(upsert_statement(namespace_id, write, false))
+            .await
+            .context("Failed to write store item to database")?;
+    }
+    txn.commit().await?;
+
+    Ok(())
+}
+
+/// # Errors
+///
+/// Will return `Err` if the database query fails
+pub async fn delete(conn: &DatabaseConnection, namespace_id: i32, key: &str) -> Result<()> {
+    delete_many(conn, namespace_id, vec![key.to_string()]).await
+}
+
+/// # Errors
+///
+/// Will return `Err` if the database query fails
+pub async fn delete_many(
+    conn: &DatabaseConnection,
+    namespace_id: i32,
+    keys: Vec<String>,
+) -> Result<()> {
+    store::Entity::delete_many()
+        .filter(store::Column::NamespaceId.eq(namespace_id))
+        .filter(store::Column::Key.is_in(keys))
+        .exec(conn)
+        .await
+        .context("Failed to delete store items from database")?;
+
+    Ok(())
+}
+
+/// # Errors
+///
+/// Will return `Err` if the database query fails
+pub async fn clear(conn: &DatabaseConnection, namespace_id: i32) -> Result<()> {
+    store::Entity::delete_many()
+        .filter(store::Column::NamespaceId.eq(namespace_id))
+        .exec(conn)
+        .await
+        .context("Failed to delete store items from database")?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct ListOptions {
+    pub prefix: Option<String>,
+    /// Between 1 and [`MAX_LIST_LIMIT`], defaults to the maximum
+    pub limit: Option<u64>,
+    /// The cursor of the previous page
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ListPage {
+    pub items: Vec<Item>,
+    pub list_complete: bool,
+    /// Only set if there is another page
+    pub cursor: Option<String>,
+}
+
+/// Lists keys ordered by name, one page at a time
+///
+/// # Errors
+///
+/// Will return `Err` if the options are invalid or the database query fails
+pub async fn list(
+    conn: &DatabaseConnection,
+    namespace_id: i32,
+    options: &ListOptions,
+) -> Result<ListPage> {
+    let limit = list_limit(options)?;
+    let mut query = store::Entity::find()
+        .filter(store::Column::NamespaceId.eq(namespace_id))
+        .filter(not_expired());
+
+    if let Some(prefix) = &options.prefix {
+        query = query.filter(store::Column::Key.like(&format!("{}%", escape_like(prefix))));
+    }
+
+    if let Some(cursor) = &options.cursor {
+        query = query.filter(store::Column::Key.gt(decode_cursor(cursor)?));
+    }
+
+    // one extra row tells us whether there is another page
+    let mut items = query
+        .order_by_asc(store::Column::Key)
+        .limit(limit + 1)
+        .all(conn)
+        .await
+        .context("Failed to get store items from database")?;
+
+    let list_complete = items.len() as u64 <= limit;
+    items.truncate(usize::try_from(limit)?);
+
+    let cursor = if list_complete {
+        None
+    } else {
+        items.last().map(|item| encode_cursor(&item.key))
+    };
+
+    Ok(ListPage {
+        items: items
+            .into_iter()
+            .map(Item::try_from)
+            .collect::<Result<_>>()?,
+        list_complete,
+        cursor,
+    })
+}
+
+pub(crate) fn list_limit(options: &ListOptions) -> Result<u64> {
+    let limit = options.limit.unwrap_or(MAX_LIST_LIMIT);
+    if limit == 0 || limit > MAX_LIST_LIMIT {
+        return Err(KvError::new(format!(
+            "The limit of a list has to be between 1 and {}",
+            MAX_LIST_LIMIT
+        ))
+        .into());
+    }
+
+    Ok(limit)
+}
+
+/// Every write takes a new version from a shared sequence, so a key that is deleted
+/// and written again never repeats a versionstamp
+///
+/// With `insert_only` an existing key is left untouched and no row is affected
+pub(crate) fn upsert_statement(namespace_id: i32, write: Write, insert_only: bool) -> Statement {
+    let on_conflict = if insert_only {
+        "DO NOTHING"
+    } else {
+        r#"
+        DO UPDATE SET
+            value_type = EXCLUDED.value_type,
+            value = EXCLUDED.value,
+            value_json = EXCLUDED.value_json,
+            value_bytes = EXCLUDED.value_bytes,
+            metadata = EXCLUDED.metadata,
+            expires_at = EXCLUDED.expires_at,
+            version = nextval('store_version_seq')
+        "#
+    };
+
+    let kind = write.value.kind();
+    let (text, json, bytes) = match write.value {
+        Value::Text(text) => (Some(text), None, None),
+        Value::Json(json) => (None, Some(json.to_string()), None),
+        Value::Bytes(bytes) => (None, None, Some(bytes)),
+    };
+
+    Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        &format!(
+            r#"
+            INSERT INTO store (key, value_type, value, value_json, value_bytes, metadata, namespace_id, created_at, expires_at)
+            VALUES ($1, $2, $3, $4::jsonb, $5, $6::jsonb, $7, now(), $8)
+            ON CONFLICT (namespace_id, key) {}
+            "#,
+            on_conflict
+        ),
+        vec![
+            write.key.into(),
+            kind.into(),
+            text.into(),
+            json.into(),
+            bytes.into(),
+            write.metadata.map(|metadata| metadata.to_string()).into(),
+            namespace_id.into(),
+            write.expires_at.into(),
+        ],
+    )
+}
+
+/// `LIKE` treats `%` and `_` as wildcards, they have to be escaped to match literally
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        if matches!(char, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+
+    escaped
+}
+
+/// Cursors are the hex encoded last key of the previous page, opaque to callers
+pub(crate) fn encode_cursor(key: &str) -> String {
+    key.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn decode_cursor(cursor: &str) -> Result<String> {
+    let invalid = || KvError::new("Invalid list cursor".into());
+    if cursor.len() % 2 != 0 {
+        return Err(invalid().into());
+    }
+
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|index| {
+            cursor
+                .get(index..index + 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect::<Result<Vec<u8>, KvError>>()?;
+
+    String::from_utf8(bytes).map_err(|_| anyhow::Error::from(invalid()))
+}
+
+/// Expired items stay in the table until the sweeper removes them, reads have to skip them
+fn not_expired() -> Condition {
+    Condition::any()
+        .add(store::Column::ExpiresAt.is_null())
+        .add(store::Column::ExpiresAt.gt(chrono::Utc::now()))
+}
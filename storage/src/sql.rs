@@ -0,0 +1,459 @@
+//! Relational databases of apps. Every app gets a Postgres schema and a role of the same name.
+//! Statements of the app run on connections that log in as that role, so there is no way back
+//! to the role of the server
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use config::DbConfig;
+use entity::{app_database, database_migration};
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, QueryFilter,
+    QueryOrder, Statement,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use sqlx::postgres::{PgArguments, PgConnectOptions, PgPool, PgPoolOptions};
+use sqlx::{Postgres, Row, Transaction};
+
+/// Connections an isolate opens to the database of its app
+const MAX_CONNECTIONS: u32 = 2;
+
+/// A statement of an app that was rejected, e.g. because it is invalid or exceeded a limit.
+/// Callers can downcast to it to tell these apart from errors of our own queries
+#[derive(Debug)]
+pub struct SqlError(String);
+
+impl SqlError {
+    #[must_use]
+    pub const fn new(message: String) -> Self {
+        Self(message)
+    }
+}
+
+impl fmt::Display for SqlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SqlError {}
+
+/// Bounds every statement an app runs
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Postgres cancels statements that run longer
+    pub statement_timeout_ms: u64,
+    /// Queries returning more rows fail instead of returning a partial result
+    pub max_rows: usize,
+}
+
+impl From<DbConfig> for Limits {
+    fn from(config: DbConfig) -> Self {
+        Self {
+            statement_timeout_ms: config.statement_timeout_ms,
+            max_rows: config.max_rows,
+        }
+    }
+}
+
+/// The name of both the schema and the role of an app
+#[must_use]
+pub fn schema_name(user_id: i32) -> String {
+    format!("app_{}", user_id)
+}
+
+/// # Errors
+///
+/// Will return `Err` if the database query fails
+pub async fn exists(conn: &DatabaseConnection, user_id: i32) -> Result<bool> {
+    let row = conn
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT EXISTS (SELECT 1 FROM pg_namespace WHERE nspname = $1) AS exists",
+            vec![schema_name(user_id).into()],
+        ))
+        .await?
+        .context("Failed to check for schema")?;
+
+    Ok(row.try_get("", "exists")?)
+}
+
+/// Creates the role and schema of the app, the user of `conn` needs the `CREATEROLE` privilege.
+/// Roles of schemas created before apps logged in on their own are given a password
+///
+/// # Errors
+///
+/// Will return `Err` if the database query fails, nothing is created in that case
+pub async fn create(conn: &DatabaseConnection, user_id: i32) -> Result<()> {
+    let exists = exists(conn, user_id).await?;
+    if exists && credentials(conn, user_id).await?.is_some() {
+        return Ok(());
+    }
+
+    // the name only contains digits besides the prefix and the password is alphanumeric, so
+    // both can be formatted into statements
+    let schema = schema_name(user_id);
+    let password: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let statements = if exists {
+        vec![format!(
+            "ALTER ROLE {} LOGIN PASSWORD '{}'",
+            schema, password
+        )]
+    } else {
+        vec![
+            format!(
+                "CREATE ROLE {} LOGIN NOINHERIT CONNECTION LIMIT 20 PASSWORD '{}'",
+                schema, password
+            ),
+            // makes us a member of the role, to create the schema for it and drop what it
+            // owns. The role itself isn't a member of anything
+            format!("GRANT {} TO CURRENT_USER", schema),
+            format!("CREATE SCHEMA {} AUTHORIZATION {}", schema, schema),
+        ]
+    };
+
+    let txn = conn.begin().await?;
+    for sql in statements {
+        txn.execute(Statement::from_string(DbBackend::Postgres, sql))
+            .await
+            .context("Failed to create schema")?;
+    }
+    app_database::Entity::delete_many()
+        .filter(app_database::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    app_database::Entity::insert(app_database::ActiveModel {
+        user_id: Set(user_id),
+        password: Set(password),
+        created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+        ..app_database::ActiveModel::default()
+    })
+    .exec(&txn)
+    .await
+    .context("Failed to write database credentials")?;
+    txn.commit().await?;
+
+    Ok(())
+}
+
+async fn credentials(
+    conn: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Option<app_database::Model>> {
+    app_database::Entity::find()
+        .filter(app_database::Column::UserId.eq(user_id))
+        .one(conn)
+        .await
+        .context("Failed to get database credentials")
+}
+
+/// Connections to the schema of an app that log in as its role
+#[derive(Debug, Clone)]
+pub struct AppDatabase {
+    user_id: i32,
+    pool: PgPool,
+}
+
+/// Connects to the database of `database_url` as the role of the app, the credentials are read
+/// through `conn`
+///
+/// # Errors
+///
+/// Will return `Err` with a [`SqlError`] if the app has no database, or if the database can't
+/// be reached
+pub async fn connect(
+    conn: &DatabaseConnection,
+    database_url: &str,
+    user_id: i32,
+) -> Result<AppDatabase> {
+    let credentials = credentials(conn, user_id).await?.ok_or_else(|| {
+        SqlError::new(
+            "No database exists for this app, create it through the API first".to_string(),
+        )
+    })?;
+
+    let options = PgConnectOptions::from_str(database_url)
+        .context("Invalid database URL")?
+        .username(&schema_name(user_id))
+        .password(&credentials.password);
+    let pool = PgPoolOptions::new()
+        .max_connections(MAX_CONNECTIONS)
+        .connect_with(options)
+        .await
+        .context("Failed to connect to the database of the app")?;
+
+    Ok(AppDatabase { user_id, pool })
+}
+
+/// Drops the schema and role of the app with everything they own, and forgets its migrations
+/// and credentials
+///
+/// # Errors
+///
+/// Will return `Err` if the database query fails, nothing is dropped in that case
+pub async fn delete(conn: &DatabaseConnection, user_id: i32) -> Result<()> {
+    if !exists(conn, user_id).await? {
+        return Ok(());
+    }
+
+    let schema = schema_name(user_id);
+    let txn = conn.begin().await?;
+    for sql in [
+        format!("DROP OWNED BY {} CASCADE", schema),
+        format!("DROP ROLE {}", schema),
+    ] {
+        txn.execute(Statement::from_string(DbBackend::Postgres, sql))
+            .await
+            .context("Failed to drop schema")?;
+    }
+    database_migration::Entity::delete_many()
+        .filter(database_migration::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await
+        .context("Failed to delete migrations from database")?;
+    app_database::Entity::delete_many()
+        .filter(app_database::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await
+        .context("Failed to delete database credentials")?;
+    txn.commit().await?;
+
+    Ok(())
+}
+
+/// The migrations applied to the schema of the app, in the order they were applied
+///
+/// # Errors
+///
+/// Will return `Err` if the database query fails
+pub async fn migrations(
+    conn: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<database_migration::Model>> {
+    database_migration::Entity::find()
+        .filter(database_migration::Column::UserId.eq(user_id))
+        .order_by_asc(database_migration::Column::Id)
+        .all(conn)
+        .await
+        .context("Failed to get migrations from database")
+}
+
+/// Runs the statements of a migration as the app and records it under `name`. The statements
+/// run in one transaction, which is only committed once the migration was recorded. Statements
+/// are sent one at a time, Postgres doesn't prepare several at once
+///
+/// # Errors
+///
+/// Will return `Err` with a [`SqlError`] if a statement fails, nothing is applied in that case
+pub async fn migrate(
+    conn: &DatabaseConnection,
+    db: &AppDatabase,
+    name: &str,
+    statements: &[String],
+    limits: Limits,
+) -> Result<()> {
+    let mut txn = begin(db, limits).await?;
+    for (index, sql) in statements.iter().enumerate() {
+        sqlx::query(&trim(sql))
+            .execute(&mut txn)
+            .await
+            .map_err(|err| {
+                statement_error(err)
+                    .context(format!("Statement {} of the migration failed", index + 1))
+            })?;
+    }
+
+    // the connection of the app can't write to our tables, a failed insert rolls back the
+    // migration when `txn` is dropped
+    let migration = database_migration::Entity::insert(database_migration::ActiveModel {
+        user_id: Set(db.user_id),
+        name: Set(name.to_string()),
+        created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+        ..database_migration::ActiveModel::default()
+    })
+    .exec(conn)
+    .await
+    .context("Failed to write migration to database")?;
+
+    if let Err(e) = txn.commit().await {
+        database_migration::Entity::delete_many()
+            .filter(database_migration::Column::Id.eq(migration.last_insert_id))
+            .exec(conn)
+            .await?;
+        return Err(statement_error(e));
+    }
+
+    Ok(())
+}
+
+/// Runs a statement that returns rows, each row is returned as a JSON object
+///
+/// # Errors
+///
+/// Will return `Err` with a [`SqlError`] if the statement fails or returns more than
+/// [`Limits::max_rows`] rows
+pub async fn query(
+    db: &AppDatabase,
+    sql: &str,
+    params: Vec<serde_json::Value>,
+    limits: Limits,
+) -> Result<Vec<serde_json::Value>> {
+    // Postgres turns the rows into JSON, so we don't need to know their types. Data modifying
+    // statements can be used in `WITH` as well, as long as they have a `RETURNING` clause
+    let sql = format!(
+        r#"
+        WITH result AS ({})
+        SELECT COALESCE(json_agg(limited), '[]'::json) AS rows
+        FROM (SELECT * FROM result LIMIT {}) AS limited
+        "#,
+        trim(sql),
+        limits.max_rows + 1
+    );
+
+    let mut txn = begin(db, limits).await?;
+    let row = bind(sqlx::query(&sql), params)
+        .fetch_one(&mut txn)
+        .await
+        .map_err(statement_error)?;
+    txn.commit().await.map_err(statement_error)?;
+
+    let rows = match row.try_get::<serde_json::Value, _>("rows")? {
+        serde_json::Value::Array(rows) => rows,
+        _ => unreachable!("json_agg always returns an array"),
+    };
+    if rows.len() > limits.max_rows {
+        return Err(SqlError::new(format!(
+            "The query returned more than {} rows, use LIMIT to page through them",
+            limits.max_rows
+        ))
+        .into());
+    }
+
+    Ok(rows)
+}
+
+/// Runs a statement for its effect and returns the number of rows it changed
+///
+/// # Errors
+///
+/// Will return `Err` with a [`SqlError`] if the statement fails
+pub async fn execute(
+    db: &AppDatabase,
+    sql: &str,
+    params: Vec<serde_json::Value>,
+    limits: Limits,
+) -> Result<u64> {
+    let sql = trim(sql);
+    let mut txn = begin(db, limits).await?;
+    let result = bind(sqlx::query(&sql), params)
+        .execute(&mut txn)
+        .await
+        .map_err(statement_error)?;
+    txn.commit().await.map_err(statement_error)?;
+
+    Ok(result.rows_affected())
+}
+
+/// Starts a transaction inside the schema of the app. The settings only last until the
+/// transaction ends, so the connection goes back to the pool unchanged
+async fn begin(db: &AppDatabase, limits: Limits) -> Result<Transaction<'static, Postgres>> {
+    let mut txn = db
+        .pool
+        .begin()
+        .await
+        .context("Failed to connect to the database of the app")?;
+
+    for sql in [
+        format!("SET LOCAL search_path TO {}", schema_name(db.user_id)),
+        format!(
+            "SET LOCAL statement_timeout = {}",
+            limits.statement_timeout_ms
+        ),
+    ] {
+        sqlx::query(&sql).execute(&mut txn).await?;
+    }
+
+    Ok(txn)
+}
+
+/// Errors of statements only tell the app the SQLSTATE, the message of Postgres could contain
+/// anything a function of the app raised
+fn statement_error(err: sqlx::Error) -> anyhow::Error {
+    match err {
+        sqlx::Error::Database(err) => {
+            let code = err.code().unwrap_or_default().into_owned();
+            SqlError::new(format!("{}: {}", code, describe(&code))).into()
+        }
+        sqlx::Error::PoolTimedOut => {
+            SqlError::new("The database of the app is busy".to_string()).into()
+        }
+        err => anyhow::Error::from(err).context("Failed to run statement"),
+    }
+}
+
+/// Descriptions of the errors apps run into most, other errors are described by their class
+fn describe(code: &str) -> &'static str {
+    match code {
+        "23502" => "not null violation",
+        "23503" => "foreign key violation",
+        "23505" => "unique violation",
+        "23514" => "check violation",
+        "22P02" => "invalid input syntax",
+        "40001" => "serialization failure",
+        "40P01" => "deadlock detected",
+        "42501" => "insufficient privilege",
+        "42601" => "syntax error",
+        "42703" => "undefined column",
+        "42883" => "undefined function",
+        "42P01" => "undefined table",
+        "42P07" => "duplicate table",
+        "57014" => "statement cancelled, it ran longer than the statement timeout",
+        _ => match code.get(..2) {
+            Some("0A") => "feature not supported",
+            Some("21") => "cardinality violation",
+            Some("22") => "data exception",
+            Some("23") => "integrity constraint violation",
+            Some("25") => "invalid transaction state",
+            Some("40") => "transaction rollback",
+            Some("42") => "syntax error or access rule violation",
+            Some("53") => "insufficient resources",
+            Some("54") => "program limit exceeded",
+            Some("55") => "object not in prerequisite state",
+            Some("57") => "operator intervention",
+            Some("P0") => "error raised by a function",
+            _ => "statement failed",
+        },
+    }
+}
+
+/// Statements are embedded into others, so a trailing semicolon would end them early
+fn trim(sql: &str) -> String {
+    sql.trim().trim_end_matches(';').to_string()
+}
+
+/// Parameters are typed by their JSON value, SQL has to cast them where Postgres expects another
+/// type, e.g. `$1::date`
+fn bind(
+    mut query: sqlx::query::Query<'_, Postgres, PgArguments>,
+    params: Vec<serde_json::Value>,
+) -> sqlx::query::Query<'_, Postgres, PgArguments> {
+    for value in params {
+        query = match value {
+            serde_json::Value::Null => query.bind(Option::<String>::None),
+            serde_json::Value::Bool(value) => query.bind(value),
+            serde_json::Value::Number(number) => match number.as_i64() {
+                Some(number) => query.bind(number),
+                None => query.bind(number.as_f64().unwrap_or_default()),
+            },
+            serde_json::Value::String(value) => query.bind(value),
+            value => query.bind(value),
+        };
+    }
+
+    query
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use migration::sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    expires_at, list, put_many, Item, KvError, ListOptions, Value, Write, MAX_VALUE_BYTES,
+};
+
+/// Amount of records written per transaction during an import
+const IMPORT_BATCH_SIZE: usize = 500;
+/// Longest line of an import, fits the biggest value base64 encoded with its key and metadata
+pub const MAX_IMPORT_LINE_BYTES: usize = MAX_VALUE_BYTES / 3 * 4 + 64 * 1024;
+
+/// One line of a JSON lines export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub key: String,
+    /// In the shape `{ "type": "text" | "json" | "bytes", "value": ... }`, bytes are base64 encoded
+    pub value: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// Seconds since the UNIX epoch at which the key expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<i64>,
+}
+
+impl From<Item> for Record {
+    fn from(item: Item) -> Self {
+        Self {
+            key: item.key,
+            value: item.value,
+            metadata: item.metadata,
+            expiration: item.expires_at.map(|expires_at| expires_at.timestamp()),
+        }
+    }
+}
+
+/// Exports one page of keys as JSON lines, every line ends with a newline
+///
+/// Returns the cursor of the next page, or `None` once the namespace is exhausted
+///
+/// # Errors
+///
+/// Will return `Err` if the cursor is invalid or the database query fails
+pub async fn export_page(
+    conn: &DatabaseConnection,
+    namespace_id: i32,
+    cursor: Option<String>,
+) -> Result<(String, Option<String>)> {
+    let options = ListOptions {
+        cursor,
+        ..ListOptions::default()
+    };
+    let page = list(conn, namespace_id, &options).await?;
+
+    let mut lines = String::new();
+    for item in page.items {
+        lines.push_str(&serde_json::to_string(&Record::from(item))?);
+        lines.push('\n');
+    }
+
+    Ok((lines, page.cursor))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Records that expired since they were exported
+    pub skipped: usize,
+}
+
+/// Imports JSON lines one line at a time, records are written in batches
+/// so an import never holds more than a batch in memory
+///
+/// Existing keys are overwritten, keys that aren't part of the import are kept
+pub struct Importer<'a> {
+    conn: &'a DatabaseConnection,
+    namespace_id: i32,
+    batch: Vec<Write>,
+    line: usize,
+    summary: ImportSummary,
+}
+
+impl<'a> Importer<'a> {
+    pub fn new(conn: &'a DatabaseConnection, namespace_id: i32) -> Self {
+        Self {
+            conn,
+            namespace_id,
+            batch: Vec::with_capacity(IMPORT_BATCH_SIZE),
+            line: 0,
+            summary: ImportSummary::default(),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return a [`KvError`] if the line isn't a valid record, batches that were
+    /// already written stay written
+    pub async fn push(&mut self, line: &str) -> Result<()> {
+        self.line += 1;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let record: Record = serde_json::from_str(line)
+            .map_err(|e| KvError::new(format!("Invalid record on line {}: {}", self.line, e)))?;
+
+        let expires_at = match record.expiration {
+            Some(expiration) if expiration <= chrono::Utc::now().timestamp() => {
+                self.summary.skipped += 1;
+                return Ok(());
+            }
+            Some(expiration) => expires_at(None, Some(expiration)).map_err(|e| {
+                KvError::new(format!("Invalid record on line {}: {}", self.line, e))
+            })?,
+            None => None,
+        };
+
+        self.batch.push(Write::new(
+            record.key,
+            record.value,
+            record.metadata,
+            expires_at,
+        )?);
+
+        if self.batch.len() >= IMPORT_BATCH_SIZE {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last batch
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database query fails
+    pub async fn finish(mut self) -> Result<ImportSummary> {
+        self.flush().await?;
+
+        Ok(self.summary)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let writes = std::mem::replace(&mut self.batch, Vec::with_capacity(IMPORT_BATCH_SIZE));
+        let count = writes.len();
+        put_many(self.conn, self.namespace_id, writes).await?;
+        self.summary.imported += count;
+
+        Ok(())
+    }
+}
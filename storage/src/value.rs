@@ -0,0 +1,60 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A stored value, serialized as `{ "type": ..., "value": ... }` with bytes base64 encoded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Value {
+    Text(String),
+    Json(serde_json::Value),
+    Bytes(#[serde(with = "base64_bytes")] Vec<u8>),
+}
+
+impl Value {
+    /// The tag stored in `store.value_type`
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Text(_) => "text",
+            Self::Json(_) => "json",
+            Self::Bytes(_) => "bytes",
+        }
+    }
+
+    /// Size in bytes, JSON is counted in its serialized form
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Text(text) => text.len(),
+            Self::Json(json) => json.to_string().len(),
+            Self::Bytes(bytes) => bytes.len(),
+        }
+    }
+
+    pub(crate) fn from_columns(
+        kind: &str,
+        text: Option<String>,
+        json: Option<serde_json::Value>,
+        bytes: Option<Vec<u8>>,
+    ) -> Result<Self> {
+        let value = match kind {
+            "text" => Self::Text(text.context("Text value without text")?),
+            "json" => Self::Json(json.context("JSON value without JSON")?),
+            "bytes" => Self::Bytes(bytes.context("Binary value without bytes")?),
+            _ => bail!("Unknown kv value type {}", kind),
+        };
+
+        Ok(value)
+    }
+}
+
+mod base64_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(encoded).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,51 @@
+//! Setup for the tests that need a Postgres database. They are ignored by default,
+//! `DATABASE_URL=... cargo test -p storage -- --ignored` runs them
+
+use entity::user;
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{ColumnTrait, Database, DatabaseConnection, EntityTrait, QueryFilter};
+use migration::{Migrator, MigratorTrait};
+
+/// A migrated database with a user of its own, removed again by `cleanup`
+pub struct Fixture {
+    pub conn: DatabaseConnection,
+    pub database_url: String,
+    pub user_id: i32,
+}
+
+impl Fixture {
+    pub async fn new(name: &str) -> Self {
+        let database_url =
+            std::env::var("DATABASE_URL").expect("DATABASE_URL has to be set for this test");
+        let conn = Database::connect(database_url.as_str()).await.unwrap();
+        Migrator::up(&conn, None).await.unwrap();
+
+        let now = chrono::Utc::now();
+        let name = format!("{}-{}", name, now.timestamp_nanos());
+        let user = user::Entity::insert(user::ActiveModel {
+            name: Set(name.clone()),
+            client_id: Set(name),
+            client_secret: Set("secret".into()),
+            created_at: Set(chrono::DateTime::into(now)),
+            ..Default::default()
+        })
+        .exec(&conn)
+        .await
+        .unwrap();
+
+        Self {
+            conn,
+            database_url,
+            user_id: user.last_insert_id,
+        }
+    }
+
+    /// Deleting the user cascades to everything it owns
+    pub async fn cleanup(self) {
+        user::Entity::delete_many()
+            .filter(user::Column::Id.eq(self.user_id))
+            .exec(&self.conn)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use storage::{Check, KvBackend, ListOptions, MemoryBackend, Mutation, Value, Write};
+
+const NAMESPACE: i32 = 1;
+
+fn write(key: &str, value: Value) -> Write {
+    Write::new(key.into(), value, None, None).unwrap()
+}
+
+#[tokio::test]
+async fn reads_back_writes() {
+    let backend = MemoryBackend::default();
+    backend
+        .put(NAMESPACE, write("text", Value::Text("value".into())))
+        .await
+        .unwrap();
+    backend
+        .put(NAMESPACE, write("bytes", Value::Bytes(vec![0, 1, 2])))
+        .await
+        .unwrap();
+
+    let item = backend.get(NAMESPACE, "text").await.unwrap().unwrap();
+    assert_eq!(item.value, Value::Text("value".into()));
+    let item = backend.get(NAMESPACE, "bytes").await.unwrap().unwrap();
+    assert_eq!(item.value, Value::Bytes(vec![0, 1, 2]));
+    assert!(backend.get(NAMESPACE + 1, "text").await.unwrap().is_none());
+
+    backend.delete(NAMESPACE, "text").await.unwrap();
+    assert!(backend.get(NAMESPACE, "text").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn lists_pages_by_prefix() {
+    let backend = MemoryBackend::default();
+    let writes = ["a/1", "a/2", "a/3", "b/1"]
+        .iter()
+        .map(|key| write(key, Value::Text((*key).into())))
+        .collect();
+    backend.put_many(NAMESPACE, writes).await.unwrap();
+
+    let mut options = ListOptions {
+        prefix: Some("a/".into()),
+        limit: Some(2),
+        cursor: None,
+    };
+    let page = backend.list(NAMESPACE, &options).await.unwrap();
+    let keys: Vec<_> = page.items.iter().map(|item| item.key.as_str()).collect();
+    assert_eq!(keys, ["a/1", "a/2"]);
+    assert!(!page.list_complete);
+
+    options.cursor = page.cursor;
+    let page = backend.list(NAMESPACE, &options).await.unwrap();
+    let keys: Vec<_> = page.items.iter().map(|item| item.key.as_str()).collect();
+    assert_eq!(keys, ["a/3"]);
+    assert!(page.list_complete);
+    assert!(page.cursor.is_none());
+}
+
+#[tokio::test]
+async fn atomic_rejects_stale_versions() {
+    let backend = MemoryBackend::default();
+    backend
+        .put(NAMESPACE, write("counter", Value::Json(1.into())))
+        .await
+        .unwrap();
+    let version = backend
+        .get(NAMESPACE, "counter")
+        .await
+        .unwrap()
+        .unwrap()
+        .version;
+
+    let check = || Check {
+        key: "counter".into(),
+        version: Some(version),
+    };
+    let result = backend
+        .atomic(
+            NAMESPACE,
+            vec![check()],
+            vec![Mutation::Sum("counter".into(), 2)],
+        )
+        .await
+        .unwrap();
+    assert!(result.ok);
+
+    let result = backend
+        .atomic(
+            NAMESPACE,
+            vec![check()],
+            vec![Mutation::Sum("counter".into(), 2)],
+        )
+        .await
+        .unwrap();
+    assert!(!result.ok);
+    assert_eq!(result.conflicts, ["counter"]);
+
+    let item = backend.get(NAMESPACE, "counter").await.unwrap().unwrap();
+    assert_eq!(item.value, Value::Json(3.into()));
+}
+
+#[tokio::test]
+async fn sum_rejects_binary_values() {
+    let backend = MemoryBackend::default();
+    backend
+        .put(NAMESPACE, write("bytes", Value::Bytes(vec![1])))
+        .await
+        .unwrap();
+
+    let result = backend
+        .atomic(NAMESPACE, vec![], vec![Mutation::Sum("bytes".into(), 1)])
+        .await;
+    assert!(result.is_err());
+
+    let item = backend.get(NAMESPACE, "bytes").await.unwrap().unwrap();
+    assert_eq!(item.value, Value::Bytes(vec![1]));
+}
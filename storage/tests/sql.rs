@@ -0,0 +1,134 @@
+use serde_json::json;
+use storage::sql::{self, Limits, SqlError};
+
+mod common;
+
+const LIMITS: Limits = Limits {
+    statement_timeout_ms: 1000,
+    max_rows: 2,
+};
+
+/// The database user has to be allowed to create roles
+#[tokio::test]
+#[ignore = "needs a Postgres database"]
+async fn statements_stay_in_the_schema_of_the_app() {
+    let fixture = common::Fixture::new("sql-test").await;
+    let (conn, user_id) = (&fixture.conn, fixture.user_id);
+
+    sql::create(conn, user_id).await.unwrap();
+    let db = sql::connect(conn, &fixture.database_url, user_id)
+        .await
+        .unwrap();
+    sql::migrate(
+        conn,
+        &db,
+        "create_todos",
+        &["CREATE TABLE todos (id serial PRIMARY KEY, title text NOT NULL)".to_string()],
+        LIMITS,
+    )
+    .await
+    .unwrap();
+
+    let changes = sql::execute(
+        &db,
+        "INSERT INTO todos (title) VALUES ($1), ($2)",
+        vec![json!("first"), json!("second")],
+        LIMITS,
+    )
+    .await
+    .unwrap();
+    let rows = sql::query(
+        &db,
+        "SELECT title FROM todos WHERE id = $1",
+        vec![json!(1)],
+        LIMITS,
+    )
+    .await
+    .unwrap();
+    let too_many = sql::query(&db, "SELECT * FROM generate_series(1, 3)", vec![], LIMITS).await;
+    let other_schema = sql::query(&db, "SELECT * FROM public.users", vec![], LIMITS).await;
+    // the role the connection logged in as is the one a reset goes back to
+    let reset_role = sql::query(
+        &db,
+        "SELECT current_user::text AS role FROM (SELECT set_config('role', 'none', true)) AS reset",
+        vec![],
+        LIMITS,
+    )
+    .await
+    .unwrap();
+    let timeout = sql::query(&db, "SELECT pg_sleep(2)", vec![], LIMITS).await;
+
+    drop(db);
+    sql::delete(conn, user_id).await.unwrap();
+    fixture.cleanup().await;
+
+    assert_eq!(changes, 2);
+    assert_eq!(rows, vec![json!({ "title": "first" })]);
+    for result in [too_many, other_schema, timeout] {
+        assert!(result.unwrap_err().downcast_ref::<SqlError>().is_some());
+    }
+    assert_eq!(
+        reset_role,
+        vec![json!({ "role": sql::schema_name(user_id) })]
+    );
+}
+
+/// A function can reset the role of its session, which has to stay the role of the app
+#[tokio::test]
+#[ignore = "needs a Postgres database"]
+async fn functions_cannot_reset_into_another_schema() {
+    let fixture = common::Fixture::new("sql-test").await;
+    let other = common::Fixture::new("sql-test-other").await;
+    let conn = &fixture.conn;
+
+    sql::create(conn, other.user_id).await.unwrap();
+    let other_db = sql::connect(conn, &other.database_url, other.user_id)
+        .await
+        .unwrap();
+    sql::migrate(
+        conn,
+        &other_db,
+        "create_secrets",
+        &["CREATE TABLE secrets (value text NOT NULL)".to_string()],
+        LIMITS,
+    )
+    .await
+    .unwrap();
+
+    sql::create(conn, fixture.user_id).await.unwrap();
+    let db = sql::connect(conn, &fixture.database_url, fixture.user_id)
+        .await
+        .unwrap();
+    sql::migrate(
+        conn,
+        &db,
+        "create_escape",
+        &[format!(
+            r#"
+            CREATE FUNCTION escape() RETURNS bigint LANGUAGE plpgsql AS $$
+            BEGIN
+                RESET ROLE;
+                RETURN (SELECT count(*) FROM {}.secrets);
+            END
+            $$
+            "#,
+            sql::schema_name(other.user_id)
+        )],
+        LIMITS,
+    )
+    .await
+    .unwrap();
+
+    let escape = sql::query(&db, "SELECT escape() AS count", vec![], LIMITS).await;
+
+    drop(db);
+    drop(other_db);
+    sql::delete(conn, fixture.user_id).await.unwrap();
+    sql::delete(conn, other.user_id).await.unwrap();
+    fixture.cleanup().await;
+    other.cleanup().await;
+
+    let error = escape.unwrap_err();
+    let error = error.downcast_ref::<SqlError>().unwrap();
+    assert!(error.to_string().starts_with("42501: "), "{}", error);
+}
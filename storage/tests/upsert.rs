@@ -0,0 +1,103 @@
+use entity::{namespace, store};
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+
+mod common;
+
+const WRITERS: usize = 32;
+
+#[tokio::test]
+#[ignore = "needs a Postgres database"]
+async fn concurrent_writers_leave_a_single_row() {
+    let fixture = common::Fixture::new("kv-upsert-test").await;
+    let conn = fixture.conn.clone();
+    let now = chrono::Utc::now();
+
+    let namespace = namespace::Entity::insert(namespace::ActiveModel {
+        name: Set("default".into()),
+        user_id: Set(fixture.user_id),
+        created_at: Set(chrono::DateTime::into(now)),
+        ..Default::default()
+    })
+    .exec(&conn)
+    .await
+    .unwrap();
+    let namespace_id = namespace.last_insert_id;
+
+    let handles: Vec<_> = (0..WRITERS)
+        .map(|index| {
+            let conn = conn.clone();
+            tokio::spawn(async move {
+                let write = storage::Write::new(
+                    "key".into(),
+                    storage::Value::Text(index.to_string()),
+                    None,
+                    None,
+                )?;
+                storage::put(&conn, namespace_id, write).await
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.await.unwrap().unwrap();
+    }
+
+    let items = store::Entity::find()
+        .filter(store::Column::NamespaceId.eq(namespace_id))
+        .all(&conn)
+        .await
+        .unwrap();
+
+    fixture.cleanup().await;
+
+    assert_eq!(items.len(), 1);
+    assert!(items[0].value.as_deref().unwrap().parse::<usize>().unwrap() < WRITERS);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database"]
+async fn sum_only_rejects_values_that_are_no_integers() {
+    let fixture = common::Fixture::new("kv-sum-test").await;
+    let conn = fixture.conn.clone();
+
+    let namespace = namespace::Entity::insert(namespace::ActiveModel {
+        name: Set("default".into()),
+        user_id: Set(fixture.user_id),
+        created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
+        ..Default::default()
+    })
+    .exec(&conn)
+    .await
+    .unwrap();
+    let namespace_id = namespace.last_insert_id;
+
+    let put = |key: &str, value: storage::Value| {
+        let write = storage::Write::new(key.into(), value, None, None).unwrap();
+        storage::put(&conn, namespace_id, write)
+    };
+    put("text", storage::Value::Text("x".into())).await.unwrap();
+    put("max", storage::Value::Json(i64::MAX.into()))
+        .await
+        .unwrap();
+    put("counter", storage::Value::Text("2".into()))
+        .await
+        .unwrap();
+
+    let sum = |key: &str, delta: i64| {
+        let mutations = vec![storage::Mutation::Sum(key.into(), delta)];
+        storage::atomic(&conn, namespace_id, vec![], mutations)
+    };
+    let text = sum("text", 1).await.unwrap_err();
+    let overflow = sum("max", 1).await.unwrap_err();
+    let counter = sum("counter", 3).await.unwrap();
+    let missing = sum("missing", 4).await.unwrap();
+    let item = storage::get(&conn, namespace_id, "counter").await.unwrap();
+
+    fixture.cleanup().await;
+
+    assert!(text.downcast_ref::<storage::KvError>().is_some());
+    assert!(overflow.downcast_ref::<storage::KvError>().is_some());
+    assert!(counter.ok && missing.ok);
+    assert_eq!(item.unwrap().value, storage::Value::Json(5.into()));
+}
@@ -118,6 +118,11 @@ mod not_docs {
     fn create_runtime_snapshot(snapshot_path: &Path, files: Vec<PathBuf>) {
         let extensions: Vec<Extension> = vec![
             kv::init(None),
+            queue::init(None),
+            actor::init(None),
+            blobs::init(None),
+            cache::init(None),
+            db::init(None),
             deno_webidl::init(),
             deno_console::init(),
             deno_url::init(),
@@ -139,6 +144,7 @@ mod not_docs {
             ),
             deno_http::init(),
             utils::init(),
+            sockets::init(),
         ];
 
         let js_runtime = JsRuntime::new(RuntimeOptions {
@@ -0,0 +1,279 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+  const nullBodyStatus = [101, 204, 205, 304];
+
+  /**
+   * @param {any} value must be serializable with JSON.stringify
+   * @returns {string}
+   */
+  function encodeValue(value) {
+    const encoded = JSON.stringify(value);
+    if (encoded === undefined) {
+      throw new TypeError("Actor storage values have to be serializable with JSON.stringify");
+    }
+    return encoded;
+  }
+
+  /**
+   * Buffers writes until they are committed together, reads see the buffered writes
+   */
+  class ActorTransaction {
+    /** @type {Map<string, string>} */
+    #puts = new Map();
+    /** @type {Set<string>} */
+    #deletes = new Set();
+    #deleteAll = false;
+    #rolledBack = false;
+
+    /**
+     * @param {string} key
+     * @returns {Promise<any>}
+     */
+    async get(key) {
+      if (this.#puts.has(key)) {
+        return JSON.parse(this.#puts.get(key));
+      }
+      if (this.#deleteAll || this.#deletes.has(key)) {
+        return undefined;
+      }
+
+      const value = await core.opAsync("op_actor_storage_get", key);
+      return value === null ? undefined : JSON.parse(value);
+    }
+
+    /**
+     * @param {string | Record<string, any>} keyOrEntries
+     * @param {any} [value]
+     */
+    put(keyOrEntries, value) {
+      const entries = typeof keyOrEntries === "string"
+        ? [[keyOrEntries, value]]
+        : Object.entries(keyOrEntries);
+
+      for (const [key, value] of entries) {
+        this.#puts.set(key, encodeValue(value));
+        this.#deletes.delete(key);
+      }
+    }
+
+    /**
+     * @param {string | string[]} keys
+     */
+    delete(keys) {
+      for (const key of Array.isArray(keys) ? keys : [keys]) {
+        this.#puts.delete(key);
+        this.#deletes.add(key);
+      }
+    }
+
+    deleteAll() {
+      this.#puts.clear();
+      this.#deletes.clear();
+      this.#deleteAll = true;
+    }
+
+    /**
+     * @param {{ prefix?: string, limit?: number }} options
+     * @returns {Promise<Map<string, any>>}
+     */
+    async list(options = {}) {
+      const prefix = options.prefix ?? "";
+      const stored = this.#deleteAll
+        ? []
+        : await core.opAsync("op_actor_storage_list", { prefix });
+
+      const entries = new Map(stored.filter(([key]) => !this.#deletes.has(key)));
+      for (const [key, value] of this.#puts) {
+        if (key.startsWith(prefix)) {
+          entries.set(key, value);
+        }
+      }
+
+      const keys = [...entries.keys()].sort().slice(0, options.limit);
+      return new Map(keys.map((key) => [key, JSON.parse(entries.get(key))]));
+    }
+
+    /**
+     * Discards every write of the transaction
+     */
+    rollback() {
+      this.#rolledBack = true;
+    }
+
+    /**
+     * @returns {Promise<void>}
+     */
+    async commit() {
+      if (this.#rolledBack) {
+        return;
+      }
+
+      await core.opAsync("op_actor_storage_commit", {
+        deleteAll: this.#deleteAll,
+        deletes: [...this.#deletes],
+        puts: [...this.#puts],
+      });
+    }
+  }
+
+  /**
+   * Storage of a single actor instance, kept in Postgres
+   */
+  class ActorStorage {
+    /**
+     * @param {string} key
+     * @returns {Promise<any>}
+     */
+    get(key) {
+      return new ActorTransaction().get(key);
+    }
+
+    /**
+     * @param {{ prefix?: string, limit?: number }} options
+     * @returns {Promise<Map<string, any>>}
+     */
+    async list(options = {}) {
+      const entries = await core.opAsync("op_actor_storage_list", {
+        prefix: options.prefix,
+        limit: options.limit,
+      });
+      return new Map(entries.map(([key, value]) => [key, JSON.parse(value)]));
+    }
+
+    /**
+     * @param {string | Record<string, any>} keyOrEntries
+     * @param {any} [value]
+     * @returns {Promise<void>}
+     */
+    put(keyOrEntries, value) {
+      return this.transaction((txn) => txn.put(keyOrEntries, value));
+    }
+
+    /**
+     * @param {string | string[]} keys
+     * @returns {Promise<void>}
+     */
+    delete(keys) {
+      return this.transaction((txn) => txn.delete(keys));
+    }
+
+    /**
+     * @returns {Promise<void>}
+     */
+    deleteAll() {
+      return this.transaction((txn) => txn.deleteAll());
+    }
+
+    /**
+     * Writes of the closure are applied together once it resolved, nothing is written if it throws
+     *
+     * @template T
+     * @param {(txn: ActorTransaction) => T | Promise<T>} closure
+     * @returns {Promise<T>}
+     */
+    async transaction(closure) {
+      const txn = new ActorTransaction();
+      const result = await closure(txn);
+      await txn.commit();
+      return result;
+    }
+  }
+
+  /**
+   * Handle for a single actor, every call for its id reaches the same instance
+   */
+  class ActorStub {
+    #binding;
+
+    /**
+     * @param {string} binding
+     * @param {string} id
+     */
+    constructor(binding, id) {
+      this.#binding = binding;
+      this.id = id;
+    }
+
+    /**
+     * @param {Request | string | URL} input relative URLs are resolved against `http://actor/`
+     * @param {RequestInit} [init]
+     * @returns {Promise<Response>}
+     */
+    async fetch(input, init) {
+      const request = input instanceof Request
+        ? new Request(input, init)
+        : new Request(new URL(input, "http://actor/"), init);
+      const hasBody = request.method !== "GET" && request.method !== "HEAD";
+
+      const response = await core.opAsync("op_actor_fetch", this.#binding, {
+        id: this.id,
+        url: request.url,
+        method: request.method,
+        headers: [...request.headers],
+        body: hasBody ? new Uint8Array(await request.arrayBuffer()) : undefined,
+      });
+
+      return new Response(nullBodyStatus.includes(response.status) ? null : response.body, {
+        status: response.status,
+        headers: response.headers,
+      });
+    }
+  }
+
+  class ActorNamespace {
+    #binding;
+
+    /**
+     * @param {string} binding
+     */
+    constructor(binding) {
+      this.#binding = binding;
+    }
+
+    /**
+     * @param {string} id
+     * @returns {ActorStub}
+     */
+    get(id) {
+      return new ActorStub(this.#binding, String(id));
+    }
+  }
+
+  let actorInstance = null;
+
+  window._hbw ??= {};
+  window._hbw.actor = {
+    /** Set by the runtime in the isolate of an actor, `{ className, id }` */
+    address: undefined,
+    /**
+     * @param {string} binding
+     * @returns {ActorNamespace}
+     */
+    namespace: (binding) => new ActorNamespace(binding),
+    /**
+     * The actor this isolate runs, created on the first call
+     *
+     * @returns {any | null}
+     */
+    instance() {
+      const address = window._hbw.actor.address;
+      if (address === undefined) {
+        return null;
+      }
+
+      if (actorInstance === null) {
+        const ActorClass = window._hbw.module?.[address.className] ?? window[address.className];
+        if (typeof ActorClass !== "function") {
+          throw new Error(`The script doesn't export an actor class named ${address.className}`);
+        }
+
+        const state = { id: address.id, storage: new ActorStorage() };
+        actorInstance = new ActorClass(state, window._hbw.env);
+      }
+
+      return actorInstance;
+    },
+  };
+})(this);
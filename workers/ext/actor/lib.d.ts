@@ -0,0 +1,48 @@
+declare global {
+  interface ActorTransaction {
+    get: (key: string) => Promise<any>,
+    put: ((key: string, value: any) => void) & ((entries: Record<string, any>) => void),
+    delete: (keys: string | string[]) => void,
+    deleteAll: () => void,
+    list: (options?: { prefix?: string, limit?: number }) => Promise<Map<string, any>>,
+    /** Discards every write of the transaction */
+    rollback: () => void,
+  }
+
+  /**
+   * Values have to be serializable with `JSON.stringify`
+   */
+  interface ActorStorage {
+    get: (key: string) => Promise<any>,
+    put: ((key: string, value: any) => Promise<void>) & ((entries: Record<string, any>) => Promise<void>),
+    delete: (keys: string | string[]) => Promise<void>,
+    deleteAll: () => Promise<void>,
+    list: (options?: { prefix?: string, limit?: number }) => Promise<Map<string, any>>,
+    /** Writes of the closure are applied together once it resolved, nothing is written if it throws */
+    transaction: <T>(closure: (txn: ActorTransaction) => T | Promise<T>) => Promise<T>,
+  }
+
+  /**
+   * Passed to the constructor of an actor class, the instance lives as long as its isolate
+   */
+  interface ActorState {
+    id: string,
+    storage: ActorStorage,
+  }
+
+  interface ActorStub {
+    id: string,
+    /** Relative URLs are resolved against `http://actor/` */
+    fetch: (input: Request | string | URL, init?: RequestInit) => Promise<Response>,
+  }
+
+  /**
+   * Actor bindings are exposed as globals and on `env` under their own name,
+   * every call for an id reaches the same instance
+   */
+  interface ActorNamespace {
+    get: (id: string) => ActorStub,
+  }
+}
+
+export { };
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::{cell::RefCell, rc::Rc};
+
+use deno_core::anyhow::{anyhow, Context};
+use deno_core::{
+    anyhow::Result, include_js_files, op, ByteString, Extension, OpState, ZeroCopyBuf,
+};
+use entity::actor_binding;
+use migration::sea_orm::ColumnTrait;
+use migration::sea_orm::EntityTrait;
+use migration::sea_orm::QueryFilter;
+use serde::{Deserialize, Serialize};
+use session::Session;
+use tokio::sync::{mpsc, oneshot};
+
+mod storage;
+
+/// Identifies one instance of an actor, calls for the same address always reach the same isolate
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActorAddress {
+    pub class_name: String,
+    pub id: String,
+}
+
+#[derive(Debug)]
+pub struct ActorRequest {
+    pub url: String,
+    pub method: String,
+    pub headers: Vec<(ByteString, ByteString)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct ActorResponse {
+    pub status: u16,
+    pub headers: Vec<(ByteString, ByteString)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct ActorCall {
+    pub address: ActorAddress,
+    pub request: ActorRequest,
+    /// The actors waiting for this call, a call to one of them would never be handled
+    pub chain: Vec<ActorAddress>,
+    pub reply: oneshot::Sender<Result<ActorResponse>>,
+}
+
+/// The actors waiting for the request the isolate is handling, the runtime replaces it for
+/// every request
+#[derive(Debug, Clone, Default)]
+pub struct CallChain(pub Vec<ActorAddress>);
+
+/// Hands calls to the workers process, which owns the isolates of all actors
+#[derive(Debug, Clone)]
+pub struct ActorRouter(pub mpsc::Sender<ActorCall>);
+
+/// `address` is only set in the isolate of an actor itself
+#[derive(Debug, Clone)]
+pub struct ActorContext {
+    pub session: Session,
+    pub router: ActorRouter,
+    pub address: Option<ActorAddress>,
+}
+
+pub fn init(maybe_context: Option<ActorContext>) -> Extension {
+    Extension::builder()
+        .js(include_js_files!(
+            prefix "ext/actor",
+            "01_actor.js",
+        ))
+        .ops(vec![
+            op_actor_fetch::decl(),
+            storage::op_actor_storage_get::decl(),
+            storage::op_actor_storage_list::decl(),
+            storage::op_actor_storage_commit::decl(),
+        ])
+        .state(move |state| {
+            if let Some(context) = maybe_context.clone() {
+                state.put::<ActorContext>(context);
+            }
+            Ok(())
+        })
+        .build()
+}
+
+/// The actor bindings of this user by name, mapped to the class implementing them
+#[derive(Clone, Default)]
+pub struct Bindings(HashMap<String, String>);
+
+impl Bindings {
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+}
+
+/// Loads the bindings of this user, the runtime puts them into the `OpState` of its isolate
+pub async fn load_bindings(session: &Session) -> Result<Bindings> {
+    // actors keep their storage in the database
+    if !session.has_database() {
+        return Ok(Bindings::default());
+    }
+
+    let bindings = actor_binding::Entity::find()
+        .filter(actor_binding::Column::UserId.eq(session.user_id))
+        .all(&session.conn)
+        .await
+        .context("Failed to get actor bindings from database")?;
+
+    Ok(Bindings(
+        bindings
+            .into_iter()
+            .map(|binding| (binding.name, binding.class_name))
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+struct FetchArgs {
+    id: String,
+    url: String,
+    method: String,
+    headers: Vec<(ByteString, ByteString)>,
+    body: Option<ZeroCopyBuf>,
+}
+
+#[derive(Serialize)]
+struct FetchResult {
+    status: u16,
+    headers: Vec<(ByteString, ByteString)>,
+    body: ZeroCopyBuf,
+}
+
+/// Sends a request to the actor with the given id and waits for its response
+#[op]
+async fn op_actor_fetch(
+    state: Rc<RefCell<OpState>>,
+    binding: String,
+    args: FetchArgs,
+) -> Result<FetchResult> {
+    let (router, class_name, chain) = {
+        let state = state.borrow();
+        let class_name = state
+            .try_borrow::<Bindings>()
+            .and_then(|bindings| bindings.0.get(&binding).cloned())
+            .with_context(|| format!("No actor binding named {} exists for this user", binding))?;
+        let context = state.borrow::<ActorContext>();
+        let mut chain = state
+            .try_borrow::<CallChain>()
+            .map(|chain| chain.0.clone())
+            .unwrap_or_default();
+        chain.extend(context.address.clone());
+
+        (context.router.clone(), class_name, chain)
+    };
+
+    let (reply, rx) = oneshot::channel();
+    let call = ActorCall {
+        address: ActorAddress {
+            class_name,
+            id: args.id,
+        },
+        request: ActorRequest {
+            url: args.url,
+            method: args.method,
+            headers: args.headers,
+            body: args.body.map(|body| body.to_vec()).unwrap_or_default(),
+        },
+        chain,
+        reply,
+    };
+    router
+        .0
+        .send(call)
+        .await
+        .map_err(|_| anyhow!("Actor calls are no longer routed for this app"))?;
+
+    let response = rx
+        .await
+        .context("The actor stopped before it responded")??;
+
+    Ok(FetchResult {
+        status: response.status,
+        headers: response.headers,
+        body: response.body.into(),
+    })
+}
@@ -0,0 +1,198 @@
+use std::{cell::RefCell, rc::Rc};
+
+use deno_core::anyhow::{bail, Context, Result};
+use deno_core::{op, OpState};
+use migration::sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement, Value};
+use serde::Deserialize;
+
+use crate::{ActorAddress, ActorContext};
+
+/// Keys are limited to 2 KiB and values to 128 KiB of serialized JSON
+const MAX_KEY_BYTES: usize = 2048;
+const MAX_VALUE_BYTES: usize = 128 * 1024;
+/// Keys written or deleted by a single commit
+const MAX_KEYS_PER_COMMIT: usize = 128;
+/// Maximum and default amount of entries returned by `list`
+const MAX_LIST_LIMIT: i64 = 1000;
+
+fn check_key(key: &str) -> Result<()> {
+    if key.len() > MAX_KEY_BYTES {
+        bail!(
+            "The key is {} bytes, the maximum is {} bytes",
+            key.len(),
+            MAX_KEY_BYTES
+        );
+    }
+
+    Ok(())
+}
+
+/// Every key of an actor is scoped by its user, class and id
+fn scope(state: &Rc<RefCell<OpState>>) -> Result<(DatabaseConnection, Vec<Value>)> {
+    let state = state.borrow();
+    let context = state.borrow::<ActorContext>();
+    let ActorAddress { class_name, id } = context
+        .address
+        .clone()
+        .context("Storage is only available inside of an actor")?;
+
+    Ok((
+        context.session.conn.clone(),
+        vec![context.session.user_id.into(), class_name.into(), id.into()],
+    ))
+}
+
+/// Returns the value as serialized JSON
+#[op]
+pub(crate) async fn op_actor_storage_get(
+    state: Rc<RefCell<OpState>>,
+    key: String,
+) -> Result<Option<String>> {
+    check_key(&key)?;
+    let (conn, mut values) = scope(&state)?;
+    values.push(key.into());
+
+    let row = conn
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            SELECT value::text AS value FROM actor_storage
+            WHERE user_id = $1 AND class_name = $2 AND actor_id = $3 AND key = $4
+            "#,
+            values,
+        ))
+        .await
+        .context("Failed to get actor storage from database")?;
+
+    row.map(|row| row.try_get::<String>("", "value"))
+        .transpose()
+        .map_err(Into::into)
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ListArgs {
+    prefix: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Returns key and serialized JSON value pairs ordered by key
+#[op]
+pub(crate) async fn op_actor_storage_list(
+    state: Rc<RefCell<OpState>>,
+    args: ListArgs,
+) -> Result<Vec<(String, String)>> {
+    let prefix = args.prefix.unwrap_or_default();
+    check_key(&prefix)?;
+    let limit = args
+        .limit
+        .map_or(MAX_LIST_LIMIT, |limit| limit.clamp(0, MAX_LIST_LIMIT));
+    let (conn, mut values) = scope(&state)?;
+    values.push(prefix.into());
+    values.push(limit.into());
+
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            SELECT key, value::text AS value FROM actor_storage
+            WHERE user_id = $1 AND class_name = $2 AND actor_id = $3 AND starts_with(key, $4)
+            ORDER BY key
+            LIMIT $5
+            "#,
+            values,
+        ))
+        .await
+        .context("Failed to list actor storage from database")?;
+
+    rows.into_iter()
+        .map(|row| Ok((row.try_get("", "key")?, row.try_get("", "value")?)))
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CommitArgs {
+    #[serde(default)]
+    delete_all: bool,
+    deletes: Vec<String>,
+    /// Key and serialized JSON value pairs
+    puts: Vec<(String, String)>,
+}
+
+/// Applies the writes of a call or transaction together, either all or none of them
+#[op]
+pub(crate) async fn op_actor_storage_commit(
+    state: Rc<RefCell<OpState>>,
+    args: CommitArgs,
+) -> Result<()> {
+    if args.deletes.len() + args.puts.len() > MAX_KEYS_PER_COMMIT {
+        bail!(
+            "A commit can write at most {} keys, split it up",
+            MAX_KEYS_PER_COMMIT
+        );
+    }
+    for key in &args.deletes {
+        check_key(key)?;
+    }
+    for (key, value) in &args.puts {
+        check_key(key)?;
+        if value.len() > MAX_VALUE_BYTES {
+            bail!(
+                "The value of {} is {} bytes, the maximum is {} bytes",
+                key,
+                value.len(),
+                MAX_VALUE_BYTES
+            );
+        }
+    }
+
+    let (conn, actor) = scope(&state)?;
+    let txn = conn.begin().await?;
+
+    if args.delete_all {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "DELETE FROM actor_storage WHERE user_id = $1 AND class_name = $2 AND actor_id = $3",
+            actor.clone(),
+        ))
+        .await?;
+    }
+
+    for key in args.deletes {
+        let mut values = actor.clone();
+        values.push(key.into());
+
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            DELETE FROM actor_storage
+            WHERE user_id = $1 AND class_name = $2 AND actor_id = $3 AND key = $4
+            "#,
+            values,
+        ))
+        .await?;
+    }
+
+    for (key, value) in args.puts {
+        let mut values = actor.clone();
+        values.push(key.into());
+        values.push(value.into());
+
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            INSERT INTO actor_storage (user_id, class_name, actor_id, key, value, created_at)
+            VALUES ($1, $2, $3, $4, $5::jsonb, now())
+            ON CONFLICT (user_id, class_name, actor_id, key) DO UPDATE SET value = EXCLUDED.value
+            "#,
+            values,
+        ))
+        .await?;
+    }
+
+    txn.commit()
+        .await
+        .context("Failed to write actor storage to database")?;
+
+    Ok(())
+}
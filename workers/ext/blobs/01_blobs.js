@@ -0,0 +1,162 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+  const encoder = new TextEncoder();
+
+  /**
+   * @typedef {string | ArrayBuffer | ArrayBufferView | Blob | ReadableStream<Uint8Array>} BlobBody
+   */
+
+  /**
+   * Yields the chunks of every kind of body `put` accepts
+   *
+   * @param {BlobBody} body
+   * @returns {AsyncGenerator<Uint8Array>}
+   */
+  async function* chunks(body) {
+    if (typeof body === "string") {
+      yield encoder.encode(body);
+    } else if (body instanceof ArrayBuffer) {
+      yield new Uint8Array(body);
+    } else if (ArrayBuffer.isView(body)) {
+      yield new Uint8Array(body.buffer, body.byteOffset, body.byteLength);
+    } else if (body instanceof Blob) {
+      yield* chunks(body.stream());
+    } else if (body instanceof ReadableStream) {
+      const reader = body.getReader();
+      try {
+        while (true) {
+          const { done, value } = await reader.read();
+          if (done) {
+            return;
+          }
+          yield value;
+        }
+      } finally {
+        reader.releaseLock();
+      }
+    } else {
+      throw new TypeError("Blob bodies have to be a string, buffer, Blob or ReadableStream");
+    }
+  }
+
+  /**
+   * @param {{ key: string, size: number, contentType: string, metadata: string | null, uploaded: number }} info
+   */
+  function decodeInfo(info) {
+    return {
+      key: info.key,
+      size: info.size,
+      contentType: info.contentType,
+      metadata: info.metadata === null ? null : JSON.parse(info.metadata),
+      uploaded: new Date(info.uploaded),
+    };
+  }
+
+  /**
+   * Streams the object in chunks, the download is aborted if the stream is cancelled
+   *
+   * @param {number} rid
+   * @returns {ReadableStream<Uint8Array>}
+   */
+  function bodyStream(rid) {
+    return new ReadableStream({
+      async pull(controller) {
+        const chunk = await core.opAsync("op_blob_read", rid);
+        if (chunk === null) {
+          controller.close();
+        } else {
+          controller.enqueue(chunk);
+        }
+      },
+      cancel() {
+        core.tryClose(rid);
+      },
+    });
+  }
+
+  /**
+   * @param {string} key
+   * @param {BlobBody} body
+   * @param {{ contentType?: string, metadata?: any }} [options]
+   */
+  async function put(key, body, options = {}) {
+    const rid = await core.opAsync("op_blob_put_start", {
+      key,
+      contentType: options.contentType ?? (body instanceof Blob && body.type ? body.type : undefined),
+      metadata: options.metadata === undefined ? undefined : JSON.stringify(options.metadata),
+    });
+
+    try {
+      for await (const chunk of chunks(body)) {
+        await core.opAsync("op_blob_put_write", rid, chunk);
+      }
+    } catch (error) {
+      core.tryClose(rid);
+      throw error;
+    }
+
+    return decodeInfo(await core.opAsync("op_blob_put_finish", rid));
+  }
+
+  /**
+   * @param {string} key
+   */
+  async function get(key) {
+    const object = await core.opAsync("op_blob_get", key);
+    if (object === null) {
+      return null;
+    }
+
+    const body = bodyStream(object.rid);
+    return {
+      ...decodeInfo(object),
+      body,
+      arrayBuffer: () => new Response(body).arrayBuffer(),
+      text: () => new Response(body).text(),
+      json: () => new Response(body).json(),
+    };
+  }
+
+  /**
+   * @param {string} key
+   */
+  async function head(key) {
+    const info = await core.opAsync("op_blob_head", key);
+    return info === null ? null : decodeInfo(info);
+  }
+
+  /**
+   * @param {string} key
+   * @returns {Promise<void>}
+   */
+  function del(key) {
+    return core.opAsync("op_blob_delete", key);
+  }
+
+  /**
+   * @param {{ prefix?: string, limit?: number, cursor?: string }} [options]
+   */
+  async function list(options = {}) {
+    const result = await core.opAsync("op_blob_list", {
+      prefix: options.prefix,
+      limit: options.limit,
+      cursor: options.cursor,
+    });
+
+    return {
+      objects: result.objects.map(decodeInfo),
+      truncated: result.truncated,
+      cursor: result.cursor,
+    };
+  }
+
+  window.blobs = {
+    put,
+    get,
+    head,
+    delete: del,
+    list,
+  };
+})(this);
@@ -0,0 +1,56 @@
+declare global {
+  type BlobBody = string | ArrayBuffer | ArrayBufferView | Blob | ReadableStream<Uint8Array>
+
+  interface BlobPutOptions {
+    /** Defaults to the type of a `Blob` body, `application/octet-stream` otherwise */
+    contentType?: string,
+    /** Serializable with `JSON.stringify` */
+    metadata?: any,
+  }
+
+  interface BlobInfo {
+    key: string,
+    /** In bytes */
+    size: number,
+    contentType: string,
+    metadata: any,
+    uploaded: Date,
+  }
+
+  interface BlobObject extends BlobInfo {
+    /** Streamed from the bucket, can only be read once */
+    body: ReadableStream<Uint8Array>,
+    arrayBuffer: () => Promise<ArrayBuffer>,
+    text: () => Promise<string>,
+    json: () => Promise<any>,
+  }
+
+  interface BlobListOptions {
+    prefix?: string,
+    /** Between 1 and 1000, defaults to 1000 */
+    limit?: number,
+    /** The cursor of the previous page */
+    cursor?: string,
+  }
+
+  interface BlobListResult {
+    /** Ordered by key */
+    objects: BlobInfo[],
+    truncated: boolean,
+    cursor: string | null,
+  }
+
+  /**
+   * Objects stored in the bucket under a prefix of the user, also available as `env.blobs`.
+   * Keys are limited to 512 bytes, all objects of a user together to `BLOBS_QUOTA_BYTES`
+   */
+  var blobs: {
+    put: (key: string, body: BlobBody, options?: BlobPutOptions) => Promise<BlobInfo>,
+    get: (key: string) => Promise<BlobObject | null>,
+    head: (key: string) => Promise<BlobInfo | null>,
+    delete: (key: string) => Promise<void>,
+    list: (options?: BlobListOptions) => Promise<BlobListResult>,
+  }
+}
+
+export { };
@@ -0,0 +1,250 @@
+use std::{cell::RefCell, rc::Rc};
+
+use deno_core::anyhow::{bail, Context};
+use deno_core::{anyhow::Result, include_js_files, op, Extension, OpState};
+use entity::blob;
+use migration::sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, QueryFilter,
+    Statement,
+};
+use s3::Bucket;
+use serde::{Deserialize, Serialize};
+use session::Session;
+
+mod stream;
+
+/// Keys are limited like S3 keys, minus the prefix of the user
+const MAX_KEY_BYTES: usize = 512;
+/// Maximum and default amount of objects returned by a single `list` call
+const MAX_LIST_LIMIT: u64 = 1000;
+/// The area of the bucket that belongs to the user of the isolate
+#[derive(Clone)]
+pub struct BlobStore {
+    session: Session,
+    bucket: Option<Bucket>,
+    quota: u64,
+}
+
+impl BlobStore {
+    /// Blobs are unavailable without a bucket, the quota comes from the blob settings of the
+    /// session
+    #[must_use]
+    pub fn new(session: Session, bucket: Option<Bucket>) -> Self {
+        let quota = session.blobs.quota_bytes;
+
+        Self {
+            session,
+            bucket,
+            quota,
+        }
+    }
+
+    fn bucket(&self) -> Result<&Bucket> {
+        self.bucket
+            .as_ref()
+            .context("Blob storage isn't configured, S3 settings are missing")
+    }
+
+    fn conn(&self) -> &DatabaseConnection {
+        &self.session.conn
+    }
+
+    /// Objects of every user live under their own prefix, next to their deployments
+    fn path(&self, key: &str) -> String {
+        format!("/{}/blobs/{}", self.session.user_id, key)
+    }
+
+    /// Bytes the user may still store, an object that is overwritten doesn't count
+    async fn remaining_quota(&self, replaced_key: &str) -> Result<u64> {
+        let row = self
+            .conn()
+            .query_one(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                r#"
+                SELECT COALESCE(SUM(size), 0)::bigint AS used FROM blobs
+                WHERE user_id = $1 AND key <> $2
+                "#,
+                vec![self.session.user_id.into(), replaced_key.into()],
+            ))
+            .await?
+            .context("Failed to get blob usage from database")?;
+        let used = u64::try_from(row.try_get::<i64>("", "used")?)?;
+
+        Ok(self.quota.saturating_sub(used))
+    }
+
+    async fn find(&self, key: &str) -> Result<Option<blob::Model>> {
+        blob::Entity::find()
+            .filter(blob::Column::UserId.eq(self.session.user_id))
+            .filter(blob::Column::Key.eq(key))
+            .one(self.conn())
+            .await
+            .context("Failed to get blob from database")
+    }
+
+    /// Deletes the object and its row, so neither outlives the other
+    async fn remove(&self, key: &str) -> Result<()> {
+        let (_, code) = self.bucket()?.delete_object(self.path(key)).await?;
+        if code != 204 && code != 200 {
+            bail!("Failed to delete blob from S3 storage, status {}", code);
+        }
+
+        blob::Entity::delete_many()
+            .filter(blob::Column::UserId.eq(self.session.user_id))
+            .filter(blob::Column::Key.eq(key))
+            .exec(self.conn())
+            .await
+            .context("Failed to delete blob from database")?;
+
+        Ok(())
+    }
+}
+
+pub fn init(maybe_store: Option<BlobStore>) -> Extension {
+    Extension::builder()
+        .js(include_js_files!(
+            prefix "ext/blobs",
+            "01_blobs.js",
+        ))
+        .ops(vec![
+            op_blob_head::decl(),
+            op_blob_delete::decl(),
+            op_blob_list::decl(),
+            stream::op_blob_put_start::decl(),
+            stream::op_blob_put_write::decl(),
+            stream::op_blob_put_finish::decl(),
+            stream::op_blob_get::decl(),
+            stream::op_blob_read::decl(),
+        ])
+        .state(move |state| {
+            if let Some(store) = maybe_store.clone() {
+                state.put::<BlobStore>(store);
+            }
+            Ok(())
+        })
+        .build()
+}
+
+fn check_key(key: &str) -> Result<()> {
+    if key.is_empty() {
+        bail!("Blob keys can't be empty");
+    }
+
+    if key.len() > MAX_KEY_BYTES {
+        bail!(
+            "Blob key is {} bytes, the maximum is {} bytes",
+            key.len(),
+            MAX_KEY_BYTES
+        );
+    }
+
+    Ok(())
+}
+
+fn store(state: &Rc<RefCell<OpState>>) -> BlobStore {
+    state.borrow().borrow::<BlobStore>().clone()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BlobInfo {
+    key: String,
+    size: i64,
+    content_type: String,
+    /// Serialized JSON
+    metadata: Option<String>,
+    /// Milliseconds since the UNIX epoch
+    uploaded: i64,
+}
+
+impl From<blob::Model> for BlobInfo {
+    fn from(blob: blob::Model) -> Self {
+        Self {
+            key: blob.key,
+            size: blob.size,
+            content_type: blob.content_type,
+            metadata: blob.metadata.map(|metadata| metadata.to_string()),
+            uploaded: blob.created_at.timestamp_millis(),
+        }
+    }
+}
+
+#[op]
+async fn op_blob_head(state: Rc<RefCell<OpState>>, key: String) -> Result<Option<BlobInfo>> {
+    let store = store(&state);
+
+    Ok(store.find(&key).await?.map(BlobInfo::from))
+}
+
+#[op]
+async fn op_blob_delete(state: Rc<RefCell<OpState>>, key: String) -> Result<()> {
+    let store = store(&state);
+    if store.find(&key).await?.is_none() {
+        return Ok(());
+    }
+
+    store.remove(&key).await
+}
+
+#[derive(Deserialize)]
+struct ListArgs {
+    prefix: Option<String>,
+    limit: Option<u64>,
+    /// The key of the last object of the previous page
+    cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ListResult {
+    objects: Vec<BlobInfo>,
+    truncated: bool,
+    cursor: Option<String>,
+}
+
+/// Lists objects ordered by key, one page at a time
+#[op]
+async fn op_blob_list(state: Rc<RefCell<OpState>>, args: ListArgs) -> Result<ListResult> {
+    let store = store(&state);
+    let limit = args.limit.unwrap_or(MAX_LIST_LIMIT);
+    if limit == 0 || limit > MAX_LIST_LIMIT {
+        bail!(
+            "The limit of a list has to be between 1 and {}",
+            MAX_LIST_LIMIT
+        );
+    }
+
+    // one extra row tells us whether there is another page
+    let mut blobs = blob::Entity::find()
+        .from_raw_sql(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            SELECT * FROM blobs
+            WHERE user_id = $1 AND starts_with(key, $2) AND key > $3
+            ORDER BY key
+            LIMIT $4
+            "#,
+            vec![
+                store.session.user_id.into(),
+                args.prefix.unwrap_or_default().into(),
+                args.cursor.unwrap_or_default().into(),
+                i64::try_from(limit + 1)?.into(),
+            ],
+        ))
+        .all(store.conn())
+        .await
+        .context("Failed to list blobs from database")?;
+
+    let truncated = blobs.len() as u64 > limit;
+    blobs.truncate(usize::try_from(limit)?);
+    let cursor = if truncated {
+        blobs.last().map(|blob| blob.key.clone())
+    } else {
+        None
+    };
+
+    Ok(ListResult {
+        objects: blobs.into_iter().map(BlobInfo::from).collect(),
+        truncated,
+        cursor,
+    })
+}
@@ -0,0 +1,263 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use deno_core::anyhow::{bail, Context, Result};
+use deno_core::{op, OpState, Resource, ResourceId, ZeroCopyBuf};
+use migration::sea_orm::{ConnectionTrait, DbBackend, Statement};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::{check_key, store, BlobInfo};
+
+/// Size of the pipes between the isolate and S3, and of the chunks handed to the script
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// An upload in progress, the script writes into one end of a pipe while S3 reads the other
+struct BlobUpload {
+    key: String,
+    content_type: String,
+    metadata: Option<String>,
+    /// Bytes that may be written before the quota of the user is exhausted
+    limit: u64,
+    written: Cell<u64>,
+    writer: Mutex<DuplexStream>,
+    task: RefCell<Option<JoinHandle<Result<u16>>>>,
+}
+
+impl Resource for BlobUpload {
+    fn name(&self) -> Cow<str> {
+        "blobUpload".into()
+    }
+}
+
+/// An upload that is dropped before it finished is aborted, S3 discards the parts
+impl Drop for BlobUpload {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.get_mut().take() {
+            task.abort();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PutArgs {
+    key: String,
+    content_type: Option<String>,
+    /// Serialized JSON
+    metadata: Option<String>,
+}
+
+#[op]
+pub(crate) async fn op_blob_put_start(
+    state: Rc<RefCell<OpState>>,
+    args: PutArgs,
+) -> Result<ResourceId> {
+    check_key(&args.key)?;
+    if let Some(metadata) = &args.metadata {
+        deno_core::serde_json::from_str::<deno_core::serde_json::Value>(metadata)
+            .context("Invalid blob metadata")?;
+    }
+
+    let store = store(&state);
+    let limit = store.remaining_quota(&args.key).await?;
+    let bucket = store.bucket()?.clone();
+    let path = store.path(&args.key);
+
+    let (writer, mut reader) = tokio::io::duplex(CHUNK_BYTES);
+    let task = tokio::spawn(async move { bucket.put_object_stream(&mut reader, path).await });
+
+    let upload = BlobUpload {
+        key: args.key,
+        content_type: args
+            .content_type
+            .unwrap_or_else(|| "application/octet-stream".into()),
+        metadata: args.metadata,
+        limit,
+        written: Cell::new(0),
+        writer: Mutex::new(writer),
+        task: RefCell::new(Some(task)),
+    };
+
+    Ok(state.borrow_mut().resource_table.add(upload))
+}
+
+#[op]
+pub(crate) async fn op_blob_put_write(
+    state: Rc<RefCell<OpState>>,
+    rid: ResourceId,
+    chunk: ZeroCopyBuf,
+) -> Result<()> {
+    let upload = state.borrow().resource_table.get::<BlobUpload>(rid)?;
+
+    let written = upload.written.get() + chunk.len() as u64;
+    if written > upload.limit {
+        state.borrow_mut().resource_table.close(rid)?;
+        bail!(
+            "Blob storage quota exceeded, the object may be at most {} bytes",
+            upload.limit
+        );
+    }
+    upload.written.set(written);
+
+    upload
+        .writer
+        .lock()
+        .await
+        .write_all(&chunk)
+        .await
+        .context("Blob upload failed")?;
+
+    Ok(())
+}
+
+/// Waits for S3 to store the object, then records it for the user
+#[op]
+pub(crate) async fn op_blob_put_finish(
+    state: Rc<RefCell<OpState>>,
+    rid: ResourceId,
+) -> Result<BlobInfo> {
+    let upload = state.borrow_mut().resource_table.take::<BlobUpload>(rid)?;
+    let store = store(&state);
+
+    // closing our end of the pipe tells S3 that the object is complete
+    upload.writer.lock().await.shutdown().await?;
+    let task = upload
+        .task
+        .borrow_mut()
+        .take()
+        .context("Blob upload already finished")?;
+    let code = task.await??;
+    if code != 200 {
+        bail!("Failed to put blob into S3 storage, status {}", code);
+    }
+
+    // concurrent uploads could have used the quota in the meantime. The object replaced an
+    // earlier one under the same key, so its row has to go as well
+    let size = upload.written.get();
+    if size > store.remaining_quota(&upload.key).await? {
+        store.remove(&upload.key).await?;
+        bail!("Blob storage quota exceeded, the blob was deleted");
+    }
+
+    let row = store
+        .conn()
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            INSERT INTO blobs (user_id, key, size, content_type, metadata, created_at)
+            VALUES ($1, $2, $3, $4, $5::jsonb, now())
+            ON CONFLICT (user_id, key) DO UPDATE SET
+                size = EXCLUDED.size,
+                content_type = EXCLUDED.content_type,
+                metadata = EXCLUDED.metadata,
+                created_at = EXCLUDED.created_at
+            RETURNING created_at
+            "#,
+            vec![
+                store.session.user_id.into(),
+                upload.key.clone().into(),
+                i64::try_from(size)?.into(),
+                upload.content_type.clone().into(),
+                upload.metadata.clone().into(),
+            ],
+        ))
+        .await
+        .context("Failed to write blob to database")?
+        .context("Failed to write blob to database")?;
+    let uploaded: chrono::DateTime<chrono::FixedOffset> = row.try_get("", "created_at")?;
+
+    Ok(BlobInfo {
+        key: upload.key.clone(),
+        size: i64::try_from(size)?,
+        content_type: upload.content_type.clone(),
+        metadata: upload.metadata.clone(),
+        uploaded: uploaded.timestamp_millis(),
+    })
+}
+
+/// A download in progress, S3 writes into one end of a pipe while the script reads the other
+struct BlobDownload {
+    reader: Mutex<DuplexStream>,
+    task: RefCell<Option<JoinHandle<Result<u16>>>>,
+}
+
+impl Resource for BlobDownload {
+    fn name(&self) -> Cow<str> {
+        "blobDownload".into()
+    }
+}
+
+impl Drop for BlobDownload {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.get_mut().take() {
+            task.abort();
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct BlobBody {
+    #[serde(flatten)]
+    info: BlobInfo,
+    rid: ResourceId,
+}
+
+#[op]
+pub(crate) async fn op_blob_get(
+    state: Rc<RefCell<OpState>>,
+    key: String,
+) -> Result<Option<BlobBody>> {
+    let store = store(&state);
+    let blob = match store.find(&key).await? {
+        Some(blob) => blob,
+        None => return Ok(None),
+    };
+
+    let bucket = store.bucket()?.clone();
+    let path = store.path(&key);
+    let (mut writer, reader) = tokio::io::duplex(CHUNK_BYTES);
+    let task = tokio::spawn(async move { bucket.get_object_stream(path, &mut writer).await });
+
+    let download = BlobDownload {
+        reader: Mutex::new(reader),
+        task: RefCell::new(Some(task)),
+    };
+    let rid = state.borrow_mut().resource_table.add(download);
+
+    Ok(Some(BlobBody {
+        info: blob.into(),
+        rid,
+    }))
+}
+
+/// Returns the next chunk of a download, `None` once the object was read completely
+#[op]
+pub(crate) async fn op_blob_read(
+    state: Rc<RefCell<OpState>>,
+    rid: ResourceId,
+) -> Result<Option<ZeroCopyBuf>> {
+    let download = state.borrow().resource_table.get::<BlobDownload>(rid)?;
+
+    let mut chunk = vec![0; CHUNK_BYTES];
+    let read = download.reader.lock().await.read(&mut chunk).await?;
+    if read > 0 {
+        chunk.truncate(read);
+        return Ok(Some(chunk.into()));
+    }
+
+    // the pipe only ends once S3 is done, which might have been an error
+    state.borrow_mut().resource_table.close(rid)?;
+    let task = download.task.borrow_mut().take();
+    if let Some(task) = task {
+        let code = task.await??;
+        if code != 200 {
+            bail!("Failed to get blob from S3 storage, status {}", code);
+        }
+    }
+
+    Ok(None)
+}
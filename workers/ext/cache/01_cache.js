@@ -0,0 +1,120 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+
+  /**
+   * @param {Request | string | URL} request
+   * @returns {Request}
+   */
+  function toRequest(request) {
+    return request instanceof Request ? request : new Request(request);
+  }
+
+  /**
+   * @param {string} cacheName
+   * @param {Request} request
+   */
+  function cacheRequest(cacheName, request) {
+    // fragments never reach the server, so they don't make a different response
+    const url = new URL(request.url);
+    url.hash = "";
+
+    return {
+      cacheName,
+      url: url.href,
+      headers: [...request.headers],
+    };
+  }
+
+  /**
+   * Responses keyed on the URL of the request and the request headers named by `Vary`.
+   * Responses are only stored while `Cache-Control` or `Expires` allows a shared cache to keep them
+   */
+  class Cache {
+    #name;
+
+    /**
+     * @param {string} name
+     */
+    constructor(name) {
+      this.#name = name;
+    }
+
+    /**
+     * @param {Request | string | URL} request
+     * @param {{ ignoreMethod?: boolean }} [options]
+     * @returns {Promise<Response | undefined>}
+     */
+    async match(request, options = {}) {
+      request = toRequest(request);
+      if (request.method !== "GET" && !options.ignoreMethod) {
+        return undefined;
+      }
+
+      const cached = await core.opAsync("op_cache_match", cacheRequest(this.#name, request));
+      if (cached === null) {
+        return undefined;
+      }
+
+      return new Response(cached.body, {
+        status: cached.status,
+        headers: cached.headers,
+      });
+    }
+
+    /**
+     * Resolves once the response was stored, or right away if its headers don't allow it
+     *
+     * @param {Request | string | URL} request
+     * @param {Response} response
+     * @returns {Promise<void>}
+     */
+    async put(request, response) {
+      request = toRequest(request);
+      if (request.method !== "GET") {
+        throw new TypeError("Only responses to GET requests can be cached");
+      }
+      if (response.status === 206) {
+        throw new TypeError("Partial responses can't be cached");
+      }
+      if (response.bodyUsed) {
+        throw new TypeError("The body of the response was already read");
+      }
+
+      await core.opAsync("op_cache_put", cacheRequest(this.#name, request), {
+        status: response.status,
+        headers: [...response.headers],
+        body: new Uint8Array(await response.arrayBuffer()),
+      });
+    }
+
+    /**
+     * @param {Request | string | URL} request
+     * @param {{ ignoreMethod?: boolean }} [options]
+     * @returns {Promise<boolean>} whether a response was deleted
+     */
+    async delete(request, options = {}) {
+      request = toRequest(request);
+      if (request.method !== "GET" && !options.ignoreMethod) {
+        return false;
+      }
+
+      return await core.opAsync("op_cache_delete", cacheRequest(this.#name, request));
+    }
+  }
+
+  class CacheStorage {
+    default = new Cache("default");
+
+    /**
+     * @param {string} name
+     * @returns {Promise<Cache>}
+     */
+    async open(name) {
+      return new Cache(String(name));
+    }
+  }
+
+  window.caches = new CacheStorage();
+})(this);
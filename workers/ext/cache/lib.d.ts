@@ -0,0 +1,27 @@
+declare global {
+  interface CacheQueryOptions {
+    /** Match requests of every method instead of only GET */
+    ignoreMethod?: boolean,
+  }
+
+  /**
+   * Responses keyed on the URL of the request and the request headers named by `Vary`.
+   * A put only stores the response while `Cache-Control` or `Expires` allows a shared
+   * cache to keep it, responses with `no-store`, `no-cache`, `private` or `Set-Cookie` are skipped
+   */
+  interface Cache {
+    match: (request: Request | string | URL, options?: CacheQueryOptions) => Promise<Response | undefined>,
+    put: (request: Request | string | URL, response: Response) => Promise<void>,
+    delete: (request: Request | string | URL, options?: CacheQueryOptions) => Promise<boolean>,
+  }
+
+  /**
+   * Kept in memory per app by default, in Postgres with `CACHE_BACKEND=postgres`
+   */
+  var caches: {
+    default: Cache,
+    open: (name: string) => Promise<Cache>,
+  }
+}
+
+export { };
@@ -0,0 +1,234 @@
+use std::{cell::RefCell, rc::Rc};
+
+use chrono::{DateTime, Utc};
+use config::CacheBackendKind;
+use deno_core::{anyhow::Result, include_js_files, op, Extension, OpState, ZeroCopyBuf};
+use serde::{Deserialize, Serialize};
+use session::Session;
+
+mod memory;
+pub mod policy;
+mod postgres;
+
+/// Bigger responses are never cached
+const MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
+/// Longer URLs are never cached, they wouldn't fit into an index
+const MAX_URL_BYTES: usize = 2048;
+
+/// A cached response together with the request headers it was stored for
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    /// The values the request had for the headers named by `Vary`
+    pub(crate) vary: Vec<(String, Option<String>)>,
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+    pub(crate) stored_at: DateTime<Utc>,
+    pub(crate) expires_at: DateTime<Utc>,
+}
+
+impl Entry {
+    pub(crate) fn matches(&self, request_headers: &[(String, String)]) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| policy::header(request_headers, name) == *value)
+    }
+
+    pub(crate) fn is_fresh(&self) -> bool {
+        self.expires_at > Utc::now()
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.body.len()
+            + self
+                .headers
+                .iter()
+                .map(|(name, value)| name.len() + value.len())
+                .sum::<usize>()
+    }
+
+    /// Identifies the variant, a put replaces the entry with the same variant
+    pub(crate) fn variant(&self) -> String {
+        deno_core::serde_json::to_string(&self.vary).unwrap()
+    }
+}
+
+/// Where the responses of the isolate are cached, chosen by the cache settings of the session
+#[derive(Clone)]
+enum Store {
+    /// Shared by the isolates of an app, lost when the process exits
+    Memory {
+        user_id: i32,
+        /// Bytes of responses kept for the app
+        budget: usize,
+    },
+    Postgres(Session),
+}
+
+impl Store {
+    fn new(session: Session) -> Self {
+        match session.cache.backend {
+            CacheBackendKind::Memory => Self::Memory {
+                user_id: session.user_id,
+                budget: session.cache.memory_bytes,
+            },
+            CacheBackendKind::Postgres => Self::Postgres(session),
+        }
+    }
+
+    async fn find(&self, cache_name: &str, url: &str) -> Result<Vec<Entry>> {
+        match self {
+            Self::Memory { user_id, .. } => Ok(memory::find(*user_id, cache_name, url)),
+            Self::Postgres(session) => postgres::find(session, cache_name, url).await,
+        }
+    }
+
+    async fn put(&self, cache_name: &str, url: &str, entry: Entry) -> Result<()> {
+        match self {
+            Self::Memory { user_id, budget } => {
+                memory::put(*user_id, *budget, cache_name, url, entry);
+                Ok(())
+            }
+            Self::Postgres(session) => postgres::put(session, cache_name, url, entry).await,
+        }
+    }
+
+    /// Removes the variants matching the request headers, returns whether there were any
+    async fn delete(
+        &self,
+        cache_name: &str,
+        url: &str,
+        request_headers: &[(String, String)],
+    ) -> Result<bool> {
+        match self {
+            Self::Memory { user_id, .. } => {
+                Ok(memory::delete(*user_id, cache_name, url, request_headers))
+            }
+            Self::Postgres(session) => {
+                postgres::delete(session, cache_name, url, request_headers).await
+            }
+        }
+    }
+}
+
+pub fn init(maybe_session: Option<Session>) -> Extension {
+    Extension::builder()
+        .js(include_js_files!(
+            prefix "ext/cache",
+            "01_cache.js",
+        ))
+        .ops(vec![
+            op_cache_match::decl(),
+            op_cache_put::decl(),
+            op_cache_delete::decl(),
+        ])
+        .state(move |state| {
+            if let Some(session) = maybe_session.clone() {
+                state.put::<Store>(Store::new(session));
+            }
+            Ok(())
+        })
+        .build()
+}
+
+fn store(state: &Rc<RefCell<OpState>>) -> Store {
+    state.borrow().borrow::<Store>().clone()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheRequest {
+    cache_name: String,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct PutResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: ZeroCopyBuf,
+}
+
+#[derive(Serialize)]
+struct MatchResult {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: ZeroCopyBuf,
+}
+
+/// The freshest variant matching the request headers
+#[op]
+async fn op_cache_match(
+    state: Rc<RefCell<OpState>>,
+    request: CacheRequest,
+) -> Result<Option<MatchResult>> {
+    let entries = store(&state)
+        .find(&request.cache_name, &request.url)
+        .await?;
+    let entry = entries
+        .into_iter()
+        .filter(|entry| entry.is_fresh() && entry.matches(&request.headers))
+        .max_by_key(|entry| entry.stored_at);
+
+    Ok(entry.map(|entry| {
+        let age = (Utc::now() - entry.stored_at).num_seconds().max(0);
+        let mut headers = entry.headers;
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("age"));
+        headers.push(("age".into(), age.to_string()));
+
+        MatchResult {
+            status: entry.status,
+            headers,
+            body: entry.body.into(),
+        }
+    }))
+}
+
+/// Stores the response if its headers allow it, returns whether it was stored
+#[op]
+async fn op_cache_put(
+    state: Rc<RefCell<OpState>>,
+    request: CacheRequest,
+    response: PutResponse,
+) -> Result<bool> {
+    let lifetime = match policy::lifetime(response.status, &response.headers) {
+        Some(lifetime) => lifetime,
+        None => return Ok(false),
+    };
+    let names = match policy::vary(&response.headers) {
+        Some(names) => names,
+        None => return Ok(false),
+    };
+    if response.body.len() > MAX_BODY_BYTES || request.url.len() > MAX_URL_BYTES {
+        return Ok(false);
+    }
+
+    let now = Utc::now();
+    let entry = Entry {
+        vary: names
+            .into_iter()
+            .map(|name| {
+                let value = policy::header(&request.headers, &name);
+                (name, value)
+            })
+            .collect(),
+        status: response.status,
+        headers: response.headers,
+        body: response.body.to_vec(),
+        stored_at: now,
+        expires_at: policy::expires_at(now, lifetime),
+    };
+    store(&state)
+        .put(&request.cache_name, &request.url, entry)
+        .await?;
+
+    Ok(true)
+}
+
+#[op]
+async fn op_cache_delete(state: Rc<RefCell<OpState>>, request: CacheRequest) -> Result<bool> {
+    store(&state)
+        .delete(&request.cache_name, &request.url, &request.headers)
+        .await
+}
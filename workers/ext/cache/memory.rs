@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lru_cache::LruCache;
+use once_cell::sync::Lazy;
+
+use crate::Entry;
+
+/// The caches of every app in this process by user id
+static APPS: Lazy<Mutex<HashMap<i32, AppCache>>> = Lazy::new(Default::default);
+
+/// Variants by cache name and URL, the least recently used URLs are evicted once the
+/// responses of the app take up more than the budget
+struct AppCache {
+    entries: LruCache<(String, String), Vec<Entry>>,
+    bytes: usize,
+}
+
+impl Default for AppCache {
+    fn default() -> Self {
+        Self {
+            entries: LruCache::new(usize::MAX),
+            bytes: 0,
+        }
+    }
+}
+
+impl AppCache {
+    fn remove(&mut self, key: &(String, String)) -> Option<Vec<Entry>> {
+        let entries = self.entries.remove(key)?;
+        self.bytes -= entries.iter().map(Entry::size).sum::<usize>();
+        Some(entries)
+    }
+
+    fn insert(&mut self, key: (String, String), entries: Vec<Entry>, budget: usize) {
+        if entries.is_empty() {
+            return;
+        }
+
+        self.bytes += entries.iter().map(Entry::size).sum::<usize>();
+        self.entries.insert(key, entries);
+
+        while self.bytes > budget {
+            match self.entries.remove_lru() {
+                Some((_, evicted)) => {
+                    self.bytes -= evicted.iter().map(Entry::size).sum::<usize>();
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+pub fn find(user_id: i32, cache_name: &str, url: &str) -> Vec<Entry> {
+    let mut apps = APPS.lock().unwrap();
+    let app = match apps.get_mut(&user_id) {
+        Some(app) => app,
+        None => return vec![],
+    };
+
+    // marks the URL as recently used
+    app.entries
+        .get_mut(&(cache_name.to_string(), url.to_string()))
+        .map(|entries| entries.clone())
+        .unwrap_or_default()
+}
+
+pub fn put(user_id: i32, budget: usize, cache_name: &str, url: &str, entry: Entry) {
+    let mut apps = APPS.lock().unwrap();
+    let app = apps.entry(user_id).or_default();
+
+    let key = (cache_name.to_string(), url.to_string());
+    let variant = entry.variant();
+    let mut entries: Vec<Entry> = app
+        .remove(&key)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|cached| cached.is_fresh() && cached.variant() != variant)
+        .collect();
+    entries.push(entry);
+
+    app.insert(key, entries, budget);
+}
+
+pub fn delete(
+    user_id: i32,
+    cache_name: &str,
+    url: &str,
+    request_headers: &[(String, String)],
+) -> bool {
+    let mut apps = APPS.lock().unwrap();
+    let app = match apps.get_mut(&user_id) {
+        Some(app) => app,
+        None => return false,
+    };
+
+    let key = (cache_name.to_string(), url.to_string());
+    let (deleted, kept): (Vec<Entry>, Vec<Entry>) = app
+        .remove(&key)
+        .unwrap_or_default()
+        .into_iter()
+        .partition(|cached| cached.matches(request_headers));
+    // only ever shrinks, nothing is evicted
+    app.insert(key, kept, usize::MAX);
+
+    !deleted.is_empty()
+}
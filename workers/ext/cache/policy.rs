@@ -0,0 +1,93 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// Responses are stored at most this long, whatever their headers say
+const MAX_LIFETIME_SECONDS: i64 = 365 * 24 * 60 * 60;
+
+/// Joins repeated headers like `Headers.get` does
+pub fn header(headers: &[(String, String)], name: &str) -> Option<String> {
+    let values: Vec<&str> = headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+        .collect();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
+}
+
+/// How long a response may be served from a shared cache, `None` if it mustn't be stored.
+/// `s-maxage` wins over `max-age`, which wins over `Expires`. Lifetimes are capped at a year
+pub fn lifetime(status: u16, headers: &[(String, String)]) -> Option<Duration> {
+    // partial content can't be combined and cookies belong to a single client
+    if status == 206 || header(headers, "set-cookie").is_some() {
+        return None;
+    }
+
+    let mut max_age = None;
+    let mut s_maxage = None;
+    for directive in header(headers, "cache-control")
+        .unwrap_or_default()
+        .split(',')
+    {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (directive.trim(), None),
+        };
+
+        match name.to_ascii_lowercase().as_str() {
+            "no-store" | "no-cache" | "private" => return None,
+            "max-age" => max_age = value.and_then(|value| value.parse::<i64>().ok()),
+            "s-maxage" => s_maxage = value.and_then(|value| value.parse::<i64>().ok()),
+            _ => {}
+        }
+    }
+
+    // `Duration::seconds` panics far below `i64::MAX`, so the seconds are clamped first
+    let lifetime = match s_maxage.or(max_age) {
+        Some(seconds) => Duration::seconds(seconds.clamp(0, MAX_LIFETIME_SECONDS)),
+        None => {
+            let expires = header(headers, "expires")?;
+            let lifetime = DateTime::parse_from_rfc2822(&expires).ok()? - Utc::now();
+            lifetime.min(Duration::seconds(MAX_LIFETIME_SECONDS))
+        }
+    };
+
+    if lifetime > Duration::zero() {
+        Some(lifetime)
+    } else {
+        None
+    }
+}
+
+/// When a response stored now with the given lifetime expires
+pub fn expires_at(now: DateTime<Utc>, lifetime: Duration) -> DateTime<Utc> {
+    now.checked_add_signed(lifetime).unwrap_or(now)
+}
+
+/// The headers a cached response varies on, `None` if it varies on everything and can't be
+/// matched at all
+pub fn vary(headers: &[(String, String)]) -> Option<Vec<String>> {
+    let vary = match header(headers, "vary") {
+        Some(vary) => vary,
+        None => return Some(vec![]),
+    };
+
+    let mut names = vec![];
+    for name in vary
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if name == "*" {
+            return None;
+        }
+        names.push(name.to_ascii_lowercase());
+    }
+    names.sort();
+    names.dedup();
+
+    Some(names)
+}
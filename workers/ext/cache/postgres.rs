@@ -0,0 +1,110 @@
+use deno_core::anyhow::{Context, Result};
+use deno_core::serde_json;
+use entity::cache_entry;
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{ColumnTrait, Condition, ConnectionTrait, EntityTrait, QueryFilter};
+use session::Session;
+
+use crate::Entry;
+
+impl TryFrom<cache_entry::Model> for Entry {
+    type Error = deno_core::anyhow::Error;
+
+    fn try_from(model: cache_entry::Model) -> Result<Self> {
+        Ok(Self {
+            vary: serde_json::from_value(model.vary)?,
+            status: u16::try_from(model.status)?,
+            headers: serde_json::from_value(model.headers)?,
+            body: model.body,
+            stored_at: model.created_at.into(),
+            expires_at: model.expires_at.into(),
+        })
+    }
+}
+
+fn url_condition(session: &Session, cache_name: &str, url: &str) -> Condition {
+    Condition::all()
+        .add(cache_entry::Column::UserId.eq(session.user_id))
+        .add(cache_entry::Column::CacheName.eq(cache_name))
+        .add(cache_entry::Column::Url.eq(url))
+}
+
+pub async fn find(session: &Session, cache_name: &str, url: &str) -> Result<Vec<Entry>> {
+    let models = cache_entry::Entity::find()
+        .filter(url_condition(session, cache_name, url))
+        .filter(cache_entry::Column::ExpiresAt.gt(chrono::Utc::now()))
+        .all(&session.conn)
+        .await
+        .context("Failed to get cache entries from database")?;
+
+    models.into_iter().map(Entry::try_from).collect()
+}
+
+/// Replaces the entry of the same variant, expired variants of the URL are dropped on the way
+pub async fn put(session: &Session, cache_name: &str, url: &str, entry: Entry) -> Result<()> {
+    let vary = serde_json::to_value(&entry.vary)?;
+    let txn = session.conn.begin().await?;
+
+    cache_entry::Entity::delete_many()
+        .filter(url_condition(session, cache_name, url))
+        .filter(
+            Condition::any()
+                .add(cache_entry::Column::ExpiresAt.lte(chrono::Utc::now()))
+                .add(cache_entry::Column::Vary.eq(vary.clone())),
+        )
+        .exec(&txn)
+        .await
+        .context("Failed to delete cache entries from database")?;
+
+    cache_entry::Entity::insert(cache_entry::ActiveModel {
+        user_id: Set(session.user_id),
+        cache_name: Set(cache_name.to_string()),
+        url: Set(url.to_string()),
+        vary: Set(vary),
+        status: Set(i32::from(entry.status)),
+        headers: Set(serde_json::to_value(&entry.headers)?),
+        body: Set(entry.body),
+        created_at: Set(entry.stored_at.into()),
+        expires_at: Set(entry.expires_at.into()),
+        ..cache_entry::ActiveModel::default()
+    })
+    .exec(&txn)
+    .await
+    .context("Failed to write cache entry to database")?;
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+pub async fn delete(
+    session: &Session,
+    cache_name: &str,
+    url: &str,
+    request_headers: &[(String, String)],
+) -> Result<bool> {
+    let models = cache_entry::Entity::find()
+        .filter(url_condition(session, cache_name, url))
+        .all(&session.conn)
+        .await
+        .context("Failed to get cache entries from database")?;
+
+    let mut ids = vec![];
+    for model in models {
+        let id = model.id;
+        if Entry::try_from(model)?.matches(request_headers) {
+            ids.push(id);
+        }
+    }
+    if ids.is_empty() {
+        return Ok(false);
+    }
+
+    cache_entry::Entity::delete_many()
+        .filter(cache_entry::Column::Id.is_in(ids))
+        .exec(&session.conn)
+        .await
+        .context("Failed to delete cache entries from database")?;
+
+    Ok(true)
+}
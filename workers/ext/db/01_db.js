@@ -0,0 +1,98 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+
+  /**
+   * Thrown when a statement fails, e.g. because of invalid SQL, a timeout or too many rows.
+   * Errors reported by Postgres start with their SQLSTATE, which is available as `code`
+   */
+  class SqlError extends Error {
+    /**
+     * @param {string} message
+     */
+    constructor(message) {
+      super(message);
+      this.name = "SqlError";
+      this.code = /^([0-9A-Z]{5}): /.exec(message)?.[1] ?? null;
+    }
+  }
+
+  core.registerErrorClass("SqlError", SqlError);
+
+  /**
+   * @param {any} value
+   */
+  function encodeParam(value) {
+    if (value === undefined) {
+      return null;
+    }
+    if (value instanceof Date) {
+      return value.toISOString();
+    }
+    return value;
+  }
+
+  /**
+   * A statement with the values of its `$1`, `$2`, ... parameters
+   */
+  class PreparedStatement {
+    #sql;
+    #params;
+
+    /**
+     * @param {string} sql
+     * @param {any[]} params
+     */
+    constructor(sql, params) {
+      this.#sql = sql;
+      this.#params = params;
+    }
+
+    /**
+     * Statements are immutable, binding returns a new one
+     *
+     * @param {...any} params
+     * @returns {PreparedStatement}
+     */
+    bind(...params) {
+      return new PreparedStatement(this.#sql, params.map(encodeParam));
+    }
+
+    /**
+     * @returns {Promise<Record<string, any>[]>}
+     */
+    all() {
+      return core.opAsync("op_db_all", this.#sql, this.#params);
+    }
+
+    /**
+     * @param {string} [column] only return the value of this column
+     * @returns {Promise<any | null>}
+     */
+    async first(column) {
+      const [row] = await this.all();
+      if (row === undefined) {
+        return null;
+      }
+      return column === undefined ? row : row[column];
+    }
+
+    /**
+     * @returns {Promise<{ changes: number }>}
+     */
+    async run() {
+      const changes = await core.opAsync("op_db_run", this.#sql, this.#params);
+      return { changes };
+    }
+  }
+
+  window.db = {
+    /**
+     * @param {string} sql
+     * @returns {PreparedStatement}
+     */
+    prepare: (sql) => new PreparedStatement(sql, []),
+  };
+  window.SqlError = SqlError;
+})(this);
@@ -0,0 +1,31 @@
+declare global {
+  interface PreparedStatement {
+    /**
+     * Values for `$1`, `$2`, ... typed by their JavaScript type, dates are sent as strings.
+     * Cast them in SQL where Postgres expects another type, e.g. `$1::date`
+     */
+    bind: (...params: any[]) => PreparedStatement,
+    /** Rows as objects, statements returning more than `DB_MAX_ROWS` rows fail */
+    all: <Row = Record<string, any>>() => Promise<Row[]>,
+    first: <T = Record<string, any>>(column?: string) => Promise<T | null>,
+    run: () => Promise<{ changes: number }>,
+  }
+
+  /**
+   * Thrown when a statement fails, e.g. because of invalid SQL, a timeout or too many rows
+   */
+  class SqlError extends Error {
+    /** The SQLSTATE of errors reported by Postgres, e.g. `23505` for a unique violation */
+    readonly code: string | null;
+  }
+
+  /**
+   * The Postgres schema of the app, also available as `env.db`.
+   * Every statement runs in its own transaction
+   */
+  var db: {
+    prepare: (sql: string) => PreparedStatement,
+  }
+}
+
+export { };
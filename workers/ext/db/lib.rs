@@ -0,0 +1,84 @@
+use std::{cell::RefCell, rc::Rc};
+
+use deno_core::anyhow::Context;
+use deno_core::error::AnyError;
+use deno_core::serde_json::Value;
+use deno_core::{anyhow::Result, include_js_files, op, Extension, OpState};
+use session::Session;
+use storage::sql::{self, AppDatabase, Limits};
+use tokio::sync::OnceCell;
+
+pub use storage::sql::SqlError;
+
+/// The schema of the app and the limits its statements run with
+#[derive(Clone)]
+struct Database {
+    session: Session,
+    limits: Limits,
+    /// Connected on the first statement, apps without a database never connect
+    connection: Rc<OnceCell<AppDatabase>>,
+}
+
+impl Database {
+    async fn connection(&self) -> Result<&AppDatabase> {
+        let database_url = self
+            .session
+            .database_url
+            .as_deref()
+            .context("Databases are unavailable without DATABASE_URL")?;
+
+        self.connection
+            .get_or_try_init(|| {
+                sql::connect(&self.session.conn, database_url, self.session.user_id)
+            })
+            .await
+    }
+}
+
+pub fn init(maybe_session: Option<Session>) -> Extension {
+    Extension::builder()
+        .js(include_js_files!(
+            prefix "ext/db",
+            "01_db.js",
+        ))
+        .ops(vec![op_db_all::decl(), op_db_run::decl()])
+        .state(move |state| {
+            if let Some(session) = maybe_session.clone() {
+                state.put::<Database>(Database {
+                    limits: session.db.into(),
+                    session,
+                    connection: Rc::default(),
+                });
+            }
+            Ok(())
+        })
+        .build()
+}
+
+/// Maps a [`SqlError`] to the JS class registered by `01_db.js`
+pub fn get_error_class_name(e: &AnyError) -> Option<&'static str> {
+    e.downcast_ref::<SqlError>().map(|_| "SqlError")
+}
+
+fn database(state: &Rc<RefCell<OpState>>) -> Database {
+    state.borrow().borrow::<Database>().clone()
+}
+
+#[op]
+async fn op_db_all(
+    state: Rc<RefCell<OpState>>,
+    sql: String,
+    params: Vec<Value>,
+) -> Result<Vec<Value>> {
+    let db = database(&state);
+
+    sql::query(db.connection().await?, &sql, params, db.limits).await
+}
+
+/// Returns the number of changed rows
+#[op]
+async fn op_db_run(state: Rc<RefCell<OpState>>, sql: String, params: Vec<Value>) -> Result<u64> {
+    let db = database(&state);
+
+    sql::execute(db.connection().await?, &sql, params, db.limits).await
+}
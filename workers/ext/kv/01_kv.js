@@ -4,56 +4,317 @@
   const core = window.Deno.core;
 
   /**
-   * @param {string} key 
-   * @param {string} value 
-   * 
-   * @returns {Promise<void>}
+   * Thrown when a write is rejected, e.g. because the key, value or metadata is too big
    */
-  function set(key, value) {
-    return core.opAsync("op_kv_set", key, value);
+  class KvError extends Error {
+    /**
+     * @param {string} message
+     */
+    constructor(message) {
+      super(message);
+      this.name = "KvError";
+    }
+  }
+
+  core.registerErrorClass("KvError", KvError);
+
+  /**
+   * @typedef {string | ArrayBuffer | ArrayBufferView | any} KvValue
+   */
+
+  /**
+   * Tags a value with its type so it is stored in the matching column
+   *
+   * @param {KvValue} value
+   */
+  function encodeValue(value) {
+    if (typeof value === "string") {
+      return { type: "text", text: value };
+    }
+
+    if (value instanceof ArrayBuffer) {
+      return { type: "bytes", bytes: new Uint8Array(value) };
+    }
+
+    if (ArrayBuffer.isView(value)) {
+      return { type: "bytes", bytes: new Uint8Array(value.buffer, value.byteOffset, value.byteLength) };
+    }
+
+    const json = JSON.stringify(value);
+    if (json === undefined) {
+      throw new TypeError("The value can't be stored in KV");
+    }
+
+    return { type: "json", json };
   }
 
   /**
-   * @param {string} key 
-   * 
-   * @returns {Promise<string | null>}
+   * @param {any} metadata
+   * @returns {string | undefined}
    */
-  function get(key) {
-    return core.opAsync("op_kv_get", key);
+  function encodeMetadata(metadata) {
+    return metadata === undefined ? undefined : JSON.stringify(metadata);
   }
 
   /**
-   * @param {string} key 
-   * 
-   * @returns {Promise<void>}
+   * @param {{ type: "text" | "json" | "bytes", text?: string, json?: string, bytes?: Uint8Array } | null} value
+   * @returns {KvValue | null}
    */
-  function _delete(key) {
-    return core.opAsync("op_kv_delete", key);
+  function decodeValue(value) {
+    if (value === null || value === undefined) {
+      return null;
+    }
+
+    switch (value.type) {
+      case "text":
+        return value.text;
+      case "json":
+        return JSON.parse(value.json);
+      case "bytes":
+        return value.bytes;
+    }
   }
 
   /**
-   * @returns {Promise<void>}
+   * Checks and mutations that are committed together, nothing is written if a check fails
    */
-  function clear() {
-    return core.opAsync("op_kv_clear");
+  class AtomicOperation {
+    #binding;
+    #checks = [];
+    #mutations = [];
+
+    /**
+     * @param {string} binding
+     */
+    constructor(binding) {
+      this.#binding = binding;
+    }
+
+    /**
+     * @param {...{ key: string, versionstamp: string | null }} checks
+     * 
+     * @returns {AtomicOperation}
+     */
+    check(...checks) {
+      for (const { key, versionstamp } of checks) {
+        this.#checks.push({ key, versionstamp });
+      }
+      return this;
+    }
+
+    /**
+     * @param {string} key 
+     * @param {KvValue} value 
+     * @param {{ expirationTtl?: number, expiration?: number, metadata?: any }} options
+     * 
+     * @returns {AtomicOperation}
+     */
+    set(key, value, options = {}) {
+      this.#mutations.push({
+        type: "set",
+        key,
+        value: encodeValue(value),
+        expirationTtl: options.expirationTtl,
+        expiration: options.expiration,
+        metadata: encodeMetadata(options.metadata),
+      });
+      return this;
+    }
+
+    /**
+     * @param {string} key 
+     * 
+     * @returns {AtomicOperation}
+     */
+    delete(key) {
+      this.#mutations.push({ type: "delete", key });
+      return this;
+    }
+
+    /**
+     * @param {string} key 
+     * @param {number} value 
+     * 
+     * @returns {AtomicOperation}
+     */
+    sum(key, value) {
+      if (!Number.isSafeInteger(value)) {
+        throw new TypeError("sum only accepts integers");
+      }
+
+      this.#mutations.push({ type: "sum", key, delta: value });
+      return this;
+    }
+
+    /**
+     * @returns {Promise<{ ok: boolean, conflicts: string[] }>}
+     */
+    commit() {
+      return core.opAsync("op_kv_atomic", this.#binding, {
+        checks: this.#checks,
+        mutations: this.#mutations,
+      });
+    }
+  }
+
+  /**
+   * A KV namespace bound to the script under a chosen name
+   */
+  class KvNamespace {
+    #binding;
+
+    /**
+     * @param {string} binding
+     */
+    constructor(binding) {
+      this.#binding = binding;
+    }
+
+    /**
+     * @param {string} key 
+     * @param {KvValue} value 
+     * @param {{ expirationTtl?: number, expiration?: number, metadata?: any }} options
+     * 
+     * @returns {Promise<void>}
+     */
+    set(key, value, options = {}) {
+      return core.opAsync("op_kv_set", this.#binding, {
+        key,
+        value: encodeValue(value),
+        expirationTtl: options.expirationTtl,
+        expiration: options.expiration,
+        metadata: encodeMetadata(options.metadata),
+      });
+    }
+
+    /**
+     * @param {string} key 
+     * 
+     * @returns {Promise<KvValue | null>}
+     */
+    async get(key) {
+      return decodeValue(await core.opAsync("op_kv_get", this.#binding, key));
+    }
+
+    /**
+     * @param {string} key 
+     * 
+     * @returns {Promise<{ key: string, value: KvValue | null, metadata: any, versionstamp: string | null }>}
+     */
+    async getEntry(key) {
+      const entry = await core.opAsync("op_kv_get_entry", this.#binding, key);
+      return {
+        key,
+        value: decodeValue(entry.value),
+        metadata: entry.metadata === null ? null : JSON.parse(entry.metadata),
+        versionstamp: entry.versionstamp,
+      };
+    }
+
+    /**
+     * @param {string} key 
+     * 
+     * @returns {Promise<void>}
+     */
+    delete(key) {
+      return core.opAsync("op_kv_delete", this.#binding, key);
+    }
+
+    /**
+     * @returns {Promise<void>}
+     */
+    clear() {
+      return core.opAsync("op_kv_clear", this.#binding);
+    }
+
+    /**
+     * @returns {AtomicOperation}
+     */
+    atomic() {
+      return new AtomicOperation(this.#binding);
+    }
+
+    /**
+     * @param {{ prefix?: string, limit?: number, cursor?: string }} options
+     * 
+     * @returns {Promise<{ keys: { name: string, expiration: number | null, metadata: any }[], list_complete: boolean, cursor: string | null }>}
+     */
+    async list(options = {}) {
+      const page = await core.opAsync("op_kv_list", this.#binding, {
+        prefix: options.prefix,
+        limit: options.limit,
+        cursor: options.cursor,
+      });
+
+      return {
+        ...page,
+        keys: page.keys.map(({ name, expiration, metadata }) => ({
+          name,
+          expiration,
+          metadata: metadata === null ? null : JSON.parse(metadata),
+        })),
+      };
+    }
+
+    /**
+     * Walks every page of the namespace
+     * 
+     * @param {boolean} includeValues
+     */
+    async *#listAll(includeValues) {
+      let cursor = undefined;
+      let complete = false;
+
+      while (!complete) {
+        const page = await core.opAsync("op_kv_list", this.#binding, { cursor, includeValues });
+        yield* page.keys;
+
+        cursor = page.cursor;
+        complete = page.list_complete;
+      }
+    }
+
+    /**
+     * @returns {Promise<string[]>}
+     */
+    async keys() {
+      const keys = [];
+      for await (const key of this.#listAll(false)) {
+        keys.push(key.name);
+      }
+      return keys;
+    }
+
+    /**
+     * @returns {Promise<KvValue[]>}
+     */
+    async values() {
+      const values = [];
+      for await (const key of this.#listAll(true)) {
+        values.push(decodeValue(key.value));
+      }
+      return values;
+    }
+
+    /**
+     * @returns {Promise<[string, KvValue][]>}
+     */
+    async entries() {
+      const entries = [];
+      for await (const key of this.#listAll(true)) {
+        entries.push([key.name, decodeValue(key.value)]);
+      }
+      return entries;
+    }
   }
 
-  window.kvStorage = {
-    set,
-    get,
-    "delete": _delete,
-    clear,
-    keys: async function () {
-      const all = await core.opAsync("op_kv_all");
-      return Object.keys(all);
-    },
-    values: async function () {
-      const all = await core.opAsync("op_kv_all");
-      return Object.values(all);
-    },
-    entries: async function () {
-      const all = await core.opAsync("op_kv_all");
-      return Object.entries(all);
-    },
+  window._hbw ??= {};
+  window._hbw.kv = {
+    /**
+     * @param {string} binding
+     * @returns {KvNamespace}
+     */
+    namespace: (binding) => new KvNamespace(binding),
   };
+  window.KvError = KvError;
 })(this);
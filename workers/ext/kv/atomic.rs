@@ -0,0 +1,116 @@
+use std::{cell::RefCell, rc::Rc};
+
+use deno_core::anyhow::{Context, Result};
+use deno_core::{op, OpState};
+use serde::Deserialize;
+use storage::{AtomicResult, Check, Mutation};
+
+use crate::cache::ReadCache;
+use crate::{resolve, KvValue, SetArgs};
+
+#[derive(Deserialize)]
+pub(crate) struct CheckArgs {
+    key: String,
+    /// `null` means the key must not exist
+    versionstamp: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum MutationType {
+    Set,
+    Delete,
+    Sum,
+}
+
+/// Kept flat instead of a tagged enum, serde would buffer a tagged enum and
+/// lose the `Uint8Array` of binary values on the way
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MutationArgs {
+    #[serde(rename = "type")]
+    kind: MutationType,
+    key: String,
+    value: Option<KvValue>,
+    /// Only used by `sum`
+    delta: Option<i64>,
+    expiration_ttl: Option<i64>,
+    expiration: Option<i64>,
+    metadata: Option<String>,
+}
+
+impl MutationArgs {
+    fn into_mutation(self) -> Result<Mutation> {
+        let mutation = match self.kind {
+            MutationType::Set => Mutation::Set(
+                SetArgs {
+                    key: self.key,
+                    value: self.value.context("Missing value for set")?,
+                    expiration_ttl: self.expiration_ttl,
+                    expiration: self.expiration,
+                    metadata: self.metadata,
+                }
+                .into_write()?,
+            ),
+            MutationType::Delete => Mutation::Delete(self.key),
+            MutationType::Sum => {
+                Mutation::Sum(self.key, self.delta.context("Missing value for sum")?)
+            }
+        };
+
+        Ok(mutation)
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AtomicArgs {
+    checks: Vec<CheckArgs>,
+    mutations: Vec<MutationArgs>,
+}
+
+#[op]
+pub(crate) async fn op_kv_atomic(
+    state: Rc<RefCell<OpState>>,
+    binding: String,
+    args: AtomicArgs,
+) -> Result<AtomicResult> {
+    let (backend, namespace_id) = resolve(&state, &binding)?;
+    let mut touched: Vec<String> = args.checks.iter().map(|check| check.key.clone()).collect();
+
+    let checks = args
+        .checks
+        .into_iter()
+        .map(|check| {
+            Ok(Check {
+                key: check.key,
+                version: check
+                    .versionstamp
+                    .as_deref()
+                    .map(str::parse::<i64>)
+                    .transpose()
+                    .context("Invalid versionstamp")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mutations = args
+        .mutations
+        .into_iter()
+        .map(MutationArgs::into_mutation)
+        .collect::<Result<Vec<_>>>()?;
+    touched.extend(mutations.iter().map(|mutation| mutation.key().to_string()));
+
+    let result = backend.atomic(namespace_id, checks, mutations).await;
+
+    // a failed check means the cached item of the key is outdated, and after a write it's
+    // outdated anyway. Retries read the keys from the backend again
+    {
+        let mut state = state.borrow_mut();
+        let cache = state.borrow_mut::<ReadCache>();
+        for key in touched {
+            cache.invalidate(namespace_id, key);
+        }
+    }
+
+    result
+}
@@ -0,0 +1,53 @@
+use lru_cache::LruCache;
+use std::time::{Duration, Instant};
+use storage::Item;
+
+const CAPACITY: usize = 1024;
+/// Writes from other isolates become visible after at most this long
+const MAX_AGE: Duration = Duration::from_secs(10);
+/// Bigger values are always read from the database so the cache stays small
+const MAX_CACHED_VALUE_BYTES: usize = 64 * 1024;
+
+/// Read-through cache of the items of an isolate, writes of the isolate itself invalidate it
+pub struct ReadCache(LruCache<(i32, String), (Instant, Option<Item>)>);
+
+impl Default for ReadCache {
+    fn default() -> Self {
+        Self(LruCache::new(CAPACITY))
+    }
+}
+
+impl ReadCache {
+    /// `None` on a miss, `Some(None)` if the key is known to not exist
+    pub fn get(&mut self, namespace_id: i32, key: &str) -> Option<Option<Item>> {
+        let cache_key = (namespace_id, key.to_string());
+        let (cached_at, item) = self.0.get_mut(&cache_key)?;
+
+        if cached_at.elapsed() > MAX_AGE {
+            self.0.remove(&cache_key);
+            return None;
+        }
+
+        let now = chrono::Utc::now();
+        Some(
+            item.clone()
+                .filter(|item| item.expires_at.map_or(true, |expires_at| expires_at > now)),
+        )
+    }
+
+    pub fn insert(&mut self, namespace_id: i32, key: String, item: Option<Item>) {
+        let size = item.as_ref().map_or(0, |item| item.value.size());
+
+        if size <= MAX_CACHED_VALUE_BYTES {
+            self.0.insert((namespace_id, key), (Instant::now(), item));
+        }
+    }
+
+    pub fn invalidate(&mut self, namespace_id: i32, key: String) {
+        self.0.remove(&(namespace_id, key));
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
@@ -1,13 +1,78 @@
 declare global {
-  var kvStorage: {
-    set: (key: string, value: string) => Promise<void>,
-    get: (key: string) => Promise<string>,
+  /**
+   * Strings and binary data are stored as they are, anything else is stored as JSON.
+   * Values are returned in the type they were stored with
+   */
+  type KvValue = string | ArrayBuffer | ArrayBufferView | any
+  interface KvPutOptions {
+    /** Seconds from now after which the key expires */
+    expirationTtl?: number,
+    /** Seconds since the UNIX epoch at which the key expires */
+    expiration?: number,
+    /** Serializable with `JSON.stringify`, at most 1 KiB */
+    metadata?: any,
+  }
+
+  interface KvListOptions {
+    prefix?: string,
+    /** Between 1 and 1000, defaults to 1000 */
+    limit?: number,
+    /** The cursor of the previous page */
+    cursor?: string,
+  }
+
+  interface KvListResult {
+    keys: { name: string, expiration: number | null, metadata: any }[],
+    list_complete: boolean,
+    cursor: string | null,
+  }
+
+  interface KvEntry {
+    key: string,
+    value: KvValue | null,
+    metadata: any,
+    /** `null` if the key doesn't exist */
+    versionstamp: string | null,
+  }
+
+  interface KvCommitResult {
+    ok: boolean,
+    /** Keys whose check failed */
+    conflicts: string[],
+  }
+
+  interface AtomicOperation {
+    check: (...checks: { key: string, versionstamp: string | null }[]) => AtomicOperation,
+    set: (key: string, value: KvValue, options?: KvPutOptions) => AtomicOperation,
+    delete: (key: string) => AtomicOperation,
+    /** Adds to an integer value, missing keys count as 0 */
+    sum: (key: string, value: number) => AtomicOperation,
+    commit: () => Promise<KvCommitResult>,
+  }
+
+  interface KvNamespace {
+    set: (key: string, value: KvValue, options?: KvPutOptions) => Promise<void>,
+    get: (key: string) => Promise<KvValue | null>,
+    getEntry: (key: string) => Promise<KvEntry>,
     delete: (key: string) => Promise<void>,
     clear: () => Promise<void>,
+    atomic: () => AtomicOperation,
+    list: (options?: KvListOptions) => Promise<KvListResult>,
     keys: () => Promise<string[]>,
-    values: () => Promise<string[]>,
-    entries: () => Promise<[string, string][]>,
+    values: () => Promise<KvValue[]>,
+    entries: () => Promise<[string, KvValue][]>,
   }
+
+  /**
+   * Thrown when a write is rejected, keys are limited to 512 bytes and values to 25 MiB
+   */
+  class KvError extends Error { }
+
+  /**
+   * Bound to the default namespace for every user, other bindings are exposed
+   * as globals and on `env` under their own name
+   */
+  var kvStorage: KvNamespace
 }
 
-export { };
\ No newline at end of file
+export { };
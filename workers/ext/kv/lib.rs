@@ -1,16 +1,26 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::{cell::RefCell, rc::Rc};
 
 use deno_core::anyhow::Context;
+use deno_core::error::AnyError;
 use deno_core::{anyhow::Result, include_js_files, op, Extension, OpState};
-use entity::namespace;
-use entity::store;
-use migration::sea_orm::ActiveValue::Set;
+use entity::kv_binding;
 use migration::sea_orm::ColumnTrait;
 use migration::sea_orm::EntityTrait;
 use migration::sea_orm::QueryFilter;
-use migration::DbErr;
+use serde::{Deserialize, Serialize};
 use session::Session;
+use storage::{Item, KvBackend, ListOptions, Write};
+
+mod atomic;
+mod cache;
+mod value;
+
+pub use storage::KvError;
+pub use value::{KvValue, ValueType};
+
+use cache::ReadCache;
 
 pub fn init(maybe_session: Option<Session>) -> Extension {
     Extension::builder()
@@ -21,154 +31,272 @@ pub fn init(maybe_session: Option<Session>) -> Extension {
         .ops(vec![
             op_kv_set::decl(),
             op_kv_get::decl(),
+            op_kv_get_entry::decl(),
             op_kv_delete::decl(),
             op_kv_clear::decl(),
-            op_kv_all::decl(),
+            op_kv_list::decl(),
+            atomic::op_kv_atomic::decl(),
         ])
         .state(move |state| {
-            if let Some(session) = maybe_session.clone() {
-                state.put::<Session>(session);
+            if let Some(session) = &maybe_session {
+                state.put::<Arc<dyn KvBackend>>(storage::backend(
+                    session.kv.backend,
+                    session.conn.clone(),
+                ));
             }
+            state.put::<ReadCache>(ReadCache::default());
             Ok(())
         })
         .build()
 }
 
-#[op]
-async fn op_kv_set(state: Rc<RefCell<OpState>>, key: String, value: String) -> Result<()> {
-    let session = {
-        let state = state.borrow();
-        state.borrow::<Session>().clone()
-    };
+/// Maps a [`KvError`] to the JS class registered by `01_kv.js`
+pub fn get_error_class_name(e: &AnyError) -> Option<&'static str> {
+    e.downcast_ref::<KvError>().map(|_| "KvError")
+}
 
-    let namespace = get_namespace(&session).await?;
-    let maybe_item = store::Entity::find()
-        .filter(store::Column::Key.eq(key.clone()))
-        .filter(store::Column::NamespaceId.eq(namespace.id))
-        .one(&session.conn)
-        .await?;
+/// The KV bindings of this user by name, resolved to their namespace ids once per isolate
+#[derive(Clone, Default)]
+pub struct Bindings(HashMap<String, i32>);
 
-    if let Some(item) = maybe_item {
-        let mut to_be_updated: store::ActiveModel = item.into();
-        to_be_updated.value = Set(value);
+impl Bindings {
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+}
 
-        store::Entity::update(to_be_updated)
-            .exec(&session.conn)
-            .await?;
+/// Namespace of the `kvStorage` binding when running without a database
+const OFFLINE_NAMESPACE_ID: i32 = 0;
 
-        return Ok(());
+/// Loads the bindings of this user, the runtime puts them into the `OpState` of its isolate.
+/// Without a database there is only the default namespace, bound as `kvStorage`
+pub async fn load_bindings(session: &Session) -> Result<Bindings> {
+    if !session.has_database() {
+        return Ok(Bindings(HashMap::from([(
+            "kvStorage".to_string(),
+            OFFLINE_NAMESPACE_ID,
+        )])));
     }
 
-    let to_be_inserted = store::ActiveModel {
-        key: Set(key),
-        value: Set(value),
-        created_at: Set(chrono::DateTime::into(chrono::Utc::now())),
-        namespace_id: Set(namespace.id),
-        ..Default::default()
-    };
+    let bindings = kv_binding::Entity::find()
+        .filter(kv_binding::Column::UserId.eq(session.user_id))
+        .all(&session.conn)
+        .await
+        .context("Failed to get kv bindings from database")?;
 
-    store::Entity::insert(to_be_inserted)
-        .exec(&session.conn)
-        .await?;
+    Ok(Bindings(
+        bindings
+            .into_iter()
+            .map(|binding| (binding.name, binding.namespace_id))
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SetArgs {
+    pub(crate) key: String,
+    pub(crate) value: KvValue,
+    /// Seconds from now after which the item expires
+    pub(crate) expiration_ttl: Option<i64>,
+    /// Seconds since the UNIX epoch at which the item expires
+    pub(crate) expiration: Option<i64>,
+    /// Serialized JSON
+    pub(crate) metadata: Option<String>,
+}
+
+impl SetArgs {
+    pub(crate) fn into_write(self) -> Result<Write> {
+        let expires_at = storage::expires_at(self.expiration_ttl, self.expiration)?;
+        let metadata = self
+            .metadata
+            .map(|metadata| deno_core::serde_json::from_str(&metadata))
+            .transpose()
+            .context("Invalid kv metadata")?;
+
+        Write::new(self.key, self.value.into_value()?, metadata, expires_at)
+    }
+}
+
+#[op]
+async fn op_kv_set(state: Rc<RefCell<OpState>>, binding: String, args: SetArgs) -> Result<()> {
+    let write = args.into_write()?;
+    let key = write.key().to_string();
+    let (backend, namespace_id) = resolve(&state, &binding)?;
+    backend.put(namespace_id, write).await?;
+
+    state
+        .borrow_mut()
+        .borrow_mut::<ReadCache>()
+        .invalidate(namespace_id, key);
 
     Ok(())
 }
 
 #[op]
-async fn op_kv_get(state: Rc<RefCell<OpState>>, key: String) -> Result<Option<String>> {
-    let session = {
-        let state = state.borrow();
-        state.borrow::<Session>().clone()
-    };
+async fn op_kv_get(
+    state: Rc<RefCell<OpState>>,
+    binding: String,
+    key: String,
+) -> Result<Option<KvValue>> {
+    let item = read(&state, &binding, key).await?;
 
-    let namespace = get_namespace(&session).await?;
-    let store_item = store::Entity::find()
-        .filter(store::Column::Key.eq(key))
-        .filter(store::Column::NamespaceId.eq(namespace.id))
-        .one(&session.conn)
-        .await?;
+    Ok(item.map(|item| item.value.into()))
+}
 
-    if let Some(item) = store_item {
-        return Ok(Some(item.value));
+/// Reads an item through the cache of the isolate
+async fn read(state: &Rc<RefCell<OpState>>, binding: &str, key: String) -> Result<Option<Item>> {
+    let (backend, namespace_id) = resolve(state, binding)?;
+    if let Some(cached) = state
+        .borrow_mut()
+        .borrow_mut::<ReadCache>()
+        .get(namespace_id, &key)
+    {
+        return Ok(cached);
     }
 
-    Ok(None)
+    let item = backend.get(namespace_id, &key).await?;
+
+    state
+        .borrow_mut()
+        .borrow_mut::<ReadCache>()
+        .insert(namespace_id, key, item.clone());
+
+    Ok(item)
 }
 
+#[derive(Serialize)]
+struct Entry {
+    value: Option<KvValue>,
+    /// Serialized JSON
+    metadata: Option<String>,
+    versionstamp: Option<String>,
+}
+
+/// Always reads from the backend, a stale versionstamp would make every check against it fail.
+/// The cache is refreshed with the item on the way
 #[op]
-async fn op_kv_delete(state: Rc<RefCell<OpState>>, key: String) -> Result<()> {
-    let session = {
-        let state = state.borrow();
-        state.borrow::<Session>().clone()
-    };
+async fn op_kv_get_entry(
+    state: Rc<RefCell<OpState>>,
+    binding: String,
+    key: String,
+) -> Result<Entry> {
+    let (backend, namespace_id) = resolve(&state, &binding)?;
+    let item = backend.get(namespace_id, &key).await?;
 
-    let namespace = get_namespace(&session).await?;
+    state
+        .borrow_mut()
+        .borrow_mut::<ReadCache>()
+        .insert(namespace_id, key, item.clone());
 
-    let maybe_item = store::Entity::find()
-        .filter(store::Column::Key.eq(key))
-        .filter(store::Column::NamespaceId.eq(namespace.id))
-        .one(&session.conn)
-        .await?;
+    Ok(match item {
+        Some(item) => Entry {
+            versionstamp: Some(item.version.to_string()),
+            metadata: item.metadata.map(|metadata| metadata.to_string()),
+            value: Some(item.value.into()),
+        },
+        None => Entry {
+            value: None,
+            metadata: None,
+            versionstamp: None,
+        },
+    })
+}
 
-    if let Some(item) = maybe_item {
-        let to_delete = store::ActiveModel {
-            id: Set(item.id),
-            ..Default::default()
-        };
+#[op]
+async fn op_kv_delete(state: Rc<RefCell<OpState>>, binding: String, key: String) -> Result<()> {
+    let (backend, namespace_id) = resolve(&state, &binding)?;
+    backend.delete(namespace_id, &key).await?;
 
-        store::Entity::delete(to_delete).exec(&session.conn).await?;
-    }
+    state
+        .borrow_mut()
+        .borrow_mut::<ReadCache>()
+        .invalidate(namespace_id, key);
 
     Ok(())
 }
 
 #[op]
-async fn op_kv_clear(state: Rc<RefCell<OpState>>) -> Result<()> {
-    let session = {
-        let state = state.borrow();
-        state.borrow::<Session>().clone()
-    };
+async fn op_kv_clear(state: Rc<RefCell<OpState>>, binding: String) -> Result<()> {
+    let (backend, namespace_id) = resolve(&state, &binding)?;
+    backend.clear(namespace_id).await?;
 
-    let namespace = get_namespace(&session).await?;
-    store::Entity::delete_many()
-        .filter(store::Column::NamespaceId.eq(namespace.id))
-        .exec(&session.conn)
-        .await?;
+    state.borrow_mut().borrow_mut::<ReadCache>().clear();
 
     Ok(())
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListArgs {
+    prefix: Option<String>,
+    limit: Option<u64>,
+    cursor: Option<String>,
+    /// Only used internally by `values()` and `entries()`
+    #[serde(default)]
+    include_values: bool,
+}
+
+#[derive(Serialize)]
+struct ListKey {
+    name: String,
+    /// Seconds since the UNIX epoch
+    expiration: Option<i64>,
+    /// Serialized JSON
+    metadata: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<KvValue>,
+}
+
+#[derive(Serialize)]
+struct ListResult {
+    keys: Vec<ListKey>,
+    list_complete: bool,
+    cursor: Option<String>,
+}
+
 #[op]
-async fn op_kv_all(state: Rc<RefCell<OpState>>) -> Result<HashMap<String, String>> {
-    let session = {
-        let state = state.borrow();
-        state.borrow::<Session>().clone()
+async fn op_kv_list(
+    state: Rc<RefCell<OpState>>,
+    binding: String,
+    args: ListArgs,
+) -> Result<ListResult> {
+    let (backend, namespace_id) = resolve(&state, &binding)?;
+    let options = ListOptions {
+        prefix: args.prefix,
+        limit: args.limit,
+        cursor: args.cursor,
     };
+    let page = backend.list(namespace_id, &options).await?;
 
-    let namespace = get_namespace(&session).await?;
-    let items = store::Entity::find()
-        .filter(store::Column::NamespaceId.eq(namespace.id))
-        .all(&session.conn)
-        .await
-        .context("Failed to get store items from database")?;
-
-    let mut map: HashMap<String, String> = HashMap::new();
-    for item in items {
-        map.insert(item.key, item.value);
-    }
+    let keys = page
+        .items
+        .into_iter()
+        .map(|item| ListKey {
+            name: item.key,
+            expiration: item.expires_at.map(|expires_at| expires_at.timestamp()),
+            metadata: item.metadata.map(|metadata| metadata.to_string()),
+            value: args.include_values.then(|| item.value.into()),
+        })
+        .collect();
 
-    Ok(map)
+    Ok(ListResult {
+        keys,
+        list_complete: page.list_complete,
+        cursor: page.cursor,
+    })
 }
 
-async fn get_namespace(session: &Session) -> Result<namespace::Model, DbErr> {
-    let name = "default";
-
-    let namespace = namespace::Entity::find()
-        .filter(namespace::Column::Name.eq(name))
-        .filter(namespace::Column::UserId.eq(session.user_id))
-        .one(&session.conn)
-        .await?
-        .expect("This user has no default namespace, something is going wrong here..");
+/// Resolves a binding from the namespace ids loaded when the isolate booted
+pub(crate) fn resolve(
+    state: &Rc<RefCell<OpState>>,
+    binding: &str,
+) -> Result<(Arc<dyn KvBackend>, i32)> {
+    let state = state.borrow();
+    let namespace_id = state
+        .try_borrow::<Bindings>()
+        .and_then(|bindings| bindings.0.get(binding).copied())
+        .with_context(|| format!("No kv binding named {} exists for this user", binding))?;
 
-    Ok(namespace)
+    Ok((state.borrow::<Arc<dyn KvBackend>>().clone(), namespace_id))
 }
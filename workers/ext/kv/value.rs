@@ -0,0 +1,68 @@
+use deno_core::anyhow::{Context, Result};
+use deno_core::ZeroCopyBuf;
+use serde::{Deserialize, Serialize};
+use storage::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    Text,
+    Json,
+    Bytes,
+}
+
+/// A value as it is passed between the script and the ops, only the field
+/// matching `type` is set. JSON travels as its serialized text
+#[derive(Deserialize, Serialize)]
+pub struct KvValue {
+    #[serde(rename = "type")]
+    pub kind: ValueType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<ZeroCopyBuf>,
+}
+
+impl KvValue {
+    pub fn into_value(self) -> Result<Value> {
+        let value = match self.kind {
+            ValueType::Text => Value::Text(self.text.context("Missing text kv value")?),
+            ValueType::Json => Value::Json(
+                deno_core::serde_json::from_str(&self.json.context("Missing json kv value")?)
+                    .context("Invalid json kv value")?,
+            ),
+            ValueType::Bytes => {
+                Value::Bytes(self.bytes.context("Missing bytes kv value")?.to_vec())
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+impl From<Value> for KvValue {
+    fn from(value: Value) -> Self {
+        let mut kv_value = Self {
+            kind: ValueType::Text,
+            text: None,
+            json: None,
+            bytes: None,
+        };
+
+        match value {
+            Value::Text(text) => kv_value.text = Some(text),
+            Value::Json(json) => {
+                kv_value.kind = ValueType::Json;
+                kv_value.json = Some(json.to_string());
+            }
+            Value::Bytes(bytes) => {
+                kv_value.kind = ValueType::Bytes;
+                kv_value.bytes = Some(bytes.into());
+            }
+        }
+
+        kv_value
+    }
+}
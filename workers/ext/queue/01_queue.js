@@ -0,0 +1,33 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+
+  /**
+   * @param {any} body must be serializable with JSON.stringify
+   * @param {{ queue?: string }} [options]
+   *
+   * @returns {Promise<void>}
+   */
+  function send(body, options = {}) {
+    const queue = options.queue ?? "default";
+    return core.opAsync("op_queue_send", queue, JSON.stringify(body));
+  }
+
+  /**
+   * @param {any[]} bodies
+   * @param {{ queue?: string }} [options]
+   *
+   * @returns {Promise<void>}
+   */
+  async function sendBatch(bodies, options = {}) {
+    for (const body of bodies) {
+      await send(body, options);
+    }
+  }
+
+  window.queue = {
+    send,
+    sendBatch,
+  };
+})(this);
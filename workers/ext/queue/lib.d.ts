@@ -0,0 +1,27 @@
+declare global {
+  interface QueueMessage<Body = any> {
+    readonly id: number,
+    readonly body: Body,
+    readonly attempts: number,
+    readonly timestamp: Date,
+    ack: () => void,
+    retry: () => void,
+  }
+
+  interface MessageBatch<Body = any> {
+    readonly queue: string,
+    readonly messages: QueueMessage<Body>[],
+    ackAll: () => void,
+    retryAll: () => void,
+  }
+
+  var queue: {
+    send: (body: any, options?: { queue?: string }) => Promise<void>,
+    sendBatch: (bodies: any[], options?: { queue?: string }) => Promise<void>,
+  }
+
+  /** Messages stay queued while a deployment has neither this nor a `queue` export */
+  var onQueue: ((batch: MessageBatch) => Promise<void> | void) | undefined
+}
+
+export { };
@@ -0,0 +1,60 @@
+use std::{cell::RefCell, rc::Rc};
+
+use deno_core::anyhow::bail;
+use deno_core::{anyhow::Result, include_js_files, op, Extension, OpState};
+use entity::queue_message;
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::EntityTrait;
+use session::Session;
+
+/// Messages bigger than this are rejected, the queue is not meant for bulk data
+const MAX_BODY_BYTES: usize = 128 * 1024;
+
+pub fn init(maybe_session: Option<Session>) -> Extension {
+    Extension::builder()
+        .js(include_js_files!(
+            prefix "ext/queue",
+            "01_queue.js",
+        ))
+        .ops(vec![op_queue_send::decl()])
+        .state(move |state| {
+            if let Some(session) = maybe_session.clone() {
+                state.put::<Session>(session);
+            }
+            Ok(())
+        })
+        .build()
+}
+
+#[op]
+async fn op_queue_send(state: Rc<RefCell<OpState>>, queue: String, body: String) -> Result<()> {
+    let session = {
+        let state = state.borrow();
+        state.borrow::<Session>().clone()
+    };
+
+    if body.len() > MAX_BODY_BYTES {
+        bail!(
+            "Queue message is {} bytes, the maximum is {} bytes",
+            body.len(),
+            MAX_BODY_BYTES
+        );
+    }
+
+    let now = chrono::Utc::now();
+    let to_be_inserted = queue_message::ActiveModel {
+        user_id: Set(session.user_id),
+        queue: Set(queue),
+        body: Set(body),
+        attempts: Set(0),
+        visible_at: Set(chrono::DateTime::into(now)),
+        created_at: Set(chrono::DateTime::into(now)),
+        ..Default::default()
+    };
+
+    queue_message::Entity::insert(to_be_inserted)
+        .exec(&session.conn)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+"use strict";
+
+((window) => {
+  const core = window.Deno.core;
+  const { EventTarget, MessageEvent, CloseEvent, ErrorEvent } = window;
+
+  const CONNECTING = 0;
+  const OPEN = 1;
+  const CLOSING = 2;
+  const CLOSED = 3;
+
+  /**
+   * Server ends of the pairs by their client ends
+   * @type {WeakMap<PairedWebSocket, PairedWebSocket>}
+   */
+  const servers = new WeakMap();
+
+  /**
+   * One end of a `WebSocketPair`. The script uses the server end, the client end is returned
+   * in a response with status 101 and stands for the client of the connection
+   */
+  class PairedWebSocket extends EventTarget {
+    #rid;
+    #isClient;
+    #accepted = false;
+    #readyState = CONNECTING;
+
+    /**
+     * @param {number} rid
+     * @param {boolean} isClient
+     */
+    constructor(rid, isClient) {
+      super();
+      this.#rid = rid;
+      this.#isClient = isClient;
+    }
+
+    get readyState() {
+      return this.#readyState;
+    }
+
+    /**
+     * Starts to dispatch the messages of the client, has to be called before the client end
+     * is returned
+     */
+    accept() {
+      this.#checkServer();
+      if (this.#accepted) {
+        throw new DOMException("The WebSocket was already accepted", "InvalidStateError");
+      }
+
+      this.#accepted = true;
+      this.#readyState = OPEN;
+      this.#receive();
+    }
+
+    /**
+     * @param {string | ArrayBuffer | ArrayBufferView} data
+     */
+    send(data) {
+      this.#checkServer();
+      if (this.#readyState !== OPEN) {
+        throw new DOMException("The WebSocket isn't open", "InvalidStateError");
+      }
+
+      if (typeof data === "string") {
+        core.opSync("op_socket_send_text", this.#rid, data);
+      } else if (data instanceof ArrayBuffer) {
+        core.opSync("op_socket_send_binary", this.#rid, new Uint8Array(data));
+      } else if (ArrayBuffer.isView(data)) {
+        core.opSync(
+          "op_socket_send_binary",
+          this.#rid,
+          new Uint8Array(data.buffer, data.byteOffset, data.byteLength),
+        );
+      } else {
+        throw new TypeError("Only strings, ArrayBuffers and ArrayBufferViews can be sent");
+      }
+    }
+
+    /**
+     * @param {number} [code]
+     * @param {string} [reason]
+     */
+    close(code = 1000, reason = "") {
+      this.#checkServer();
+      if (code !== 1000 && (code < 3000 || code > 4999)) {
+        throw new DOMException("The close code has to be 1000 or between 3000 and 4999", "InvalidAccessError");
+      }
+      if (new TextEncoder().encode(reason).byteLength > 123) {
+        throw new DOMException("The close reason may be at most 123 bytes", "SyntaxError");
+      }
+      if (this.#readyState === CLOSING || this.#readyState === CLOSED) {
+        return;
+      }
+
+      core.opSync("op_socket_close", this.#rid, { code, reason });
+      this.#readyState = CLOSING;
+    }
+
+    #checkServer() {
+      if (this.#isClient) {
+        throw new TypeError("The client end of a WebSocketPair can only be returned in a response");
+      }
+    }
+
+    async #receive() {
+      try {
+        while (true) {
+          const received = await core.opAsync("op_socket_next", this.#rid);
+          if (received === null) {
+            this.#readyState = CLOSED;
+            this.dispatchEvent(new CloseEvent("close", { code: 1006, reason: "", wasClean: false }));
+            break;
+          }
+
+          if (received.close !== null) {
+            this.#readyState = CLOSED;
+            this.dispatchEvent(new CloseEvent("close", { ...received.close, wasClean: true }));
+            break;
+          }
+
+          const { binary } = received;
+          const data = binary === null
+            ? received.text
+            : binary.buffer.slice(binary.byteOffset, binary.byteOffset + binary.byteLength);
+          this.dispatchEvent(new MessageEvent("message", { data }));
+        }
+      } catch (error) {
+        this.#readyState = CLOSED;
+        this.dispatchEvent(new ErrorEvent("error", { error, message: String(error) }));
+      } finally {
+        core.tryClose(this.#rid);
+      }
+    }
+
+    /**
+     * The pair of a client end that is returned in a response
+     *
+     * @param {unknown} webSocket
+     * @returns {number}
+     */
+    static connect(webSocket) {
+      const server = servers.get(webSocket);
+      if (server === undefined) {
+        throw new TypeError("The webSocket of a response has to be the client end of a WebSocketPair");
+      }
+      if (!server.#accepted) {
+        throw new TypeError("accept() has to be called on the server end before the client end is returned");
+      }
+
+      return server.#rid;
+    }
+  }
+
+  /**
+   * Two connected sockets, `0` is the client end and `1` the server end
+   */
+  class WebSocketPair {
+    constructor() {
+      const rid = core.opSync("op_socket_pair");
+      const client = new PairedWebSocket(rid, true);
+      const server = new PairedWebSocket(rid, false);
+      servers.set(client, server);
+
+      this[0] = client;
+      this[1] = server;
+    }
+  }
+
+  // responses can't have status 101 otherwise, the runtime picks this up before `Response`
+  // becomes a global
+  const fetch = window.__bootstrap.fetch;
+  fetch.Response = new Proxy(fetch.Response, {
+    construct(target, args, newTarget) {
+      const [body, init] = args;
+      const webSocket = init?.webSocket ?? null;
+      if (webSocket === null) {
+        return Reflect.construct(target, args, newTarget);
+      }
+
+      if (!servers.has(webSocket)) {
+        throw new TypeError("webSocket has to be the client end of a WebSocketPair");
+      }
+      if (init.status !== 101) {
+        throw new RangeError("A response with a webSocket needs status 101");
+      }
+
+      const response = Reflect.construct(target, [body, { ...init, status: 200 }], newTarget);
+      Object.defineProperties(response, {
+        status: { value: 101 },
+        ok: { value: false },
+        webSocket: { value: webSocket },
+      });
+      return response;
+    },
+  });
+
+  window.WebSocketPair = WebSocketPair;
+  window._hbw.sockets = {
+    connect: PairedWebSocket.connect,
+  };
+})(this);
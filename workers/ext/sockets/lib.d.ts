@@ -0,0 +1,26 @@
+declare global {
+  interface PairedWebSocket extends EventTarget {
+    readonly readyState: number,
+    accept: () => void,
+    send: (data: string | ArrayBuffer | ArrayBufferView) => void,
+    close: (code?: number, reason?: string) => void,
+    addEventListener(type: "message", listener: (event: MessageEvent<string | ArrayBuffer>) => void): void,
+    addEventListener(type: "close", listener: (event: CloseEvent) => void): void,
+    addEventListener(type: "error", listener: (event: ErrorEvent) => void): void,
+  }
+
+  class WebSocketPair {
+    0: PairedWebSocket
+    1: PairedWebSocket
+  }
+
+  interface ResponseInit {
+    webSocket?: PairedWebSocket | null,
+  }
+
+  interface Response {
+    readonly webSocket?: PairedWebSocket,
+  }
+}
+
+export { };
@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use deno_core::anyhow::{anyhow, Result};
+use deno_core::{
+    include_js_files, op, CancelFuture, CancelHandle, Extension, OpState, RcRef, Resource,
+    ResourceId, ZeroCopyBuf,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Mutex};
+
+/// Messages from the client waiting for the script, the connection stops reading when it's full
+const INCOMING_CAPACITY: usize = 64;
+/// Messages from the script waiting for the client, sending fails when it's full
+const OUTGOING_CAPACITY: usize = 256;
+
+/// A message between the script and the client of a socket
+#[derive(Debug)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Close(u16, String),
+}
+
+/// The ends of a `WebSocketPair` the connection works with, the script has the other ends
+pub struct Connection {
+    /// Messages from the client to the script
+    pub incoming: mpsc::Sender<Frame>,
+    /// Messages from the script to the client
+    pub outgoing: mpsc::Receiver<Frame>,
+}
+
+/// The server end of a `WebSocketPair`
+struct ServerSocket {
+    incoming: Mutex<mpsc::Receiver<Frame>>,
+    outgoing: mpsc::Sender<Frame>,
+    /// Taken once the client end was returned in a response
+    connection: RefCell<Option<Connection>>,
+    cancel: CancelHandle,
+}
+
+impl Resource for ServerSocket {
+    fn name(&self) -> Cow<str> {
+        "webSocketPair".into()
+    }
+
+    fn close(self: Rc<Self>) {
+        self.cancel.cancel();
+    }
+}
+
+/// Sockets handed to a connection, the isolate keeps running while one of them is open
+#[derive(Default)]
+struct Connected(HashSet<ResourceId>);
+
+pub fn init() -> Extension {
+    Extension::builder()
+        .js(include_js_files!(
+            prefix "ext/sockets",
+            "01_sockets.js",
+        ))
+        .ops(vec![
+            op_socket_pair::decl(),
+            op_socket_next::decl(),
+            op_socket_send_text::decl(),
+            op_socket_send_binary::decl(),
+            op_socket_close::decl(),
+        ])
+        .state(|state| {
+            state.put(Connected::default());
+            Ok(())
+        })
+        .build()
+}
+
+/// Takes the ends of the pair the connection needs, once the script returned its client end
+///
+/// # Errors
+///
+/// Will return `Err` if there is no such pair or it was returned before
+pub fn connect(state: &mut OpState, rid: ResourceId) -> Result<Connection> {
+    let socket = state.resource_table.get::<ServerSocket>(rid)?;
+    let connection = socket
+        .connection
+        .borrow_mut()
+        .take()
+        .ok_or_else(|| anyhow!("The WebSocket was already returned in another response"))?;
+    state.borrow_mut::<Connected>().0.insert(rid);
+
+    Ok(connection)
+}
+
+/// Whether a connected socket is still open, sockets are closed by the script once their
+/// connection ended
+pub fn has_connected(state: &mut OpState) -> bool {
+    let resource_table = &state.resource_table;
+    let open: HashSet<ResourceId> = state
+        .borrow::<Connected>()
+        .0
+        .iter()
+        .copied()
+        .filter(|rid| resource_table.get::<ServerSocket>(*rid).is_ok())
+        .collect();
+    let has_connected = !open.is_empty();
+    state.put(Connected(open));
+
+    has_connected
+}
+
+/// Ends the connection of every socket, e.g. when nothing in the isolate reads from them
+pub fn disconnect_all(state: &mut OpState) {
+    let connected = std::mem::take(&mut state.borrow_mut::<Connected>().0);
+    for rid in connected {
+        state.resource_table.close(rid).unwrap_or(());
+    }
+}
+
+#[op]
+fn op_socket_pair(state: &mut OpState) -> ResourceId {
+    let (incoming_tx, incoming_rx) = mpsc::channel(INCOMING_CAPACITY);
+    let (outgoing_tx, outgoing_rx) = mpsc::channel(OUTGOING_CAPACITY);
+
+    state.resource_table.add(ServerSocket {
+        incoming: Mutex::new(incoming_rx),
+        outgoing: outgoing_tx,
+        connection: RefCell::new(Some(Connection {
+            incoming: incoming_tx,
+            outgoing: outgoing_rx,
+        })),
+        cancel: CancelHandle::default(),
+    })
+}
+
+/// A message from the client as the script gets it
+#[derive(Serialize, Default)]
+struct Received {
+    text: Option<String>,
+    binary: Option<ZeroCopyBuf>,
+    close: Option<CloseArgs>,
+}
+
+impl From<Frame> for Received {
+    fn from(frame: Frame) -> Self {
+        match frame {
+            Frame::Text(text) => Self {
+                text: Some(text),
+                ..Self::default()
+            },
+            Frame::Binary(binary) => Self {
+                binary: Some(binary.into()),
+                ..Self::default()
+            },
+            Frame::Close(code, reason) => Self {
+                close: Some(CloseArgs { code, reason }),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CloseArgs {
+    code: u16,
+    reason: String,
+}
+
+/// Waits for the next message from the client, `None` once the connection ended
+#[op]
+async fn op_socket_next(state: Rc<RefCell<OpState>>, rid: ResourceId) -> Result<Option<Received>> {
+    let socket = state.borrow().resource_table.get::<ServerSocket>(rid)?;
+    let cancel = RcRef::map(&socket, |socket| &socket.cancel);
+
+    let frame = async { socket.incoming.lock().await.recv().await }
+        .or_cancel(cancel)
+        .await;
+
+    Ok(frame.ok().flatten().map(Received::from))
+}
+
+/// Sending is synchronous for the script, so a client that doesn't keep up makes it fail
+/// instead of buffering without a bound
+fn send(state: &mut OpState, rid: ResourceId, frame: Frame) -> Result<()> {
+    let socket = state.resource_table.get::<ServerSocket>(rid)?;
+    socket.outgoing.try_send(frame).map_err(|e| match e {
+        TrySendError::Full(_) => anyhow!(
+            "The client of the WebSocket is too slow, {} messages are waiting already",
+            OUTGOING_CAPACITY
+        ),
+        TrySendError::Closed(_) => anyhow!("The WebSocket is closed"),
+    })
+}
+
+#[op]
+fn op_socket_send_text(state: &mut OpState, rid: ResourceId, text: String) -> Result<()> {
+    send(state, rid, Frame::Text(text))
+}
+
+#[op]
+fn op_socket_send_binary(state: &mut OpState, rid: ResourceId, binary: ZeroCopyBuf) -> Result<()> {
+    send(state, rid, Frame::Binary(binary.to_vec()))
+}
+
+#[op]
+fn op_socket_close(state: &mut OpState, rid: ResourceId, args: CloseArgs) -> Result<()> {
+    send(state, rid, Frame::Close(args.code, args.reason))
+}
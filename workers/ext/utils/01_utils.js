@@ -1,44 +1,223 @@
 "use strict";
 
 ((window) => {
+  const { Event } = window.__bootstrap.event;
+  const { headerListFromHeaders } = window.__bootstrap.headers;
+
   /**
-   * @param {Response} response 
+   * @param {Response | Promise<Response>} response
    * @returns {Promise<void}
    */
   async function respondWith(response) {
+    response = await response
+
+    if (!(response instanceof Response)) {
+      throw new TypeError("The fetch handler must respond with a Response object")
+    }
+
     const serialized = {
-      headers: Object.fromEntries(response.headers),
+      headers: headerListFromHeaders(response.headers).map(([name, value]) => [name, value]),
       ok: response.ok,
       redirected: response.redirected,
       status: response.status,
       statusText: response.statusText,
       trailer: response.trailer,
       type: response.type,
-      body: new Uint8Array(await response.arrayBuffer())
+      body: new Uint8Array(await response.arrayBuffer()),
+      // the resource of the `WebSocketPair` the connection is handed to
+      webSocket: response.webSocket ? window._hbw.sockets.connect(response.webSocket) : null
     }
 
     window.requestResult = serialized
   }
 
   /**
-   * @param {any} request 
-   * @returns {Promise<any>}
+   * Service Worker compatible event dispatched to `addEventListener("fetch", ...)` listeners
+   */
+  class FetchEvent extends Event {
+    #waitUntil
+    #response = null
+
+    /**
+     * @param {Request} request
+     * @param {(promise: Promise<any>) => void} waitUntil
+     */
+    constructor(request, waitUntil) {
+      super("fetch")
+      this.request = request
+      this.#waitUntil = waitUntil
+    }
+
+    /**
+     * @param {Response | Promise<Response>} response
+     */
+    respondWith(response) {
+      if (this.#response !== null) {
+        throw new DOMException("respondWith() was already called", "InvalidStateError")
+      }
+
+      this.#response = Promise.resolve(response)
+    }
+
+    /**
+     * @param {Promise<any>} promise
+     */
+    waitUntil(promise) {
+      this.#waitUntil(promise)
+    }
+
+    get response() {
+      return this.#response
+    }
+  }
+
+  /**
+   * Calls the request handler of the script, in order of preference:
+   * `export default { fetch }`, `window.onRequest` and `addEventListener("fetch")`.
+   * In the isolate of an actor every request goes to the `fetch` method of the actor instead.
+   *
+   * Resolves once the handler returned and every promise passed to `waitUntil` settled,
+   * the response itself is picked up as soon as `respondWith` is done
+   *
+   * @param {any} request
+   * @returns {Promise<void>}
    */
   async function callOnRequest(request) {
-    const event = {
-      request: new Request(request.url, {
-        method: request.method,
-        headers: request.headers,
-        body: (request.method !== "GET" && request.method !== "HEAD") ? request.body : undefined
-      }),
-      respondWith: respondWith
+    const waitUntilPromises = []
+    /**
+     * @param {Promise<any>} promise
+     */
+    const waitUntil = (promise) => {
+      waitUntilPromises.push(Promise.resolve(promise).catch((error) => console.error(error)))
     }
 
-    return window.onRequest(event)
+    const jsRequest = new Request(request.url, {
+      method: request.method,
+      headers: request.headers,
+      body: (request.method !== "GET" && request.method !== "HEAD") ? request.body : undefined
+    })
+    Object.defineProperty(jsRequest, "cf", {
+      value: Object.freeze(request.cf ?? {}),
+      enumerable: true
+    })
+
+    const handlers = window._hbw.module?.default
+    const actor = window._hbw.actor?.instance()
+
+    try {
+      if (actor !== null && actor !== undefined) {
+        if (typeof actor.fetch !== "function") {
+          throw new Error("The actor class has no fetch method")
+        }
+
+        await respondWith(actor.fetch(jsRequest))
+      } else if (typeof handlers?.fetch === "function") {
+        const ctx = {
+          waitUntil,
+          passThroughOnException: () => {}
+        }
+
+        await respondWith(handlers.fetch(jsRequest, window._hbw.env, ctx))
+      } else if (typeof window.onRequest === "function") {
+        await window.onRequest({
+          request: jsRequest,
+          respondWith,
+          waitUntil
+        })
+      } else {
+        const event = new FetchEvent(jsRequest, waitUntil)
+        window.dispatchEvent(event)
+
+        if (event.response === null) {
+          throw new Error("No fetch handler found, export a default fetch handler or add a fetch event listener")
+        }
+
+        await respondWith(event.response)
+      }
+    } finally {
+      while (waitUntilPromises.length > 0) {
+        await Promise.all(waitUntilPromises.splice(0))
+      }
+    }
+  }
+
+  /**
+   * @param {{ queue: string, messages: { id: number, body: string, attempts: number, timestamp: number }[] }} batch
+   * @returns {Promise<void>}
+   */
+  async function callOnQueue(batch) {
+    const retried = new Set()
+    const messages = batch.messages.map((message) => ({
+      id: message.id,
+      body: JSON.parse(message.body),
+      attempts: message.attempts,
+      timestamp: new Date(message.timestamp),
+      ack: () => retried.delete(message.id),
+      retry: () => retried.add(message.id),
+    }))
+
+    const handlers = window._hbw.module?.default
+    const handler = typeof handlers?.queue === "function"
+      ? (batch) => handlers.queue(batch, window._hbw.env, ctx)
+      : window.onQueue
+
+    // the messages stay queued, the consumer skips this deployment from now on
+    if (typeof handler !== "function") {
+      window.queueResult = {
+        retry: [],
+        unhandled: true
+      }
+      return
+    }
+
+    const waitUntilPromises = []
+    const ctx = {
+      waitUntil: (promise) => waitUntilPromises.push(promise)
+    }
+
+    try {
+      const messageBatch = {
+        queue: batch.queue,
+        messages,
+        ackAll: () => retried.clear(),
+        retryAll: () => messages.forEach((message) => retried.add(message.id)),
+      }
+
+      await handler(messageBatch)
+
+      await Promise.all(waitUntilPromises)
+    } catch (error) {
+      console.error(error)
+      messages.forEach((message) => retried.add(message.id))
+    }
+
+    window.queueResult = {
+      retry: [...retried]
+    }
+  }
+
+  /**
+   * Exposes a binding to the script, both as global and on the `env` of module handlers
+   *
+   * @param {string} name
+   * @param {any} value
+   */
+  function bind(name, value) {
+    window._hbw.env[name] = value
+
+    // never shadow the globals of the runtime itself
+    if (!(name in window)) {
+      window[name] = value
+    }
   }
 
   window.callOnRequest = callOnRequest
+  window.callOnQueue = callOnQueue
   window._hbw = {
-    cwd: undefined
+    ...window._hbw,
+    cwd: undefined,
+    env: {},
+    module: undefined,
+    bind
   }
 })(this);
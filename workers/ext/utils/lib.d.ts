@@ -0,0 +1,39 @@
+declare global {
+  interface RequestMetadata {
+    readonly remoteAddress: string,
+    readonly protocol: string,
+    readonly httpVersion: string,
+    readonly requestId: string,
+    readonly receivedAt: number,
+  }
+
+  interface Request {
+    readonly cf: RequestMetadata,
+  }
+
+  interface ExecutionContext {
+    waitUntil: (promise: Promise<any>) => void,
+    passThroughOnException: () => void,
+  }
+
+  interface ExportedHandler<Env = Record<string, any>> {
+    fetch?: (request: Request, env: Env, ctx: ExecutionContext) => Response | Promise<Response>,
+    queue?: (batch: MessageBatch, env: Env, ctx: Pick<ExecutionContext, "waitUntil">) => void | Promise<void>,
+  }
+
+  class FetchEvent extends Event {
+    readonly request: Request
+    respondWith: (response: Response | Promise<Response>) => void
+    waitUntil: (promise: Promise<any>) => void
+  }
+
+  function addEventListener(type: "fetch", listener: (event: FetchEvent) => void): void
+
+  var onRequest: ((event: {
+    request: Request,
+    respondWith: (response: Response | Promise<Response>) => Promise<void>,
+    waitUntil: (promise: Promise<any>) => void,
+  }) => Promise<void> | void) | undefined
+}
+
+export { };
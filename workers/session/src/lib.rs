@@ -1,7 +1,38 @@
+use config::{BlobsConfig, CacheConfig, Config, DbConfig, KvConfig};
 use migration::sea_orm::DatabaseConnection;
 
+/// A user and the settings their scripts run with
 #[derive(Debug, Clone)]
 pub struct Session {
     pub user_id: i32,
+    /// Disconnected when running without a database
     pub conn: DatabaseConnection,
+    /// The database of the app is reached with the credentials of its own role. `None` when
+    /// running without a database, which only `hbw run` does
+    pub database_url: Option<String>,
+    pub kv: KvConfig,
+    pub db: DbConfig,
+    pub cache: CacheConfig,
+    pub blobs: BlobsConfig,
+}
+
+impl Session {
+    #[must_use]
+    pub fn new(user_id: i32, conn: DatabaseConnection, config: &Config) -> Self {
+        Self {
+            user_id,
+            conn,
+            database_url: config.database_url().ok().map(ToString::to_string),
+            kv: config.kv,
+            db: config.db,
+            cache: config.cache,
+            blobs: config.blobs,
+        }
+    }
+
+    /// Without a database only KV in memory and the cache work
+    #[must_use]
+    pub const fn has_database(&self) -> bool {
+        self.database_url.is_some()
+    }
 }
@@ -0,0 +1,54 @@
+use actor::{ActorRequest, ActorResponse};
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::http::header::{HeaderName, HOST};
+use axum::http::{HeaderValue, Method, Request, Response, Uri};
+use deno_core::ByteString;
+
+/// Calls to actors are delivered to their runtime like any other request
+pub fn into_request(actor_request: ActorRequest) -> Result<Request<Body>> {
+    let uri = Uri::try_from(actor_request.url).context("Invalid actor request URL")?;
+    let host = uri
+        .authority()
+        .context("Actor request URL has no host")?
+        .to_string();
+
+    let mut request = Request::builder()
+        .method(Method::from_bytes(actor_request.method.as_bytes())?)
+        .uri(uri)
+        .body(Body::from(actor_request.body))?;
+
+    let headers = request.headers_mut();
+    for (key, value) in actor_request.headers {
+        headers.append(
+            HeaderName::from_bytes(&key)?,
+            HeaderValue::from_bytes(&value)?,
+        );
+    }
+    if !headers.contains_key(HOST) {
+        headers.insert(HOST, HeaderValue::from_str(&host)?);
+    }
+
+    Ok(request)
+}
+
+pub async fn from_response(response: Response<Body>) -> Result<ActorResponse> {
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(key, value)| {
+            (
+                ByteString::from(key.as_str().as_bytes().to_vec()),
+                ByteString::from(value.as_bytes().to_vec()),
+            )
+        })
+        .collect();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+
+    Ok(ActorResponse {
+        status,
+        headers,
+        body: body.to_vec(),
+    })
+}
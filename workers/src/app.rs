@@ -1,16 +1,63 @@
+use actor::{
+    ActorAddress, ActorCall, ActorContext, ActorRequest, ActorResponse, ActorRouter, CallChain,
+};
+use anyhow::{anyhow, bail};
 use axum::{body::Body, http::Request, response::Response};
+use blobs::BlobStore;
 use deno_runtime::permissions::{Permissions, PermissionsOptions};
+use once_cell::sync::Lazy;
+use s3::Bucket;
 use session::Session;
 use std::{
+    collections::HashMap,
     path::PathBuf,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     thread::{self},
+    time::Duration,
 };
-use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::sync::{mpsc, oneshot, watch, RwLock};
 
+use crate::actor::{from_response, into_request};
+use crate::queue::{QueueBatch, QueueBatchResult};
 use crate::runtime::Runtime;
 
-pub type RuntimeChannelPayload = (Request<Body>, oneshot::Sender<Response<Body>>);
+/// Runtimes are stopped after this long without a payload
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Actors keep their in-memory state between calls, so they stay around longer
+const ACTOR_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Every actor has its own thread and isolate, ids are chosen by the script
+const MAX_ACTORS: usize = 100;
+/// Calls fail after this long, the actor keeps handling them though
+const ACTOR_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The actor instances of every user. Their storage is shared by all deployments of a user,
+/// so an id must never run in two deployments at once
+static ACTORS: Lazy<Mutex<HashMap<i32, Actors>>> = Lazy::new(Default::default);
+/// Later deployments get a higher generation than the ones they replace
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The actors of a user, all of them belong to the latest deployment that called one
+#[derive(Debug, Default)]
+struct Actors {
+    generation: u64,
+    instances: HashMap<ActorAddress, App>,
+    /// Turns true once the instances of the replaced deployment stopped
+    ready: Option<watch::Receiver<bool>>,
+}
+
+#[derive(Debug)]
+pub enum RuntimeChannelPayload {
+    Request(Request<Body>, oneshot::Sender<Response<Body>>),
+    Queue(
+        QueueBatch,
+        oneshot::Sender<anyhow::Result<QueueBatchResult>>,
+    ),
+    /// Stops the runtime once the payloads sent before are handled
+    Stop,
+}
 
 #[derive(Debug, Clone)]
 pub struct App {
@@ -18,8 +65,22 @@ pub struct App {
     pub name: String,
     pub path: PathBuf,
     pub script_file_name: String,
+    /// Served before requests reach the script, see `Manifest`
+    pub assets: Option<PathBuf>,
     pub deployment: String,
+    /// Where the script's blobs are stored, blobs are unavailable without it
+    pub blobs_bucket: Option<Bucket>,
     runtime: Arc<RwLock<Option<mpsc::Sender<RuntimeChannelPayload>>>>,
+    /// Only set for the app running a single actor instance
+    actor: Option<ActorAddress>,
+    /// Tells apart the deployments of a user, see `ACTORS`
+    generation: u64,
+    /// Set once the actor's deployment was replaced, its runtime never starts again
+    retired: Arc<AtomicBool>,
+    /// The actor's runtime only starts once this is true, see `Actors`
+    ready: Option<watch::Receiver<bool>>,
+    /// Cleared once the script turned out to have no queue handler
+    queue_handler: Arc<AtomicBool>,
 }
 
 impl App {
@@ -29,27 +90,177 @@ impl App {
         name: String,
         path: PathBuf,
         script_file_name: String,
+        assets: Option<PathBuf>,
         deployment: String,
+        blobs_bucket: Option<Bucket>,
     ) -> Self {
         Self {
             session,
             name,
             path,
             script_file_name,
+            assets,
             deployment,
+            blobs_bucket,
             runtime: Arc::new(RwLock::new(None)),
+            actor: None,
+            generation: GENERATION.fetch_add(1, Ordering::Relaxed) + 1,
+            retired: Arc::new(AtomicBool::new(false)),
+            ready: None,
+            queue_handler: Arc::new(AtomicBool::new(true)),
         }
     }
 
+    /// Whether queue messages should be delivered to this deployment
+    pub fn has_queue_handler(&self) -> bool {
+        self.queue_handler.load(Ordering::Relaxed)
+    }
+
+    pub fn set_no_queue_handler(&self) {
+        self.queue_handler.store(false, Ordering::Relaxed);
+    }
+
     pub async fn get_runtime(&self) -> mpsc::Sender<RuntimeChannelPayload> {
         if let Some(runtime) = self.runtime.read().await.clone() {
             return runtime;
         }
 
-        self.new_worker().await
+        // checked again under the write lock, an actor must never run in two runtimes
+        let mut runtime = self.runtime.write().await;
+        if let Some(runtime) = runtime.clone() {
+            return runtime;
+        }
+
+        // nothing receives from this channel, so every payload sent to it fails
+        if self.retired.load(Ordering::Relaxed) {
+            return mpsc::channel(1).0;
+        }
+        if let Some(mut ready) = self.ready.clone() {
+            while !*ready.borrow() {
+                if ready.changed().await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        let tx = self.new_worker();
+        *runtime = Some(tx.clone());
+        tx
     }
 
-    async fn new_worker(&self) -> mpsc::Sender<RuntimeChannelPayload> {
+    /// The app running the actor at `address`, created on the first call. The first call
+    /// of a new deployment stops the actors of the one it replaced
+    fn actor(&self, address: ActorAddress) -> anyhow::Result<Self> {
+        let mut registry = ACTORS.lock().unwrap();
+        let actors = registry.entry(self.session.user_id).or_default();
+
+        if self.generation < actors.generation {
+            bail!("The deployment was replaced, its actors can't be called anymore");
+        }
+        if self.generation > actors.generation {
+            let replaced = std::mem::take(&mut actors.instances)
+                .into_values()
+                .collect();
+            actors.generation = self.generation;
+            actors.ready = Some(stop_all(replaced));
+        }
+
+        if !actors.instances.contains_key(&address) && actors.instances.len() >= MAX_ACTORS {
+            evict_idle(&mut actors.instances);
+            if actors.instances.len() >= MAX_ACTORS {
+                bail!("{} actors are running for this user already", MAX_ACTORS);
+            }
+        }
+
+        let ready = actors.ready.clone();
+        Ok(actors
+            .instances
+            .entry(address.clone())
+            .or_insert_with(|| Self {
+                actor: Some(address),
+                runtime: Arc::new(RwLock::new(None)),
+                retired: Arc::new(AtomicBool::new(false)),
+                ready,
+                ..self.clone()
+            })
+            .clone())
+    }
+
+    /// Stops the actor's runtime after the payloads it already received and waits for it
+    async fn stop(&self) {
+        let mut runtime = self.runtime.write().await;
+        self.retired.store(true, Ordering::Relaxed);
+        let tx = runtime.take();
+        drop(runtime);
+
+        if let Some(tx) = tx {
+            tx.send(RuntimeChannelPayload::Stop).await.unwrap_or(());
+            tx.closed().await;
+        }
+    }
+
+    /// Delivers the calls an isolate makes to actors, each actor handles its calls one at a time
+    fn route_actor_calls(&self, mut calls: mpsc::Receiver<ActorCall>) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            while let Some(ActorCall {
+                address,
+                request,
+                chain,
+                reply,
+            }) = calls.recv().await
+            {
+                // the actor is busy waiting for this call, it would wait for itself forever
+                if chain.contains(&address) {
+                    reply
+                        .send(Err(anyhow!(
+                            "Actor {} {} is waiting for this call, actors can't be called back",
+                            address.class_name,
+                            address.id
+                        )))
+                        .unwrap_or(());
+                    continue;
+                }
+
+                let actor = match app.actor(address) {
+                    Ok(actor) => actor,
+                    Err(e) => {
+                        reply.send(Err(e)).unwrap_or(());
+                        continue;
+                    }
+                };
+                tokio::spawn(async move {
+                    reply.send(actor.call(request, chain).await).unwrap_or(());
+                });
+            }
+        });
+    }
+
+    async fn call(
+        &self,
+        request: ActorRequest,
+        chain: Vec<ActorAddress>,
+    ) -> anyhow::Result<ActorResponse> {
+        let mut request = into_request(request)?;
+        request.extensions_mut().insert(CallChain(chain));
+
+        let call = async {
+            let (tx, rx) = oneshot::channel();
+            let runtime_channel = self.get_runtime().await;
+            runtime_channel
+                .send(RuntimeChannelPayload::Request(request, tx))
+                .await
+                .map_err(|_| anyhow!("Actor runtime stopped before the call was delivered"))?;
+
+            from_response(rx.await?).await
+        };
+
+        tokio::time::timeout(ACTOR_CALL_TIMEOUT, call)
+            .await
+            .map_err(|_| anyhow!("The actor didn't respond within {:?}", ACTOR_CALL_TIMEOUT))?
+    }
+
+    fn new_worker(&self) -> mpsc::Sender<RuntimeChannelPayload> {
         println!("New worker spawned from {:?}", self.path);
         let permission_options = PermissionsOptions {
             allow_env: None,
@@ -68,6 +279,20 @@ impl App {
         script_path.push(self.script_file_name.clone());
 
         let session = self.session.clone();
+        let blob_store = BlobStore::new(session.clone(), self.blobs_bucket.clone());
+
+        let (actor_tx, actor_rx) = mpsc::channel::<ActorCall>(10);
+        self.route_actor_calls(actor_rx);
+        let actor_context = ActorContext {
+            session: session.clone(),
+            router: ActorRouter(actor_tx),
+            address: self.actor.clone(),
+        };
+        let idle_timeout = if self.actor.is_some() {
+            ACTOR_IDLE_TIMEOUT
+        } else {
+            IDLE_TIMEOUT
+        };
 
         thread::spawn(move || {
             tokio::runtime::Builder::new_multi_thread()
@@ -77,24 +302,52 @@ impl App {
                 .build()
                 .unwrap()
                 .block_on(async {
-                    let mut runtime = Runtime::new(session, script_path.as_path(), permissions);
-                    runtime.handle_request(&mut rx).await;
+                    let mut runtime = Runtime::new(
+                        session,
+                        actor_context,
+                        blob_store,
+                        script_path.as_path(),
+                        permissions,
+                    )
+                    .await;
+                    runtime.handle_request(&mut rx, idle_timeout).await;
                 });
         });
 
-        {
-            let tx2 = tx.clone();
-            *self.runtime.write().await = Some(tx2);
-        }
-
         let runtime2 = self.runtime.clone();
         let tx2 = tx.clone();
+        let user_id = self.actor.as_ref().map(|_| self.session.user_id);
         tokio::spawn(async move {
             tx2.closed().await;
-            let mut item = runtime2.write().await;
-            *item = None;
+            *runtime2.write().await = None;
+            drop(runtime2);
+
+            if let Some(user_id) = user_id {
+                if let Some(actors) = ACTORS.lock().unwrap().get_mut(&user_id) {
+                    evict_idle(&mut actors.instances);
+                }
+            }
         });
 
         tx
     }
 }
+
+/// Stops the actors of a replaced deployment, the receiver turns true once all of them did
+fn stop_all(actors: Vec<App>) -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+    tokio::spawn(async move {
+        for actor in actors {
+            actor.stop().await;
+        }
+        tx.send(true).unwrap_or(());
+    });
+
+    rx
+}
+
+/// Forgets the actors whose runtime stopped. An app that is only referenced by the map can't
+/// be handling a call, every call holds a clone of it until it's done
+fn evict_idle(actors: &mut HashMap<ActorAddress, App>) {
+    actors.retain(|_, actor| Arc::strong_count(&actor.runtime) > 1);
+}
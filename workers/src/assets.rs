@@ -0,0 +1,401 @@
+use anyhow::Result;
+use axum::body::Body;
+use axum::http::header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE, VARY,
+};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use percent_encoding::percent_decode_str;
+use std::ffi::OsString;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+/// Precompressed files next to an asset, by their `Content-Encoding` in order of preference
+const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// Serves a file from the assets of the deployment. `None` if there is no file for the path,
+/// the request goes to the script then
+pub async fn serve(dir: &Path, request: &Request<Body>) -> Option<Response<Body>> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return None;
+    }
+
+    let root = tokio::fs::canonicalize(dir).await.ok()?;
+    let path = resolve(&root, request.uri().path()).await?;
+
+    match respond(&root, &path, request).await {
+        Ok(response) => Some(response),
+        Err(e) => {
+            println!("Failed to serve asset {:?}: {:?}", path, e);
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            Some(response)
+        }
+    }
+}
+
+/// The file for a request path, directories are served by their `index.html`
+async fn resolve(root: &Path, uri_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(uri_path).decode_utf8().ok()?;
+
+    let mut path = root.to_path_buf();
+    for segment in decoded.split('/').filter(|segment| !segment.is_empty()) {
+        if segment == "." || segment == ".." || segment.contains('\\') || segment.contains('\0') {
+            return None;
+        }
+        path.push(segment);
+    }
+
+    if tokio::fs::metadata(&path).await.ok()?.is_dir() {
+        path.push("index.html");
+    }
+
+    inside(root, &path).await
+}
+
+/// Symlinks could point outside of the assets, only regular files inside of them are served
+async fn inside(root: &Path, path: &Path) -> Option<PathBuf> {
+    let path = tokio::fs::canonicalize(path).await.ok()?;
+    let is_file = tokio::fs::metadata(&path).await.ok()?.is_file();
+
+    if is_file && path.starts_with(root) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// The precompressed variant of the file the client accepts, if there is one
+async fn encoded(
+    root: &Path,
+    path: &Path,
+    accept_encoding: &str,
+) -> Option<(PathBuf, &'static str)> {
+    for (encoding, extension) in ENCODINGS {
+        if !accepts(accept_encoding, encoding) {
+            continue;
+        }
+
+        let mut variant = OsString::from(path.as_os_str());
+        variant.push(".");
+        variant.push(extension);
+        if let Some(variant) = inside(root, Path::new(&variant)).await {
+            return Some((variant, encoding));
+        }
+    }
+
+    None
+}
+
+fn accepts(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|item| {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let rejected = parts.any(|param| {
+            param
+                .trim()
+                .strip_prefix("q=")
+                .and_then(|quality| quality.parse::<f32>().ok())
+                == Some(0.0)
+        });
+
+        name.eq_ignore_ascii_case(encoding) && !rejected
+    })
+}
+
+async fn respond(root: &Path, path: &Path, request: &Request<Body>) -> Result<Response<Body>> {
+    let request_headers = request.headers();
+
+    // byte ranges always refer to the file itself, so ranged requests aren't compressed
+    let variant = match request_headers.get(ACCEPT_ENCODING) {
+        Some(accept_encoding) if !request_headers.contains_key(RANGE) => {
+            encoded(root, path, accept_encoding.to_str().unwrap_or_default()).await
+        }
+        _ => None,
+    };
+    let (file_path, encoding) = match &variant {
+        Some((file_path, encoding)) => (file_path.as_path(), Some(*encoding)),
+        None => (path, None),
+    };
+
+    let metadata = tokio::fs::metadata(file_path).await?;
+    let len = metadata.len();
+    let modified = metadata.modified().ok();
+    let etag = etag(len, modified, encoding);
+
+    let mut headers = HeaderMap::new();
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_str(mime.as_ref())?);
+    headers.insert(ETAG, HeaderValue::from_str(&etag)?);
+    if let Some(modified) = modified {
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(modified))?,
+        );
+    }
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+    if let Some(encoding) = encoding {
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+
+    if not_modified(request_headers, &etag, modified) {
+        return Ok(with_headers(
+            StatusCode::NOT_MODIFIED,
+            headers,
+            Body::empty(),
+        ));
+    }
+
+    let range = request_headers
+        .get(RANGE)
+        .filter(|_| if_range_matches(request_headers, &etag, modified))
+        .and_then(|range| parse_range(range.to_str().ok()?, len));
+    let (status, start, end) = match range {
+        Some(ByteRange::Satisfiable(start, end)) => {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, len))?,
+            );
+            (StatusCode::PARTIAL_CONTENT, start, end + 1)
+        }
+        Some(ByteRange::Unsatisfiable) => {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{}", len))?,
+            );
+            return Ok(with_headers(
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                headers,
+                Body::empty(),
+            ));
+        }
+        None => (StatusCode::OK, 0, len),
+    };
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(end - start));
+
+    if request.method() == Method::HEAD {
+        return Ok(with_headers(status, headers, Body::empty()));
+    }
+
+    let mut file = tokio::fs::File::open(file_path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let body = Body::wrap_stream(ReaderStream::new(file.take(end - start)));
+
+    Ok(with_headers(status, headers, body))
+}
+
+fn with_headers(status: StatusCode, headers: HeaderMap, body: Body) -> Response<Body> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    response
+}
+
+/// Changes whenever the file is replaced, every encoding has its own
+fn etag(len: u64, modified: Option<SystemTime>, encoding: Option<&str>) -> String {
+    let modified = modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_secs());
+
+    match encoding {
+        Some(encoding) => format!("\"{:x}-{:x}-{}\"", len, modified, encoding),
+        None => format!("\"{:x}-{:x}\"", len, modified),
+    }
+}
+
+/// HTTP dates only have seconds, so the modification time is compared in seconds as well
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn header_str<'a>(
+    headers: &'a HeaderMap,
+    name: &axum::http::header::HeaderName,
+) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// `If-None-Match` wins over `If-Modified-Since`, like RFC 9110 asks for
+fn not_modified(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = header_str(headers, &IF_NONE_MATCH) {
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
+    let since = header_str(headers, &IF_MODIFIED_SINCE)
+        .and_then(|since| httpdate::parse_http_date(since).ok());
+    match (since, modified) {
+        (Some(since), Some(modified)) => unix_seconds(modified) <= unix_seconds(since),
+        _ => false,
+    }
+}
+
+/// Ranges are only served if the client still has the same version of the file
+fn if_range_matches(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    let if_range = match header_str(headers, &IF_RANGE) {
+        Some(if_range) => if_range.trim(),
+        None => return true,
+    };
+
+    if if_range.starts_with('"') {
+        return if_range == etag;
+    }
+
+    match (httpdate::parse_http_date(if_range), modified) {
+        (Ok(date), Some(modified)) => unix_seconds(modified) == unix_seconds(date),
+        _ => false,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// First and last byte, both inclusive
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+}
+
+/// `None` if the header isn't a single byte range, the whole file is served then
+fn parse_range(header: &str, len: u64) -> Option<ByteRange> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len.checked_sub(1))
+        }
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)),
+        (start, end) => {
+            let start: u64 = start.parse().ok()?;
+            let end: u64 = end.parse().ok()?;
+            // an inverted range is invalid rather than unsatisfiable, RFC 9110 ignores it
+            if start > end {
+                return None;
+            }
+            (start, len.checked_sub(1).map(|last| end.min(last)))
+        }
+    };
+
+    match end {
+        Some(end) if start <= end => Some(ByteRange::Satisfiable(start, end)),
+        _ => Some(ByteRange::Unsatisfiable),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const ETAG: &str = "\"abc\"";
+
+    fn headers(name: &axum::http::header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-4", 10),
+            Some(ByteRange::Satisfiable(0, 4))
+        );
+        assert_eq!(
+            parse_range("bytes=5-", 10),
+            Some(ByteRange::Satisfiable(5, 9))
+        );
+        assert_eq!(
+            parse_range("bytes=-3", 10),
+            Some(ByteRange::Satisfiable(7, 9))
+        );
+        // the end is clamped to the last byte
+        assert_eq!(
+            parse_range("bytes=8-100", 10),
+            Some(ByteRange::Satisfiable(8, 9))
+        );
+        assert_eq!(parse_range("bytes=10-", 10), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_range("bytes=0-", 0), Some(ByteRange::Unsatisfiable));
+    }
+
+    #[test]
+    fn ignores_invalid_ranges() {
+        assert_eq!(parse_range("bytes=5-3", 10), None);
+        assert_eq!(parse_range("bytes=0-1,3-4", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+        assert_eq!(parse_range("bytes=5", 10), None);
+    }
+
+    #[test]
+    fn reads_accept_encoding() {
+        assert!(accepts("gzip, br", "br"));
+        assert!(accepts("GZIP;q=0.5", "gzip"));
+        assert!(!accepts("gzip;q=0", "gzip"));
+        assert!(!accepts("gzip", "br"));
+        assert!(!accepts("", "gzip"));
+    }
+
+    #[test]
+    fn checks_if_none_match_before_if_modified_since() {
+        let modified = Some(UNIX_EPOCH + Duration::from_secs(1_000_000));
+        let later = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(2_000_000));
+
+        assert!(not_modified(
+            &headers(&IF_NONE_MATCH, "\"x\", W/\"abc\""),
+            ETAG,
+            None
+        ));
+        assert!(not_modified(&headers(&IF_NONE_MATCH, "*"), ETAG, None));
+        assert!(not_modified(
+            &headers(&IF_MODIFIED_SINCE, &later),
+            ETAG,
+            modified
+        ));
+        assert!(!not_modified(
+            &headers(&IF_MODIFIED_SINCE, &later),
+            ETAG,
+            None
+        ));
+        assert!(!not_modified(&HeaderMap::new(), ETAG, modified));
+
+        let mut both = headers(&IF_NONE_MATCH, "\"x\"");
+        both.insert(IF_MODIFIED_SINCE, HeaderValue::from_str(&later).unwrap());
+        assert!(!not_modified(&both, ETAG, modified));
+    }
+
+    #[test]
+    fn matches_if_range() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let date = httpdate::fmt_http_date(modified);
+
+        assert!(if_range_matches(&HeaderMap::new(), ETAG, None));
+        assert!(if_range_matches(&headers(&IF_RANGE, ETAG), ETAG, None));
+        assert!(!if_range_matches(&headers(&IF_RANGE, "\"x\""), ETAG, None));
+        assert!(if_range_matches(
+            &headers(&IF_RANGE, &date),
+            ETAG,
+            Some(modified)
+        ));
+        assert!(!if_range_matches(
+            &headers(&IF_RANGE, &date),
+            ETAG,
+            Some(modified + Duration::from_secs(1))
+        ));
+        assert!(!if_range_matches(
+            &headers(&IF_RANGE, "garbage"),
+            ETAG,
+            Some(modified)
+        ));
+    }
+}
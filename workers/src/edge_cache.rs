@@ -0,0 +1,416 @@
+use anyhow::Result;
+use axum::body::{Body, Bytes};
+use axum::http::header::{HeaderName, AGE, HOST};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use cache::policy;
+use chrono::{DateTime, Utc};
+use config::EdgeCacheConfig;
+use entity::cache_purge;
+use lru_cache::LruCache;
+use migration::sea_orm::{
+    ColumnTrait, Database, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Bigger responses are always passed through
+const MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
+/// Purges made through the API are applied after at most this long
+const PURGE_INTERVAL: Duration = Duration::from_secs(2);
+/// Purges are read again for this long after they were created, see `AppliedPurges`
+const PURGE_OVERLAP_SECONDS: i64 = 60;
+/// Copied from the cached response onto a 304
+const NOT_MODIFIED_HEADERS: [&str; 7] = [
+    "cache-control",
+    "content-location",
+    "date",
+    "etag",
+    "expires",
+    "last-modified",
+    "vary",
+];
+
+/// A GET or HEAD request that can be answered from the cache, taken apart before the request
+/// is handed to the runtime
+pub struct CacheableRequest {
+    method: Method,
+    key: String,
+    headers: Vec<(String, String)>,
+}
+
+impl CacheableRequest {
+    /// `None` for requests that always go to the app, e.g. ones with credentials
+    pub fn new(request: &Request<Body>) -> Option<Self> {
+        if request.method() != Method::GET && request.method() != Method::HEAD {
+            return None;
+        }
+        if request.headers().contains_key("authorization") {
+            return None;
+        }
+
+        let host = request
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| request.uri().authority().map(|host| host.as_str()))?;
+
+        Some(Self {
+            method: request.method().clone(),
+            key: cache_key(host, request.uri()),
+            headers: header_list(request.headers()),
+        })
+    }
+}
+
+/// Responses are cached per host, path and query, so one key never spans two apps
+fn cache_key(host: &str, uri: &Uri) -> String {
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    format!("{}{}", host.to_ascii_lowercase(), path)
+}
+
+fn header_list(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+struct Entry {
+    /// The values the request had for the headers named by `Vary`
+    vary: Vec<(String, Option<String>)>,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+impl Entry {
+    fn matches(&self, request: &CacheableRequest) -> bool {
+        self.expires_at > Utc::now()
+            && self
+                .vary
+                .iter()
+                .all(|(name, value)| policy::header(&request.headers, name) == *value)
+    }
+
+    fn size(&self) -> usize {
+        self.body.len()
+            + self
+                .headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum::<usize>()
+    }
+
+    /// A cached response for the request, a 304 if the client has it already
+    fn respond(&self, request: &CacheableRequest) -> Response<Body> {
+        let age = (Utc::now() - self.stored_at).num_seconds().max(0);
+
+        let mut response = if not_modified(request, &self.headers) {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_MODIFIED;
+            for name in NOT_MODIFIED_HEADERS {
+                for value in self.headers.get_all(name) {
+                    response
+                        .headers_mut()
+                        .append(HeaderName::from_static(name), value.clone());
+                }
+            }
+            response
+        } else {
+            let body = if request.method == Method::HEAD {
+                Body::empty()
+            } else {
+                Body::from(self.body.clone())
+            };
+            let mut response = Response::new(body);
+            *response.status_mut() = self.status;
+            *response.headers_mut() = self.headers.clone();
+            response
+        };
+
+        let headers = response.headers_mut();
+        headers.insert(AGE, HeaderValue::from(age));
+        headers.insert("x-edge-cache", HeaderValue::from_static("HIT"));
+        response
+    }
+}
+
+/// `If-None-Match` wins over `If-Modified-Since`, like RFC 9110 asks for
+fn not_modified(request: &CacheableRequest, headers: &HeaderMap) -> bool {
+    if let Some(if_none_match) = policy::header(&request.headers, "if-none-match") {
+        let etag = match headers.get("etag").and_then(|etag| etag.to_str().ok()) {
+            Some(etag) => etag.trim_start_matches("W/"),
+            None => return false,
+        };
+
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
+    let since = policy::header(&request.headers, "if-modified-since")
+        .and_then(|since| DateTime::parse_from_rfc2822(&since).ok());
+    let last_modified = headers
+        .get("last-modified")
+        .and_then(|last_modified| last_modified.to_str().ok())
+        .and_then(|last_modified| DateTime::parse_from_rfc2822(last_modified).ok());
+
+    match (since, last_modified) {
+        (Some(since), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+struct Entries {
+    /// Variants by user id and cache key
+    lru: LruCache<(i32, String), Vec<Entry>>,
+    bytes: usize,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &(i32, String)) -> Vec<Entry> {
+        let entries = self.lru.remove(key).unwrap_or_default();
+        self.bytes -= entries.iter().map(Entry::size).sum::<usize>();
+        entries
+    }
+}
+
+/// Shared cache of the responses of every app, in front of their runtimes
+#[derive(Clone)]
+pub struct EdgeCache {
+    entries: Arc<Mutex<Entries>>,
+    budget: usize,
+}
+
+impl EdgeCache {
+    #[must_use]
+    pub fn new(config: EdgeCacheConfig) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(Entries {
+                lru: LruCache::new(usize::MAX),
+                bytes: 0,
+            })),
+            budget: config.budget_bytes,
+        }
+    }
+
+    pub fn lookup(&self, user_id: i32, request: &CacheableRequest) -> Option<Response<Body>> {
+        let mut entries = self.entries.lock().unwrap();
+        let variants = entries.lru.get_mut(&(user_id, request.key.clone()))?;
+
+        variants
+            .iter()
+            .filter(|entry| entry.matches(request))
+            .max_by_key(|entry| entry.stored_at)
+            .map(|entry| entry.respond(request))
+    }
+
+    /// Keeps the response of the app if its headers allow a shared cache to, then answers
+    /// the request with it
+    pub async fn store(
+        &self,
+        user_id: i32,
+        request: CacheableRequest,
+        response: Response<Body>,
+    ) -> Response<Body> {
+        if request.method != Method::GET {
+            return response;
+        }
+
+        let response_headers = header_list(response.headers());
+        let lifetime = policy::lifetime(response.status().as_u16(), &response_headers);
+        let names = policy::vary(&response_headers);
+        let (lifetime, names) = match (lifetime, names) {
+            (Some(lifetime), Some(names)) => (lifetime, names),
+            _ => return response,
+        };
+
+        let (parts, body) = response.into_parts();
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(e) => {
+                println!("Failed to read response for the edge cache: {:?}", e);
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::BAD_GATEWAY;
+                return response;
+            }
+        };
+        if body.len() > MAX_BODY_BYTES {
+            return Response::from_parts(parts, Body::from(body));
+        }
+
+        let now = Utc::now();
+        let entry = Entry {
+            vary: names
+                .into_iter()
+                .map(|name| {
+                    let value = policy::header(&request.headers, &name);
+                    (name, value)
+                })
+                .collect(),
+            status: parts.status,
+            headers: parts.headers,
+            body,
+            stored_at: now,
+            expires_at: policy::expires_at(now, lifetime),
+        };
+
+        let mut response = entry.respond(&request);
+        response
+            .headers_mut()
+            .insert("x-edge-cache", HeaderValue::from_static("MISS"));
+        self.insert(user_id, request.key, entry);
+
+        response
+    }
+
+    fn insert(&self, user_id: i32, key: String, entry: Entry) {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (user_id, key);
+
+        // a new response replaces the one for the same request headers
+        let mut variants: Vec<Entry> = entries
+            .remove(&key)
+            .into_iter()
+            .filter(|cached| cached.expires_at > Utc::now() && cached.vary != entry.vary)
+            .collect();
+        variants.push(entry);
+
+        entries.bytes += variants.iter().map(Entry::size).sum::<usize>();
+        entries.lru.insert(key, variants);
+
+        while entries.bytes > self.budget {
+            match entries.lru.remove_lru() {
+                Some((_, evicted)) => {
+                    entries.bytes -= evicted.iter().map(Entry::size).sum::<usize>();
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Drops the cached responses for a URL of the app, or all of them
+    fn purge(&self, user_id: i32, url: Option<&str>) {
+        let mut entries = self.entries.lock().unwrap();
+
+        let keys: Vec<(i32, String)> = match url {
+            Some(url) => match url.parse::<Uri>() {
+                Ok(uri) => uri
+                    .authority()
+                    .map(|host| vec![(user_id, cache_key(host.as_str(), &uri))])
+                    .unwrap_or_default(),
+                Err(_) => vec![],
+            },
+            None => entries
+                .lru
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|(id, _)| *id == user_id)
+                .cloned()
+                .collect(),
+        };
+
+        for key in keys {
+            entries.remove(&key);
+        }
+    }
+
+    /// Applies the purges made through the API, only purges made after the start are relevant
+    pub fn spawn_purge_listener(&self, database_url: String) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let conn = Database::connect(database_url.as_str())
+                .await
+                .expect("Database connection failed");
+
+            let settled = match latest_purge(&conn).await {
+                Ok(last_id) => last_id,
+                Err(e) => {
+                    println!("Failed to get purges: {:?}", e);
+                    0
+                }
+            };
+            let mut applied = AppliedPurges {
+                settled,
+                recent: HashMap::new(),
+            };
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = cache.apply_purges(&conn, &mut applied).await {
+                    println!("Failed to apply purges: {:?}", e);
+                }
+            }
+        });
+    }
+
+    /// Applies the purges that weren't applied yet
+    async fn apply_purges(
+        &self,
+        conn: &DatabaseConnection,
+        applied: &mut AppliedPurges,
+    ) -> Result<()> {
+        let purges = cache_purge::Entity::find()
+            .filter(cache_purge::Column::Id.gt(applied.settled))
+            .order_by_asc(cache_purge::Column::Id)
+            .all(conn)
+            .await?;
+
+        for purge in purges {
+            if applied.recent.contains_key(&purge.id) {
+                continue;
+            }
+
+            self.purge(purge.user_id, purge.url.as_deref());
+            applied
+                .recent
+                .insert(purge.id, purge.created_at.with_timezone(&Utc));
+        }
+
+        applied.settle(Utc::now() - chrono::Duration::seconds(PURGE_OVERLAP_SECONDS));
+        Ok(())
+    }
+}
+
+/// The purges a worker applied. Serial ids are assigned at insert and not at commit, so a
+/// purge can become visible after one with a higher id. Purges are only skipped by id once
+/// they are older than `PURGE_OVERLAP_SECONDS`, until then they are read again
+#[derive(Debug)]
+struct AppliedPurges {
+    /// Every purge up to this id was applied or is too old to still become visible
+    settled: i32,
+    /// The purges above `settled` that were applied, with the time they were created
+    recent: HashMap<i32, DateTime<Utc>>,
+}
+
+impl AppliedPurges {
+    /// Moves `settled` past the purges created before `cutoff`
+    fn settle(&mut self, cutoff: DateTime<Utc>) {
+        let settled = self
+            .recent
+            .iter()
+            .filter(|(_, created_at)| **created_at < cutoff)
+            .map(|(id, _)| *id)
+            .max();
+
+        if let Some(settled) = settled {
+            self.settled = settled;
+            self.recent.retain(|id, _| *id > settled);
+        }
+    }
+}
+
+async fn latest_purge(conn: &DatabaseConnection) -> Result<i32> {
+    let purge = cache_purge::Entity::find()
+        .order_by_desc(cache_purge::Column::Id)
+        .one(conn)
+        .await?;
+
+    Ok(purge.map_or(0, |purge| purge.id))
+}
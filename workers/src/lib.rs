@@ -3,12 +3,14 @@
 #![warn(clippy::nursery)]
 #![allow(clippy::future_not_send)]
 #![allow(clippy::diverging_sub_expression)]
-use app::App;
+use anyhow::Context;
+use app::{App, RuntimeChannelPayload};
 use async_zip::read::mem::ZipFileReader;
 use axum::body::Body;
-use axum::extract::Extension;
-use axum::http::{Request, Response, StatusCode};
+use axum::extract::{ConnectInfo, Extension};
+use axum::http::{HeaderValue, Request, Response, StatusCode};
 use axum::{routing::any, Router};
+use config::Config;
 use migration::sea_orm::{Database, EntityTrait};
 use session::Session;
 use std::net::SocketAddr;
@@ -19,26 +21,56 @@ use std::time::Duration;
 use tokio::sync::oneshot::{self};
 use tokio::sync::RwLock;
 
+use edge_cache::{CacheableRequest, EdgeCache};
 use entity::user;
+use manifest::Manifest;
+use metadata::{RequestMetadata, TrustedProxies};
+use tls::TlsServer;
+use websocket::Upgrade;
 
+mod actor;
 pub mod app;
+mod assets;
+mod edge_cache;
+pub mod manifest;
+mod metadata;
+mod queue;
 mod runtime;
 mod snapshot;
+mod sweeper;
+mod tls;
+mod websocket;
 
 #[derive(Clone)]
 struct AppState {
     apps: Arc<RwLock<Vec<App>>>,
+    trusted_proxies: TrustedProxies,
+    edge_cache: Option<EdgeCache>,
 }
 
+/// Without a default app the deployments of all users are loaded from S3. Only a default app
+/// runs without a database, queues and cache purges are unavailable then
+///
 /// # Errors
 ///
-/// Will return `Err` if webserver panics
-pub async fn run(maybe_default_app: Option<App>) -> anyhow::Result<()> {
+/// Will return `Err` if webserver panics or S3 or the database isn't configured to load the
+/// deployments
+pub async fn run(config: Config, maybe_default_app: Option<App>) -> anyhow::Result<()> {
     let apps: Arc<RwLock<Vec<App>>>;
     if let Some(default_app) = maybe_default_app {
         apps = Arc::new(RwLock::new(vec![default_app]));
     } else {
-        apps = Arc::new(RwLock::new(setup(&[]).await));
+        let s3 = config
+            .s3
+            .as_ref()
+            .context("S3 has to be configured to load the deployments")?;
+        let deployments = Deployments {
+            database_url: config.database_url()?.to_string(),
+            config: config.clone(),
+            bucket: s3.bucket()?,
+            blobs_bucket: s3.blobs_bucket()?,
+        };
+        apps = Arc::new(RwLock::new(setup(&deployments, &[]).await));
 
         let apps2 = apps.clone();
         tokio::spawn(async move {
@@ -48,94 +80,72 @@ pub async fn run(maybe_default_app: Option<App>) -> anyhow::Result<()> {
                 interval.tick().await;
                 let new_apps = {
                     let existing_apps = apps2.read().await;
-                    setup(&existing_apps).await
+                    setup(&deployments, &existing_apps).await
                 };
                 *apps2.write().await = new_apps;
             }
         });
     }
 
-    let app_state = AppState { apps };
+    let edge_cache = config.edge_cache.map(EdgeCache::new);
+    if let Ok(database_url) = config.database_url() {
+        queue::spawn_consumer(apps.clone());
+        sweeper::spawn(database_url.to_string());
+
+        if let Some(edge_cache) = &edge_cache {
+            edge_cache.spawn_purge_listener(database_url.to_string());
+        }
+    }
+
+    let app_state = AppState {
+        apps,
+        trusted_proxies: TrustedProxies::new(config.trusted_proxies.clone()),
+        edge_cache,
+    };
 
     let worker_app = Router::new()
         .route("/*key", any(handler))
         .layer(Extension(Arc::new(app_state)));
-    let worker_addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    let worker_addr = config.workers_listen;
+    let tls_server = config.tls.as_ref().map(TlsServer::new).transpose()?;
 
     println!("Workers listening on {}", worker_addr);
 
-    axum::Server::bind(&worker_addr)
-        .serve(worker_app.into_make_service())
-        .await?;
+    // HTTP/2 without TLS (h2c) is detected by hyper itself
+    let server = axum::Server::bind(&worker_addr).serve(
+        worker_app
+            .clone()
+            .into_make_service_with_connect_info::<SocketAddr, _>(),
+    );
+
+    match tls_server {
+        Some(tls_server) => {
+            tokio::try_join!(
+                async { server.await.map_err(anyhow::Error::from) },
+                tls_server.serve(worker_app)
+            )?;
+        }
+        None => server.await?,
+    }
 
     Ok(())
 }
 
-fn init_bucket() -> s3::Bucket {
-    let credentials = s3::creds::Credentials::new(
-        Some(
-            std::env::var("S3_ACCESS_KEY")
-                .expect("S3_ACCESS_KEY not found")
-                .as_str(),
-        ),
-        Some(
-            std::env::var("S3_SECRET_KEY")
-                .expect("S3_SECRET_KEY not found")
-                .as_str(),
-        ),
-        None,
-        None,
-        None,
-    )
-    .unwrap();
-
-    let maybe_endpoint = std::env::var("S3_ENDPOINT");
-
-    if maybe_endpoint.is_err() {
-        let bucket = s3::Bucket::new(
-            std::env::var("S3_BUCKET")
-                .expect("S3_BUCKET not found")
-                .as_str(),
-            s3::Region::from_str(
-                std::env::var("S3_REGION")
-                    .expect("S3_REGION not found")
-                    .as_str(),
-            )
-            .expect("Unknown region"),
-            credentials,
-        )
-        .unwrap();
-
-        return bucket;
-    }
-
-    let region = s3::Region::Custom {
-        region: std::env::var("S3_REGION").expect("S3_REGION not found"),
-        endpoint: maybe_endpoint.unwrap(),
-    };
-
-    let bucket = s3::Bucket::new_with_path_style(
-        std::env::var("S3_BUCKET")
-            .expect("S3_BUCKET not found")
-            .as_str(),
-        region,
-        credentials,
-    )
-    .unwrap();
-
-    bucket
+/// Where the deployments of the users are loaded from
+struct Deployments {
+    database_url: String,
+    /// The settings the sessions of the apps are created with
+    config: Config,
+    bucket: s3::Bucket,
+    blobs_bucket: s3::Bucket,
 }
 
-async fn setup(existing_apps: &[App]) -> Vec<App> {
-    let conn = Database::connect(
-        std::env::var("DATABASE_URL")
-            .expect("No DATABASE_URL environment variable found.")
-            .as_str(),
-    )
-    .await
-    .expect("Database connection failed");
+async fn setup(deployments: &Deployments, existing_apps: &[App]) -> Vec<App> {
+    let conn = Database::connect(deployments.database_url.as_str())
+        .await
+        .expect("Database connection failed");
 
-    let bucket = init_bucket();
+    let bucket = &deployments.bucket;
     let users = user::Entity::find()
         .all(&conn)
         .await
@@ -183,17 +193,25 @@ async fn setup(existing_apps: &[App]) -> Vec<App> {
             reader.copy_to_end_crc(&mut output, 65536).await.unwrap();
         }
 
-        let session = Session {
-            user_id: user.id,
-            conn: conn.clone(),
+        let path = PathBuf::from_str(parent_dir.as_str()).unwrap();
+        let manifest = match Manifest::load(&path) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                println!("Invalid deployment {}: {:?}", deployment_path, e);
+                continue;
+            }
         };
 
+        let session = Session::new(user.id, conn.clone(), &deployments.config);
+
         let app = App::new(
             session,
             user.name.clone(),
-            PathBuf::from_str(parent_dir.clone().as_str()).unwrap(),
-            "main.js".into(),
+            path,
+            manifest.main,
+            manifest.assets,
             deployment_path.into(),
+            Some(deployments.blobs_bucket.clone()),
         );
 
         println!("New deployment found: {}", deployment_path);
@@ -205,30 +223,73 @@ async fn setup(existing_apps: &[App]) -> Vec<App> {
     apps
 }
 
-async fn handler(Extension(state): Extension<Arc<AppState>>, req: Request<Body>) -> Response<Body> {
-    let (tx, rx) = oneshot::channel::<Response<Body>>();
+async fn handler(
+    Extension(state): Extension<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut req: Request<Body>,
+) -> Response<Body> {
+    let metadata = RequestMetadata::new(&req, peer, &state.trusted_proxies);
+    req.extensions_mut().insert(metadata);
 
-    let header = req.headers().get("x-app");
-    if let Some(header_value) = header {
-        let guard = state.apps.read().await;
-        let app = guard
-            .iter()
-            .find(|it| it.name == header_value.to_str().unwrap())
-            .unwrap();
-        let runtime_channel = app.get_runtime().await;
-        runtime_channel.send((req, tx)).await.unwrap();
-    } else {
+    let maybe_app = {
         let guard = state.apps.read().await;
-        let app = guard.get(0);
-        if let Some(app) = app {
-            let runtime_channel = app.get_runtime().await;
-            runtime_channel.send((req, tx)).await.unwrap();
-        } else {
+        match req.headers().get("x-app").map(HeaderValue::to_str) {
+            Some(Ok(name)) => guard
+                .iter()
+                .find(|it| it.name == name)
+                .cloned()
+                .ok_or(StatusCode::NOT_FOUND),
+            Some(Err(_)) => Err(StatusCode::BAD_REQUEST),
+            None => guard.get(0).cloned().ok_or(StatusCode::BAD_REQUEST),
+        }
+    };
+
+    let app = match maybe_app {
+        Ok(app) => app,
+        Err(status) => {
             let mut response = Response::new(Body::empty());
-            *response.status_mut() = StatusCode::BAD_REQUEST;
-            tx.send(response).unwrap();
+            *response.status_mut() = status;
+            return response;
         }
+    };
+
+    if let Some(upgrade) = Upgrade::new(&mut req) {
+        let response = dispatch(&app, req).await;
+        return upgrade.accept(response);
+    }
+
+    if let Some(assets) = &app.assets {
+        if let Some(response) = assets::serve(assets, &req).await {
+            return response;
+        }
+    }
+
+    let edge_cache = match &state.edge_cache {
+        Some(edge_cache) => edge_cache,
+        None => return dispatch(&app, req).await,
+    };
+    let cacheable = match CacheableRequest::new(&req) {
+        Some(cacheable) => cacheable,
+        None => return dispatch(&app, req).await,
+    };
+
+    let user_id = app.session.user_id;
+    if let Some(response) = edge_cache.lookup(user_id, &cacheable) {
+        return response;
     }
 
+    let response = dispatch(&app, req).await;
+    edge_cache.store(user_id, cacheable, response).await
+}
+
+async fn dispatch(app: &App, req: Request<Body>) -> Response<Body> {
+    let (tx, rx) = oneshot::channel::<Response<Body>>();
+
+    let runtime_channel = app.get_runtime().await;
+    runtime_channel
+        .send(RuntimeChannelPayload::Request(req, tx))
+        .await
+        .unwrap();
+
     rx.await.expect("Failed to receive value from V8 runtime.")
 }
@@ -1,3 +1,4 @@
+use config::{Config, Server};
 use dotenv::dotenv;
 use migration::{sea_orm::Database, Migrator, MigratorTrait};
 
@@ -5,17 +6,21 @@ use migration::{sea_orm::Database, Migrator, MigratorTrait};
 async fn main() {
     dotenv().ok();
 
+    let config = match Config::load(&[Server::Workers]) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     {
-        let conn = Database::connect(
-            std::env::var("DATABASE_URL")
-                .expect("No DATABASE_URL environment variable found.")
-                .as_str(),
-        )
-        .await
-        .expect("Database connection failed");
+        let conn = Database::connect(config.database_url().unwrap())
+            .await
+            .expect("Database connection failed");
 
         Migrator::up(&conn, None).await.unwrap();
     }
 
-    workers::run(None).await.unwrap();
+    workers::run(config, None).await.unwrap();
 }
@@ -0,0 +1,71 @@
+use anyhow::{bail, Context, Result};
+use deno_core::serde_json;
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
+
+/// Optional file at the root of a deployment describing its layout
+pub const MANIFEST_FILE_NAME: &str = "workers.json";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    main: Option<String>,
+    assets: Option<String>,
+}
+
+/// Layout of a deployment, resolved against the directory it was extracted to
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// The script, relative to the deployment
+    pub main: String,
+    /// Files in here are served without invoking the script
+    pub assets: Option<PathBuf>,
+}
+
+impl Manifest {
+    /// Deployments without a manifest run `main.js` and have no assets
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the manifest is invalid or points outside of the deployment
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE_NAME);
+        let file: ManifestFile = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid {}", MANIFEST_FILE_NAME))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ManifestFile {
+                main: None,
+                assets: None,
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let main = file.main.unwrap_or_else(|| "main.js".into());
+        check_relative(&main)?;
+
+        let assets = match file.assets {
+            Some(assets) => {
+                check_relative(&assets)?;
+                Some(dir.join(assets))
+            }
+            None => None,
+        };
+
+        Ok(Self { main, assets })
+    }
+}
+
+fn check_relative(path: &str) -> Result<()> {
+    let is_relative = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !is_relative {
+        bail!(
+            "Paths in {} have to stay inside of the deployment: {}",
+            MANIFEST_FILE_NAME,
+            path
+        );
+    }
+
+    Ok(())
+}
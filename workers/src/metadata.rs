@@ -0,0 +1,195 @@
+use axum::body::Body;
+use axum::http::Request;
+use ipnet::IpNet;
+use rand::Rng;
+use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use crate::tls::Tls;
+
+/// Metadata about the client and the request, exposed to scripts as `request.cf`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestMetadata {
+    pub remote_address: String,
+    pub protocol: String,
+    pub http_version: String,
+    pub request_id: String,
+    pub received_at: i64,
+}
+
+/// Proxies that are allowed to set the `X-Forwarded-*` and `X-Request-Id` headers
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    #[must_use]
+    pub const fn new(nets: Vec<IpNet>) -> Self {
+        Self(nets)
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+}
+
+impl RequestMetadata {
+    pub fn new(
+        request: &Request<Body>,
+        peer: SocketAddr,
+        trusted_proxies: &TrustedProxies,
+    ) -> Self {
+        let from_trusted_proxy = trusted_proxies.contains(&peer.ip());
+        let forwarded_header = |name: &str| {
+            if !from_trusted_proxy {
+                return None;
+            }
+
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        let remote_address = from_trusted_proxy
+            .then(|| forwarded_for(request))
+            .flatten()
+            .and_then(|value| client_address(&value, trusted_proxies))
+            .unwrap_or_else(|| peer.ip())
+            .to_string();
+
+        let protocol = forwarded_header("x-forwarded-proto").unwrap_or_else(|| {
+            if request.extensions().get::<Tls>().is_some() {
+                "https".into()
+            } else {
+                "http".into()
+            }
+        });
+
+        let request_id = forwarded_header("x-request-id").unwrap_or_else(|| {
+            let bytes: [u8; 16] = rand::thread_rng().gen();
+            bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+        });
+
+        Self {
+            remote_address,
+            protocol,
+            http_version: format!("{:?}", request.version()),
+            request_id,
+            received_at: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+}
+
+/// Every `X-Forwarded-For` line in order, a proxy may append its own line instead of
+/// extending the one the client sent
+fn forwarded_for(request: &Request<Body>) -> Option<String> {
+    let lines: Vec<&str> = request
+        .headers()
+        .get_all("x-forwarded-for")
+        .iter()
+        .map(|value| value.to_str().ok())
+        .collect::<Option<_>>()?;
+
+    Some(lines.join(",")).filter(|value| !value.trim().is_empty())
+}
+
+/// Walks `X-Forwarded-For` from right to left, the first address that isn't one of
+/// our own proxies is the client. Entries left of it are up to the client and ignored
+fn client_address(forwarded_for: &str, trusted_proxies: &TrustedProxies) -> Option<IpAddr> {
+    let mut client = None;
+    for entry in forwarded_for.split(',').rev() {
+        let ip = IpAddr::from_str(entry.trim()).ok()?;
+        client = Some(ip);
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies::new(vec![
+            IpNet::from_str("10.0.0.0/8").unwrap(),
+            IpNet::from_str("192.168.1.1/32").unwrap(),
+        ])
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        IpAddr::from_str(value).unwrap()
+    }
+
+    fn remote_address(peer: &str, lines: &[&str]) -> String {
+        let mut request = Request::builder();
+        for line in lines {
+            request = request.header("x-forwarded-for", *line);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        let peer = SocketAddr::new(IpAddr::from_str(peer).unwrap(), 4000);
+
+        RequestMetadata::new(&request, peer, &proxies()).remote_address
+    }
+
+    #[test]
+    fn skips_trusted_proxies() {
+        let proxies = proxies();
+
+        assert_eq!(client_address("1.2.3.4", &proxies), Some(ip("1.2.3.4")));
+        assert_eq!(
+            client_address("1.2.3.4, 10.0.0.2, 192.168.1.1", &proxies),
+            Some(ip("1.2.3.4"))
+        );
+        // the client can put anything in front of its own address
+        assert_eq!(
+            client_address("6.6.6.6, 1.2.3.4, 10.0.0.2", &proxies),
+            Some(ip("1.2.3.4"))
+        );
+        // only proxies, the one furthest away is the closest to the client
+        assert_eq!(
+            client_address("10.0.0.3, 10.0.0.2", &proxies),
+            Some(ip("10.0.0.3"))
+        );
+    }
+
+    #[test]
+    fn ignores_garbage_left_of_the_client() {
+        let proxies = proxies();
+
+        assert_eq!(
+            client_address("garbage, 1.2.3.4, 10.0.0.2", &proxies),
+            Some(ip("1.2.3.4"))
+        );
+        assert_eq!(client_address("1.2.3.4, garbage", &proxies), None);
+        assert_eq!(client_address("1.2.3.4, , 10.0.0.2", &proxies), None);
+        assert_eq!(client_address("", &proxies), None);
+    }
+
+    #[test]
+    fn ignores_headers_of_untrusted_peers() {
+        assert_eq!(remote_address("5.5.5.5", &["1.2.3.4"]), "5.5.5.5");
+        assert_eq!(remote_address("10.0.0.1", &["1.2.3.4"]), "1.2.3.4");
+        assert_eq!(remote_address("10.0.0.1", &[]), "10.0.0.1");
+        assert_eq!(remote_address("10.0.0.1", &["garbage"]), "10.0.0.1");
+    }
+
+    #[test]
+    fn joins_every_header_line() {
+        // the client's own line comes first, the proxy appended the real address
+        assert_eq!(
+            remote_address("10.0.0.1", &["6.6.6.6", "1.2.3.4, 10.0.0.2"]),
+            "1.2.3.4"
+        );
+        assert_eq!(
+            remote_address("10.0.0.1", &["6.6.6.6", "1.2.3.4"]),
+            "1.2.3.4"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::Result;
+use entity::{dead_letter, queue_message};
+use migration::sea_orm::ActiveValue::Set;
+use migration::sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, QueryFilter,
+    Statement,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, RwLock};
+
+use crate::app::{App, RuntimeChannelPayload};
+
+/// Maximum amount of messages delivered to a queue handler at once
+const BATCH_SIZE: i32 = 10;
+/// Messages are moved to the dead letter table after this many failed deliveries
+const MAX_ATTEMPTS: i32 = 3;
+/// How long a claimed message stays invisible to other consumers
+const VISIBILITY_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize)]
+pub struct QueueBatch {
+    pub queue: String,
+    pub messages: Vec<QueueMessage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueMessage {
+    pub id: i32,
+    pub body: String,
+    pub attempts: i32,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueueBatchResult {
+    pub retry: Vec<i32>,
+    /// The script has no queue handler, none of the messages were looked at
+    #[serde(default)]
+    pub unhandled: bool,
+}
+
+/// Polls the queue tables and delivers batches to the `onQueue` handler of every app
+pub fn spawn_consumer(apps: Arc<RwLock<Vec<App>>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            let apps = apps.read().await.clone();
+
+            let handles: Vec<_> = apps
+                .into_iter()
+                .map(|app| {
+                    tokio::spawn(async move {
+                        if let Err(e) = dispatch(&app).await {
+                            println!(
+                                "Failed to dispatch queue messages for {}: {:?}",
+                                app.name, e
+                            );
+                        }
+                    })
+                })
+                .collect();
+
+            for handle in handles {
+                handle.await.unwrap_or(());
+            }
+        }
+    });
+}
+
+async fn dispatch(app: &App) -> Result<()> {
+    if !app.has_queue_handler() {
+        return Ok(());
+    }
+
+    let conn = &app.session.conn;
+    let claimed = claim(conn, app.session.user_id).await?;
+    if claimed.is_empty() {
+        return Ok(());
+    }
+    let mut unsettled: Vec<i32> = claimed.iter().map(|message| message.id).collect();
+
+    let mut by_queue: BTreeMap<String, Vec<queue_message::Model>> = BTreeMap::new();
+    for message in claimed {
+        by_queue
+            .entry(message.queue.clone())
+            .or_default()
+            .push(message);
+    }
+
+    for (queue, messages) in by_queue {
+        let batch = QueueBatch {
+            queue,
+            messages: messages
+                .iter()
+                .map(|message| QueueMessage {
+                    id: message.id,
+                    body: message.body.clone(),
+                    attempts: message.attempts,
+                    timestamp: message.created_at.timestamp_millis(),
+                })
+                .collect(),
+        };
+
+        let retry = match deliver(app, batch).await {
+            Ok(result) if result.unhandled => {
+                println!(
+                    "{} has no queue handler, its messages stay queued for a later deployment",
+                    app.name
+                );
+                app.set_no_queue_handler();
+                return release(conn, &unsettled).await;
+            }
+            Ok(result) => result.retry,
+            Err(e) => {
+                println!("Queue batch failed for {}: {:?}", app.name, e);
+                messages.iter().map(|message| message.id).collect()
+            }
+        };
+
+        unsettled.retain(|id| !messages.iter().any(|message| message.id == *id));
+        settle(conn, messages, &retry).await?;
+    }
+
+    Ok(())
+}
+
+async fn deliver(app: &App, batch: QueueBatch) -> Result<QueueBatchResult> {
+    let (tx, rx) = oneshot::channel();
+    let runtime_channel = app.get_runtime().await;
+    runtime_channel
+        .send(RuntimeChannelPayload::Queue(batch, tx))
+        .await
+        .map_err(|_| anyhow::anyhow!("Runtime stopped before the batch was delivered"))?;
+
+    tokio::time::timeout(VISIBILITY_TIMEOUT, rx).await??
+}
+
+/// Claims a batch of visible messages by pushing their visibility into the future,
+/// `SKIP LOCKED` makes sure concurrent consumers never claim the same message
+async fn claim(conn: &DatabaseConnection, user_id: i32) -> Result<Vec<queue_message::Model>> {
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"
+        UPDATE queue_messages
+        SET attempts = attempts + 1, visible_at = now() + $2::int * interval '1 second'
+        WHERE id IN (
+            SELECT id FROM queue_messages
+            WHERE user_id = $1 AND visible_at <= now()
+            ORDER BY id
+            LIMIT $3
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+        vec![
+            user_id.into(),
+            i32::try_from(VISIBILITY_TIMEOUT.as_secs())?.into(),
+            BATCH_SIZE.into(),
+        ],
+    );
+
+    let mut messages = queue_message::Entity::find()
+        .from_raw_sql(statement)
+        .all(conn)
+        .await?;
+    messages.sort_by_key(|message| message.id);
+
+    Ok(messages)
+}
+
+/// Undoes the claim of messages that were never delivered, without using up an attempt
+async fn release(conn: &DatabaseConnection, ids: &[i32]) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let placeholders: Vec<String> = (1..=ids.len()).map(|index| format!("${}", index)).collect();
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        &format!(
+            "UPDATE queue_messages SET attempts = attempts - 1, visible_at = now() WHERE id IN ({})",
+            placeholders.join(", ")
+        ),
+        ids.iter().map(|id| (*id).into()).collect::<Vec<_>>(),
+    );
+    conn.execute(statement).await?;
+
+    Ok(())
+}
+
+/// Acknowledged messages are deleted, retried messages become visible again after a backoff
+/// or end up in the dead letter table once they ran out of attempts
+async fn settle(
+    conn: &DatabaseConnection,
+    messages: Vec<queue_message::Model>,
+    retry: &[i32],
+) -> Result<()> {
+    let txn = conn.begin().await?;
+    let now = chrono::Utc::now();
+
+    let mut to_delete: Vec<i32> = vec![];
+    for message in messages {
+        if !retry.contains(&message.id) {
+            to_delete.push(message.id);
+            continue;
+        }
+
+        if message.attempts >= MAX_ATTEMPTS {
+            let to_be_inserted = dead_letter::ActiveModel {
+                user_id: Set(message.user_id),
+                queue: Set(message.queue),
+                body: Set(message.body),
+                attempts: Set(message.attempts),
+                enqueued_at: Set(message.created_at),
+                created_at: Set(chrono::DateTime::into(now)),
+                ..Default::default()
+            };
+            dead_letter::Entity::insert(to_be_inserted)
+                .exec(&txn)
+                .await?;
+
+            to_delete.push(message.id);
+            continue;
+        }
+
+        let backoff = chrono::Duration::seconds(i64::from(message.attempts) * 5);
+        let to_be_updated = queue_message::ActiveModel {
+            id: Set(message.id),
+            visible_at: Set(chrono::DateTime::into(now + backoff)),
+            ..Default::default()
+        };
+        queue_message::Entity::update(to_be_updated)
+            .exec(&txn)
+            .await?;
+    }
+
+    if !to_delete.is_empty() {
+        queue_message::Entity::delete_many()
+            .filter(queue_message::Column::Id.is_in(to_delete))
+            .exec(&txn)
+            .await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(())
+}
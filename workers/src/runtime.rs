@@ -1,4 +1,5 @@
-use anyhow::Result;
+use actor::{ActorContext, CallChain};
+use anyhow::{anyhow, bail, Context, Result};
 use axum::body::Body;
 use axum::http::header::HeaderName;
 use axum::http::header::HOST;
@@ -8,56 +9,91 @@ use axum::http::Response;
 use axum::http::StatusCode;
 use deno_broadcast_channel::InMemoryBroadcastChannel;
 use deno_core::error::AnyError;
+use deno_core::futures::future::poll_fn;
 use deno_core::located_script_name;
+use deno_core::ByteString;
 use deno_core::Extension;
 use deno_core::FsModuleLoader;
 use deno_core::JsRuntime;
+use deno_core::ModuleSpecifier;
 use deno_core::RuntimeOptions;
 use deno_runtime::deno_web::BlobStore;
 use deno_runtime::ops;
 use deno_runtime::permissions::Permissions;
 use deno_runtime::worker::WorkerOptions;
 use deno_runtime::BootstrapOptions;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 use session::Session;
-use std::collections::HashMap;
 use std::path::Path;
 use std::rc::Rc;
-use std::str::FromStr;
 use std::sync::Arc;
+use std::task::Poll;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
 use crate::app::RuntimeChannelPayload;
+use crate::metadata::RequestMetadata;
+use crate::queue::{QueueBatch, QueueBatchResult};
 use crate::snapshot;
+use crate::tls::Tls;
+use crate::websocket;
+
+/// How long `waitUntil` tasks may keep a runtime alive once it idles out or shuts down
+const WAIT_UNTIL_GRACE_PERIOD: Duration = Duration::from_secs(30);
 
 pub struct Runtime {
     js_runtime: JsRuntime,
+    /// Settles once the `waitUntil` tasks of a request are done, one per answered request
+    pending: Vec<v8::Global<v8::Promise>>,
 }
 
 impl Runtime {
-    pub fn new(session: Session, script_path: &Path, permissions: Permissions) -> Self {
+    pub async fn new(
+        session: Session,
+        actor_context: ActorContext,
+        blob_store: blobs::BlobStore,
+        script_path: &Path,
+        permissions: Permissions,
+    ) -> Self {
         Self {
-            js_runtime: init(session, script_path, permissions),
+            js_runtime: init(session, actor_context, blob_store, script_path, permissions).await,
+            pending: vec![],
         }
     }
 
     /**
-     * Converts a http request into a Request object used inside the runtime
+     * Converts a http request into a Request object used inside the runtime,
+     * resolves as soon as `respondWith` produced a response. The returned promise
+     * settles once every `waitUntil` task of the request is done.
      */
-    async fn run(&mut self, request: Request<Body>) -> Result<JsResponse> {
+    async fn run(
+        &mut self,
+        request: Request<Body>,
+    ) -> Result<(JsResponse, v8::Global<v8::Promise>)> {
         let js_runtime = &mut self.js_runtime;
 
-        {
+        let pending = {
             let scope = &mut js_runtime.handle_scope();
             let request_obj = v8::Object::new(scope);
 
+            let host = request
+                .headers()
+                .get(HOST)
+                .ok_or_else(|| anyhow!("Request has no host header"))?
+                .to_str()?;
+
             let url_key = v8::String::new(scope, "url").unwrap();
+            let default_scheme = if request.extensions().get::<Tls>().is_some() {
+                "https"
+            } else {
+                "http"
+            };
             let url = format!(
                 "{}://{}{}",
-                request.uri().scheme_str().unwrap_or("http"),
-                request.headers().get(HOST).unwrap().to_str().unwrap(),
+                request.uri().scheme_str().unwrap_or(default_scheme),
+                host,
                 request.uri().path_and_query().unwrap()
             );
             let url_value = v8::String::new(scope, &url).unwrap();
@@ -68,15 +104,29 @@ impl Runtime {
             let method_value = v8::String::new(scope, request.method().as_str()).unwrap();
             request_obj.set(scope, method_key.into(), method_value.into());
 
+            // headers are passed as a list of pairs so repeated headers survive, values are
+            // byte strings which means they're not guaranteed to be valid UTF-8
             let header_key = v8::String::new(scope, "headers").unwrap();
-            let header_object = v8::Object::new(scope);
-            for (key, value) in request.headers() {
+            let header_list = v8::Array::new(scope, i32::try_from(request.headers().len())?);
+            for (index, (key, value)) in request.headers().iter().enumerate() {
                 let key = v8::String::new(scope, key.as_str()).unwrap();
-                let value = v8::String::new(scope, value.to_str().unwrap()).unwrap();
+                let value = v8::String::new_from_one_byte(
+                    scope,
+                    value.as_bytes(),
+                    v8::NewStringType::Normal,
+                )
+                .unwrap();
+
+                let pair = v8::Array::new_with_elements(scope, &[key.into(), value.into()]);
+                header_list.set_index(scope, u32::try_from(index)?, pair.into());
+            }
+            request_obj.set(scope, header_key.into(), header_list.into());
 
-                header_object.set(scope, key.into(), value.into());
+            if let Some(metadata) = request.extensions().get::<RequestMetadata>() {
+                let cf_key = v8::String::new(scope, "cf").unwrap();
+                let cf_value = deno_core::serde_v8::to_v8(scope, metadata)?;
+                request_obj.set(scope, cf_key.into(), cf_value);
             }
-            request_obj.set(scope, header_key.into(), header_object.into());
 
             let body = request.into_body();
             let bytes = hyper::body::to_bytes(body).await?;
@@ -99,26 +149,165 @@ impl Runtime {
 
             let cb = v8::Local::<v8::Function>::try_from(func)?;
             let args = &[request_obj.into()];
-            cb.call(scope, global.into(), args).unwrap();
+            let result = cb.call(scope, global.into(), args).unwrap();
+            let promise = v8::Local::<v8::Promise>::try_from(result)?;
+            v8::Global::new(scope, promise)
+        };
+
+        // drive the event loop only until the response is there, trailing work keeps
+        // running alongside the next requests in `poll_background`
+        let js_response = poll_fn(|cx| {
+            let poll = js_runtime.poll_event_loop(cx, false);
+
+            match take_global::<JsResponse>(js_runtime, "requestResult") {
+                Ok(Some(js_response)) => return Poll::Ready(Ok(js_response)),
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+
+            match poll {
+                Poll::Ready(Ok(())) => Poll::Ready(Err(anyhow!(
+                    "Event loop finished without a call to respondWith"
+                ))),
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await?;
+
+        Ok((js_response, pending))
+    }
+
+    /**
+     * Keeps the event loop running until the outstanding `waitUntil` tasks settled, but
+     * never longer than the grace period. Only used before the runtime stops, while it
+     * serves requests the tasks run in `poll_background`
+     */
+    async fn wait_until(&mut self) {
+        let Self {
+            js_runtime,
+            pending,
+        } = self;
+
+        let settled = poll_fn(|cx| {
+            let poll = js_runtime.poll_event_loop(cx, false);
+            retain_pending(js_runtime, pending);
+            if pending.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            poll
+        });
+
+        match tokio::time::timeout(WAIT_UNTIL_GRACE_PERIOD, settled).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => println!("Error from runtime {:?}", e),
+            Err(_) => println!(
+                "waitUntil tasks didn't finish within {:?}, stopping the runtime anyway.",
+                WAIT_UNTIL_GRACE_PERIOD
+            ),
         }
+        self.pending.clear();
+    }
+
+    /**
+     * Hands a batch of queue messages to the queue handler of the script
+     */
+    async fn run_queue(&mut self, batch: QueueBatch) -> Result<QueueBatchResult> {
+        let js_runtime = &mut self.js_runtime;
 
         {
-            js_runtime.run_event_loop(false).await?;
+            let scope = &mut js_runtime.handle_scope();
+            let batch_value = deno_core::serde_v8::to_v8(scope, batch)?;
+
+            let context = scope.get_current_context();
+            let global = context.global(scope);
+
+            let name = v8::String::new(scope, "callOnQueue").unwrap();
+            let func = global.get(scope, name.into()).unwrap();
+
+            let cb = v8::Local::<v8::Function>::try_from(func)?;
+            let args = &[batch_value];
+            cb.call(scope, global.into(), args).unwrap();
         }
 
-        let js_response = {
-            let context = js_runtime.global_context();
-            let scope = &mut js_runtime.handle_scope();
-            let global = context.open(scope).global(scope);
-            let name = v8::String::new(scope, "requestResult").unwrap();
-            let response = global.get(scope, name.into()).unwrap();
-            global.delete(scope, name.into()).unwrap();
+        // sockets and timers of earlier requests can keep the event loop alive forever, so
+        // it's only driven until the handler is done
+        poll_fn(|cx| {
+            let poll = js_runtime.poll_event_loop(cx, false);
 
-            let js_response: JsResponse = deno_core::serde_v8::from_v8(scope, response)?;
-            js_response
-        };
+            match take_global::<QueueBatchResult>(js_runtime, "queueResult") {
+                Ok(Some(result)) => return Poll::Ready(Ok(result)),
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+
+            match poll {
+                Poll::Ready(Ok(())) => {
+                    Poll::Ready(Err(anyhow!("Queue handler finished without a result")))
+                }
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
+    }
 
-        Ok(js_response)
+    /**
+     * Hands the `WebSocketPair` the script returned to the connection of the request
+     */
+    fn respond(&mut self, js_response: JsResponse, is_upgrade: bool) -> Result<Response<Body>> {
+        let web_socket = js_response.web_socket;
+        let mut response = into_response(js_response)?;
+
+        if let Some(rid) = web_socket {
+            if !is_upgrade {
+                bail!("A WebSocket can only be returned for a WebSocket upgrade request");
+            }
+
+            let connection = sockets::connect(&mut self.js_runtime.op_state().borrow_mut(), rid)?;
+            *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+            response.extensions_mut().insert(connection);
+        }
+
+        Ok(response)
+    }
+
+    /**
+     * Drives the event loop between requests for the open WebSockets and the `waitUntil`
+     * tasks of earlier requests, until all sockets are closed and all tasks settled
+     */
+    async fn poll_background(&mut self) -> Result<()> {
+        let Self {
+            js_runtime,
+            pending,
+        } = self;
+
+        poll_fn(|cx| {
+            let poll = js_runtime.poll_event_loop(cx, false);
+            retain_pending(js_runtime, pending);
+            let op_state = js_runtime.op_state();
+            let mut op_state = op_state.borrow_mut();
+            if !sockets::has_connected(&mut op_state) && pending.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            match poll {
+                // nothing in the isolate is waiting for the sockets or tasks anymore
+                Poll::Ready(Ok(())) => {
+                    sockets::disconnect_all(&mut op_state);
+                    pending.clear();
+                    Poll::Ready(Ok(()))
+                }
+                Poll::Ready(Err(e)) => {
+                    sockets::disconnect_all(&mut op_state);
+                    pending.clear();
+                    Poll::Ready(Err(e))
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
     }
 
     pub fn terminate(&mut self) {
@@ -126,42 +315,61 @@ impl Runtime {
         isolate.terminate_execution();
     }
 
-    pub async fn handle_request(&mut self, rx: &mut mpsc::Receiver<RuntimeChannelPayload>) {
+    pub async fn handle_request(
+        &mut self,
+        rx: &mut mpsc::Receiver<RuntimeChannelPayload>,
+        idle_timeout: Duration,
+    ) {
         loop {
-            let sleep = tokio::time::sleep(Duration::from_secs(5));
+            let sleep = tokio::time::sleep(idle_timeout);
             tokio::pin!(sleep);
+            // open WebSockets keep the isolate alive, their messages need the event loop
+            let has_sockets = sockets::has_connected(&mut self.js_runtime.op_state().borrow_mut());
+            let is_busy = has_sockets || !self.pending.is_empty();
 
             tokio::select! {
-                Some((request, oneshot_tx)) = rx.recv() => {
-                    let js_response = match self.run(request).await {
-                        Ok(js_response) => js_response,
-                        Err(e) => {
-                            println!("Error from runtime {:?}", e);
-                            let mut response = Response::new(Body::empty());
-                            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-                            oneshot_tx.send(response).unwrap();
-                            continue;
+                payload = rx.recv() => {
+                    match payload {
+                        Some(RuntimeChannelPayload::Request(mut request, oneshot_tx)) => {
+                            let is_upgrade = websocket::is_upgrade(&request);
+                            let chain = request.extensions_mut().remove::<CallChain>().unwrap_or_default();
+                            self.js_runtime.op_state().borrow_mut().put(chain);
+                            match self.run(request).await {
+                                Ok((js_response, pending)) => {
+                                    let response = self.respond(js_response, is_upgrade).unwrap_or_else(|e| {
+                                        println!("Invalid response from runtime {:?}", e);
+                                        internal_error_response()
+                                    });
+                                    // the caller may have given up waiting already
+                                    oneshot_tx.send(response).unwrap_or(());
+                                    self.pending.push(pending);
+                                }
+                                Err(e) => {
+                                    println!("Error from runtime {:?}", e);
+                                    oneshot_tx.send(internal_error_response()).unwrap_or(());
+                                }
+                            }
+                        }
+                        Some(RuntimeChannelPayload::Queue(batch, oneshot_tx)) => {
+                            let result = self.run_queue(batch).await;
+                            oneshot_tx.send(result).unwrap_or(());
+                        }
+                        // the app is shutting down or the actor's deployment was replaced
+                        Some(RuntimeChannelPayload::Stop) | None => {
+                            self.wait_until().await;
+                            self.terminate();
+                            break;
                         }
-                    };
-
-                    let body = String::from_utf8(js_response.body.to_vec()).unwrap_or_else(|_| "".into());
-                    let mut response = Response::new(Body::try_from(body).unwrap());
-
-                    *response.status_mut() = StatusCode::from_u16(js_response.status)
-                        .expect("Status Code used that doesn't exist");
-
-                    let headers = response.headers_mut();
-                    for (key, value) in js_response.headers {
-                        headers.insert(
-                            HeaderName::from_str(key.as_str()).unwrap(),
-                            HeaderValue::from_str(value.as_str()).unwrap(),
-                        );
                     }
-
-                    oneshot_tx.send(response).unwrap();
                 }
-                _ = &mut sleep => {
-                    println!("5 seconds passed without a request, so we're killing this runtime.");
+                result = self.poll_background(), if is_busy => {
+                    if let Err(e) = result {
+                        println!("Error from runtime {:?}", e);
+                    }
+                }
+                _ = &mut sleep, if !has_sockets => {
+                    println!("{:?} passed without a request, so we're killing this runtime.", idle_timeout);
+                    self.wait_until().await;
                     self.terminate();
                     break;
                 }
@@ -170,19 +378,75 @@ impl Runtime {
     }
 }
 
+fn internal_error_response() -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    response
+}
+
+fn into_response(js_response: JsResponse) -> Result<Response<Body>> {
+    let mut response = Response::new(Body::from(js_response.body.to_vec()));
+
+    *response.status_mut() = StatusCode::from_u16(js_response.status)?;
+
+    // appending instead of inserting keeps repeated headers like `Set-Cookie` intact
+    let headers = response.headers_mut();
+    for (key, value) in js_response.headers {
+        let name = HeaderName::from_bytes(&key)
+            .with_context(|| format!("Invalid header name {:?}", String::from_utf8_lossy(&key)))?;
+        let value = HeaderValue::from_bytes(&value)
+            .with_context(|| format!("Invalid value for header {}", name))?;
+
+        headers.append(name, value);
+    }
+
+    Ok(response)
+}
+
+/**
+ * Forgets the `waitUntil` promises that settled
+ */
+fn retain_pending(js_runtime: &mut JsRuntime, pending: &mut Vec<v8::Global<v8::Promise>>) {
+    let scope = &mut js_runtime.handle_scope();
+    pending.retain(|promise| v8::Local::new(scope, promise).state() == v8::PromiseState::Pending);
+}
+
+/**
+ * Reads and removes a global that the JS side uses to hand results back
+ */
+fn take_global<T: DeserializeOwned>(js_runtime: &mut JsRuntime, name: &str) -> Result<Option<T>> {
+    let context = js_runtime.global_context();
+    let scope = &mut js_runtime.handle_scope();
+    let global = context.open(scope).global(scope);
+    let key = v8::String::new(scope, name).unwrap();
+    let value = global.get(scope, key.into()).unwrap();
+    if value.is_undefined() {
+        return Ok(None);
+    }
+    global.delete(scope, key.into()).unwrap();
+
+    Ok(Some(deno_core::serde_v8::from_v8(scope, value)?))
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct JsResponse {
-    pub headers: HashMap<String, String>,
+    pub headers: Vec<(ByteString, ByteString)>,
     pub ok: bool,
     pub redirected: bool,
     pub status: u16,
     #[serde(rename = "statusText")]
     pub status_text: String,
     pub body: deno_core::serde_v8::Buffer,
+    /// The resource of a `WebSocketPair` whose client end was returned
+    #[serde(rename = "webSocket")]
+    pub web_socket: Option<deno_core::ResourceId>,
 }
 
 fn get_error_class_name(e: &AnyError) -> &'static str {
-    deno_runtime::errors::get_error_class_name(e).unwrap_or("Error")
+    kv::get_error_class_name(e)
+        .or_else(|| db::get_error_class_name(e))
+        .or_else(|| deno_runtime::errors::get_error_class_name(e))
+        .unwrap_or("Error")
 }
 
 fn get_options() -> WorkerOptions {
@@ -227,7 +491,13 @@ fn get_options() -> WorkerOptions {
     }
 }
 
-fn init(session: Session, script_path: &Path, permissions: Permissions) -> deno_core::JsRuntime {
+async fn init(
+    session: Session,
+    actor_context: ActorContext,
+    blob_store: blobs::BlobStore,
+    script_path: &Path,
+    permissions: Permissions,
+) -> deno_core::JsRuntime {
     let mut options = get_options();
     let unstable = options.bootstrap.unstable;
     let enable_testing_features = options.bootstrap.enable_testing_features;
@@ -242,7 +512,12 @@ fn init(session: Session, script_path: &Path, permissions: Permissions) -> deno_
 
     // Internal modules
     let mut extensions: Vec<Extension> = vec![
-        kv::init(Some(session)),
+        kv::init(Some(session.clone())),
+        queue::init(Some(session.clone())),
+        actor::init(Some(actor_context.clone())),
+        blobs::init(Some(blob_store)),
+        cache::init(Some(session.clone())),
+        db::init(Some(session.clone())),
         // Web APIs
         deno_webidl::init(),
         deno_console::init(),
@@ -288,13 +563,14 @@ fn init(session: Session, script_path: &Path, permissions: Permissions) -> deno_
         ops::tty::init(),
         ops::http::init(),
         utils::init(),
+        sockets::init(),
         // Permissions ext (worker specific state)
         perm_ext,
     ];
     extensions.extend(std::mem::take(&mut options.extensions));
 
     let mut js_runtime = JsRuntime::new(RuntimeOptions {
-        module_loader: None,
+        module_loader: Some(options.module_loader.clone()),
         // startup_snapshot: None,
         startup_snapshot: Some(snapshot::workers_isolate_init()),
         js_error_create_fn: options.js_error_create_fn.clone(),
@@ -321,10 +597,88 @@ fn init(session: Session, script_path: &Path, permissions: Permissions) -> deno_
         .execute_script("set_cwd_script", set_cwd_script.as_str())
         .unwrap();
 
-    let js_code = std::fs::read_to_string(script_path).unwrap();
+    let kv_bindings = kv::load_bindings(&session).await.unwrap();
+    let mut set_bindings_script = String::from(
+        "window._hbw.bind(\"queue\", globalThis.queue);\nwindow._hbw.bind(\"blobs\", globalThis.blobs);\nwindow._hbw.bind(\"db\", globalThis.db);\n",
+    );
+    for binding in kv_bindings.names() {
+        let binding = deno_core::serde_json::to_string(&binding).unwrap();
+        set_bindings_script.push_str(&format!(
+            "window._hbw.bind({}, window._hbw.kv.namespace({}));\n",
+            binding, binding
+        ));
+    }
+
+    let actor_bindings = actor::load_bindings(&session).await.unwrap();
+    for binding in actor_bindings.names() {
+        let binding = deno_core::serde_json::to_string(&binding).unwrap();
+        set_bindings_script.push_str(&format!(
+            "window._hbw.bind({}, window._hbw.actor.namespace({}));\n",
+            binding, binding
+        ));
+    }
+
+    if let Some(address) = &actor_context.address {
+        set_bindings_script.push_str(&format!(
+            "window._hbw.actor.address = {};\n",
+            deno_core::serde_json::to_string(address).unwrap()
+        ));
+    }
+
     js_runtime
-        .execute_script(script_path.to_str().unwrap(), &js_code)
+        .execute_script("set_bindings_script", set_bindings_script.as_str())
         .unwrap();
+    js_runtime.op_state().borrow_mut().put(kv_bindings);
+    js_runtime.op_state().borrow_mut().put(actor_bindings);
+
+    let js_code = std::fs::read_to_string(script_path).unwrap();
+    if is_classic_script(&mut js_runtime, &js_code) {
+        js_runtime
+            .execute_script(script_path.to_str().unwrap(), &js_code)
+            .unwrap();
+    } else {
+        load_module(&mut js_runtime, script_path).await.unwrap();
+    }
 
     js_runtime
 }
+
+/**
+ * Scripts that don't compile as a classic script (because they use `import` or `export`)
+ * are loaded as an ES module instead
+ */
+fn is_classic_script(js_runtime: &mut JsRuntime, js_code: &str) -> bool {
+    let scope = &mut js_runtime.handle_scope();
+    let tc_scope = &mut v8::TryCatch::new(scope);
+    let source = v8::String::new(tc_scope, js_code).unwrap();
+
+    v8::Script::compile(tc_scope, source, None).is_some()
+}
+
+/**
+ * Evaluates the script as an ES module and hands its exports to `callOnRequest`
+ */
+async fn load_module(js_runtime: &mut JsRuntime, script_path: &Path) -> Result<()> {
+    let specifier = ModuleSpecifier::from_file_path(script_path)
+        .map_err(|_| anyhow!("Script path {:?} is not absolute", script_path))?;
+
+    let module_id = js_runtime.load_main_module(&specifier, None).await?;
+    let receiver = js_runtime.mod_evaluate(module_id);
+    js_runtime.run_event_loop(false).await?;
+    receiver.await??;
+
+    let namespace = js_runtime.get_module_namespace(module_id)?;
+    let scope = &mut js_runtime.handle_scope();
+    let namespace = v8::Local::new(scope, namespace);
+    let context = scope.get_current_context();
+    let global = context.global(scope);
+
+    let hbw_key = v8::String::new(scope, "_hbw").unwrap();
+    let hbw = global.get(scope, hbw_key.into()).unwrap();
+    let hbw = v8::Local::<v8::Object>::try_from(hbw)?;
+
+    let module_key = v8::String::new(scope, "module").unwrap();
+    hbw.set(scope, module_key.into(), namespace.into());
+
+    Ok(())
+}
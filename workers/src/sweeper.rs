@@ -0,0 +1,48 @@
+use anyhow::Result;
+use migration::sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, Statement};
+use std::time::Duration;
+
+/// Maximum amount of expired rows deleted per statement, keeps the locks short
+const BATCH_SIZE: u64 = 1000;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically deletes expired KV items, reads already skip them so this only reclaims space
+pub fn spawn(database_url: String) {
+    tokio::spawn(async move {
+        let conn = Database::connect(database_url.as_str())
+            .await
+            .expect("Database connection failed");
+
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = sweep(&conn).await {
+                println!("Failed to delete expired kv items: {:?}", e);
+            }
+        }
+    });
+}
+
+async fn sweep(conn: &DatabaseConnection) -> Result<()> {
+    loop {
+        let statement = Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"
+            DELETE FROM store
+            WHERE id IN (
+                SELECT id FROM store
+                WHERE expires_at <= now()
+                LIMIT $1
+            )
+            "#,
+            vec![i64::try_from(BATCH_SIZE)?.into()],
+        );
+
+        let result = conn.execute(statement).await?;
+        if result.rows_affected() < BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::{anyhow, bail, Context, Result};
+use axum::extract::{ConnectInfo, Extension};
+use axum::Router;
+use config::TlsConfig;
+use hyper::server::conn::Http;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::{self, CertifiedKey};
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// Certificate files are checked for changes this often, renewed certificates are used
+/// without a restart
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+/// Connections that don't finish the handshake within this long are closed
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Added to the extensions of requests that came in over TLS
+#[derive(Debug, Clone, Copy)]
+pub struct Tls;
+
+/// Where the certificates are read from
+#[derive(Clone)]
+struct Sources {
+    /// Certificate chain and key for clients without a matching domain
+    default: Option<(PathBuf, PathBuf)>,
+    /// A directory per domain with a `fullchain.pem` and a `privkey.pem`, laid out like the
+    /// `live` directory of certbot. Wildcard certificates go into directories like `*.example.com`
+    dir: Option<PathBuf>,
+}
+
+impl Sources {
+    fn new(config: &TlsConfig) -> Self {
+        Self {
+            default: config.default.clone(),
+            dir: config.certs_dir.clone(),
+        }
+    }
+
+    /// The certificate files with their modification times, changes whenever a certificate
+    /// is added or renewed
+    fn files(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let mut paths = vec![];
+        if let Some((cert, key)) = &self.default {
+            paths.push(cert.clone());
+            paths.push(key.clone());
+        }
+        if let Some(dir) = &self.dir {
+            for (_, cert, key) in domains(dir).unwrap_or_default() {
+                paths.push(cert);
+                paths.push(key);
+            }
+        }
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let modified = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                (path, modified)
+            })
+            .collect()
+    }
+
+    fn load(&self) -> Result<Certificates> {
+        let default = match &self.default {
+            Some((cert, key)) => Some(load_key(cert, key)?),
+            None => None,
+        };
+
+        // one broken domain must not keep the others from loading or renewing
+        let mut by_domain = HashMap::new();
+        if let Some(dir) = &self.dir {
+            for (domain, cert, key) in domains(dir)? {
+                match load_key(&cert, &key) {
+                    Ok(key) => {
+                        by_domain.insert(domain, key);
+                    }
+                    Err(e) => println!("Skipping the certificate of {}: {:?}", domain, e),
+                }
+            }
+        }
+
+        Ok(Certificates { default, by_domain })
+    }
+}
+
+/// The subdirectories of the certificates directory that contain a certificate
+fn domains(dir: &Path) -> Result<Vec<(String, PathBuf, PathBuf)>> {
+    let mut domains = vec![];
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                println!("Failed to read an entry of {}: {:?}", dir.display(), e);
+                continue;
+            }
+        };
+        let domain = match path.file_name().and_then(|name| name.to_str()) {
+            Some(domain) => domain.to_ascii_lowercase(),
+            None => continue,
+        };
+
+        let cert = path.join("fullchain.pem");
+        let key = path.join("privkey.pem");
+        if cert.is_file() && key.is_file() {
+            domains.push((domain, cert, key));
+        }
+    }
+
+    Ok(domains)
+}
+
+fn load_key(cert: &Path, key: &Path) -> Result<Arc<CertifiedKey>> {
+    let certs = rustls_pemfile::certs(&mut open(cert)?)
+        .with_context(|| format!("Invalid certificate {}", cert.display()))?;
+    if certs.is_empty() {
+        bail!("No certificate found in {}", cert.display());
+    }
+
+    let key_der = rustls_pemfile::read_all(&mut open(key)?)
+        .with_context(|| format!("Invalid private key {}", key.display()))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("No private key found in {}", key.display()))?;
+    let signing_key = sign::any_supported_type(&PrivateKey(key_der))
+        .map_err(|_| anyhow!("Unsupported private key in {}", key.display()))?;
+
+    Ok(Arc::new(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        signing_key,
+    )))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
+
+/// The loaded certificates, replaced as a whole when they're reloaded
+struct Certificates {
+    default: Option<Arc<CertifiedKey>>,
+    by_domain: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl Certificates {
+    /// A certificate for the domain itself wins over a wildcard certificate
+    fn find(&self, server_name: &str) -> Option<Arc<CertifiedKey>> {
+        let server_name = server_name.to_ascii_lowercase();
+        let wildcard = server_name
+            .split_once('.')
+            .map(|(_, parent)| format!("*.{}", parent));
+
+        self.by_domain
+            .get(&server_name)
+            .or_else(|| wildcard.and_then(|wildcard| self.by_domain.get(&wildcard)))
+            .cloned()
+    }
+}
+
+/// Picks the certificate by the server name the client sent (SNI)
+struct Resolver(RwLock<Arc<Certificates>>);
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let certificates = self.0.read().unwrap().clone();
+
+        client_hello
+            .server_name()
+            .and_then(|server_name| certificates.find(server_name))
+            .or_else(|| certificates.default.clone())
+    }
+}
+
+/// Terminates TLS in front of the workers, HTTP/2 is negotiated with ALPN
+pub struct TlsServer {
+    sources: Sources,
+    resolver: Arc<Resolver>,
+    addr: SocketAddr,
+}
+
+impl TlsServer {
+    /// # Errors
+    ///
+    /// Will return `Err` if the certificates can't be loaded
+    pub fn new(config: &TlsConfig) -> Result<Self> {
+        let sources = Sources::new(config);
+        let certificates = sources
+            .load()
+            .context("Failed to load the TLS certificates")?;
+
+        Ok(Self {
+            sources,
+            resolver: Arc::new(Resolver(RwLock::new(Arc::new(certificates)))),
+            addr: SocketAddr::from(([0, 0, 0, 0], config.port)),
+        })
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the port can't be bound
+    pub async fn serve(self, router: Router) -> Result<()> {
+        self.spawn_reloader();
+
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(self.resolver.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind(self.addr).await?;
+        println!("Workers listening with TLS on {}", self.addr);
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    println!("Failed to accept a TLS connection: {:?}", e);
+                    continue;
+                }
+            };
+
+            let acceptor = acceptor.clone();
+            let service = router
+                .clone()
+                .layer(Extension(ConnectInfo(peer)))
+                .layer(Extension(Tls));
+
+            tokio::spawn(async move {
+                let stream =
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => {
+                            println!("TLS handshake with {} failed: {:?}", peer, e);
+                            return;
+                        }
+                        Err(_) => {
+                            println!("TLS handshake with {} timed out", peer);
+                            return;
+                        }
+                    };
+
+                let is_http2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+                let connection = Http::new()
+                    .http2_only(is_http2)
+                    .serve_connection(stream, service)
+                    .with_upgrades();
+                if let Err(e) = connection.await {
+                    println!("TLS connection to {} failed: {:?}", peer, e);
+                }
+            });
+        }
+    }
+
+    fn spawn_reloader(&self) {
+        let sources = self.sources.clone();
+        let resolver = self.resolver.clone();
+
+        tokio::spawn(async move {
+            let mut files = sources.files();
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let current = sources.files();
+                if current == files {
+                    continue;
+                }
+
+                // files that are still being written fail to load, they're retried next time
+                match sources.load() {
+                    Ok(certificates) => {
+                        *resolver.0.write().unwrap() = Arc::new(certificates);
+                        files = current;
+                        println!("Reloaded the TLS certificates");
+                    }
+                    Err(e) => println!("Failed to reload the TLS certificates: {:?}", e),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,137 @@
+use axum::body::Body;
+use axum::http::header::{
+    CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, Response};
+use deno_core::futures::{SinkExt, StreamExt};
+use hyper::upgrade::OnUpgrade;
+use sockets::{Connection, Frame};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// Whether the request asks for a WebSocket, only those may be answered with a `WebSocketPair`
+pub fn is_upgrade(request: &Request<Body>) -> bool {
+    let headers = request.headers();
+
+    request.method() == Method::GET
+        && has_token(headers, &CONNECTION, "upgrade")
+        && has_token(headers, &UPGRADE, "websocket")
+        && headers
+            .get(SEC_WEBSOCKET_VERSION)
+            .map_or(false, |version| version == "13")
+        && headers.contains_key(SEC_WEBSOCKET_KEY)
+}
+
+fn has_token(headers: &HeaderMap, name: &axum::http::header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// A WebSocket upgrade, taken from the request before the script decides whether to accept it
+pub struct Upgrade {
+    key: HeaderValue,
+    on_upgrade: OnUpgrade,
+}
+
+impl Upgrade {
+    /// `None` for requests that aren't WebSocket upgrades
+    pub fn new(request: &mut Request<Body>) -> Option<Self> {
+        if !is_upgrade(request) {
+            return None;
+        }
+
+        Some(Self {
+            key: request.headers().get(SEC_WEBSOCKET_KEY)?.clone(),
+            on_upgrade: hyper::upgrade::on(request),
+        })
+    }
+
+    /// Completes the handshake if the script returned a `WebSocketPair`, any other response
+    /// is sent as it is
+    pub fn accept(self, mut response: Response<Body>) -> Response<Body> {
+        let connection = match response.extensions_mut().remove::<Connection>() {
+            Some(connection) => connection,
+            None => return response,
+        };
+
+        let headers = response.headers_mut();
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(
+            SEC_WEBSOCKET_ACCEPT,
+            HeaderValue::from_str(&derive_accept_key(self.key.as_bytes())).unwrap(),
+        );
+
+        tokio::spawn(pump(self.on_upgrade, connection));
+        response
+    }
+}
+
+/// Passes messages between the client and the script until both sides are done
+async fn pump(on_upgrade: OnUpgrade, connection: Connection) {
+    let upgraded = match on_upgrade.await {
+        Ok(upgraded) => upgraded,
+        Err(e) => {
+            println!("WebSocket upgrade failed: {:?}", e);
+            return;
+        }
+    };
+
+    let stream = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+    let (mut sink, mut stream) = stream.split();
+    let Connection {
+        incoming,
+        mut outgoing,
+    } = connection;
+
+    let to_client = async move {
+        while let Some(frame) = outgoing.recv().await {
+            let message = match frame {
+                Frame::Text(text) => Message::Text(text),
+                Frame::Binary(binary) => Message::Binary(binary),
+                Frame::Close(code, reason) => Message::Close(Some(CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: reason.into(),
+                })),
+            };
+            let is_close = matches!(message, Message::Close(_));
+
+            if sink.send(message).await.is_err() || is_close {
+                break;
+            }
+        }
+
+        // the socket was dropped by the script, the client is told the connection ended
+        sink.close().await.unwrap_or(());
+    };
+
+    // the script sees the connection end once `incoming` is dropped
+    let to_script = async move {
+        while let Some(Ok(message)) = stream.next().await {
+            let frame = match message {
+                Message::Text(text) => Frame::Text(text),
+                Message::Binary(binary) => Frame::Binary(binary),
+                Message::Close(close) => close.map_or_else(
+                    || Frame::Close(CloseCode::Status.into(), String::new()),
+                    |close| Frame::Close(close.code.into(), close.reason.into_owned()),
+                ),
+                // pings are answered by tungstenite itself
+                _ => continue,
+            };
+            let is_close = matches!(frame, Frame::Close(..));
+
+            if incoming.send(frame).await.is_err() || is_close {
+                break;
+            }
+        }
+    };
+
+    tokio::join!(to_client, to_script);
+}